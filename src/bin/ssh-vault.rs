@@ -9,6 +9,9 @@ fn main() -> Result<()> {
 
     // Handle the action
     match action {
+        Action::Cache { .. } => {
+            actions::cache::handle(action)?;
+        }
         Action::Fingerprint { .. } => {
             actions::fingerprint::handle(action)?;
         }
@@ -21,6 +24,18 @@ fn main() -> Result<()> {
         Action::Edit { .. } => {
             actions::edit::handle(action)?;
         }
+        Action::Rekey { .. } => {
+            actions::rekey::handle(action)?;
+        }
+        Action::Serve { .. } => {
+            actions::serve::handle(action)?;
+        }
+        Action::Sign { .. } | Action::Verify { .. } => {
+            actions::sign::handle(action)?;
+        }
+        Action::Trust { .. } | Action::Untrust { .. } => {
+            actions::trust::handle(action)?;
+        }
         Action::Help => {
             eprintln!("No command or argument provided, try --help");
 
@@ -1,30 +1,45 @@
-use crate::tools::get_home;
-use anyhow::{Result, anyhow};
+use crate::{
+    config,
+    tools::{get_home, parse_duration},
+};
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
 use std::{
-    fs,
+    fs::{self, File},
     path::{Path, PathBuf},
     time::{Duration, SystemTime},
 };
 
+// default time to live of the cache entries, 30 days
+const DEFAULT_TTL: Duration = Duration::from_hours(30 * 24);
+
+/// Where a cache entry came from and the validators to revalidate it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Meta {
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+/// A cache entry as listed by `ssh-vault cache list`.
+#[derive(Debug)]
+pub struct Entry {
+    pub key: String,
+    pub meta: Meta,
+    pub age: Duration,
+    pub size: u64,
+    pub expired: bool,
+}
+
 // Load the response from a cache file ~/.ssh/vault/keys/`<key>`
 /// # Errors
-/// Return an error if the cache is older than 30 days
+/// Return an error if the cache is missing or older than the TTL
 pub fn get(key: &str) -> Result<String> {
     let cache = get_cache_path(key)?;
     if cache.exists() {
-        let metadata = fs::metadata(&cache);
-        let last_modified = metadata.map_or_else(
-            |_| SystemTime::now(),
-            |meta| meta.modified().unwrap_or_else(|_| SystemTime::now()),
-        );
-
-        // Calculate the duration since the file was last modified
-        let duration_since_modified = SystemTime::now()
-            .duration_since(last_modified)
-            .unwrap_or(Duration::from_secs(0));
-
-        // Return an error if the cache is older than 30 days
-        if duration_since_modified > Duration::from_hours(30 * 24) {
+        if is_expired(age(&cache), ttl()?) {
             Err(anyhow!("cache expired"))
         } else {
             Ok(fs::read_to_string(cache)?)
@@ -34,6 +49,14 @@ pub fn get(key: &str) -> Result<String> {
     }
 }
 
+/// Load the response from a cache file ignoring the TTL
+/// # Errors
+/// Return an error if the cache file can't be read
+pub fn read(key: &str) -> Result<String> {
+    let cache = get_cache_path(key)?;
+    fs::read_to_string(&cache).with_context(|| format!("cache {key} not found"))
+}
+
 /// Save the response to a cache file ~/.ssh/vault/keys/`<key>`
 /// # Errors
 /// Return an error if the cache file can't be created
@@ -46,14 +69,156 @@ pub fn put(key: &str, response: &str) -> Result<()> {
     Ok(fs::write(cache, response)?)
 }
 
-/// Get the path to the cache file ~/.ssh/vault/keys/`<key>`
+/// Save the response with its origin and validators in `<key>.meta`
+/// # Errors
+/// Return an error if the cache files can't be created
+pub fn store(key: &str, response: &str, meta: &Meta) -> Result<()> {
+    put(key, response)?;
+    Ok(fs::write(
+        get_meta_path(key)?,
+        serde_json::to_string(meta)?,
+    )?)
+}
+
+/// Load the origin and validators of a cache entry, if any
 /// # Errors
 /// Return an error if we can't get the path to the cache file
+pub fn meta(key: &str) -> Result<Option<Meta>> {
+    let meta = get_meta_path(key)?;
+    Ok(fs::read_to_string(meta)
+        .ok()
+        .and_then(|meta| serde_json::from_str(&meta).ok()))
+}
+
+/// Reset the age of a cache entry, after the server confirmed it didn't change
+/// # Errors
+/// Return an error if the cache file can't be updated
+pub fn touch(key: &str) -> Result<()> {
+    let cache = get_cache_path(key)?;
+    File::options()
+        .write(true)
+        .open(&cache)?
+        .set_modified(SystemTime::now())?;
+    Ok(())
+}
+
+/// Remove a cache entry, returns false if it doesn't exist
+/// # Errors
+/// Return an error if the cache files can't be removed
+pub fn remove(key: &str) -> Result<bool> {
+    let cache = get_cache_path(key)?;
+    if !cache.is_file() {
+        return Ok(false);
+    }
+    fs::remove_file(cache)?;
+    let meta = get_meta_path(key)?;
+    if meta.is_file() {
+        fs::remove_file(meta)?;
+    }
+    Ok(true)
+}
+
+/// List the cache entries, oldest first
+/// # Errors
+/// Return an error if the cache directory can't be read
+pub fn list() -> Result<Vec<Entry>> {
+    let dir = get_ssh_vault_path()?.join("keys");
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let ttl = ttl()?;
+    let mut entries = Vec::new();
+
+    for file in fs::read_dir(dir)? {
+        let path = file?.path();
+        let Some(key) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        // the metadata and files that aren't cache entries
+        if !is_key(key) || !path.is_file() {
+            continue;
+        }
+        let age = age(&path);
+        entries.push(Entry {
+            key: key.to_string(),
+            meta: meta(key)?.unwrap_or_default(),
+            age,
+            size: fs::metadata(&path)?.len(),
+            expired: is_expired(age, ttl),
+        });
+    }
+
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.age));
+
+    Ok(entries)
+}
+
+/// The time to live of the cache entries from `cache_ttl`, a number of
+/// seconds or a duration like `12h` or `30d`, `0` to always revalidate and
+/// `never` to never expire. `None` when the entries don't expire.
+/// # Errors
+/// Return an error if `cache_ttl` is invalid
+pub fn ttl() -> Result<Option<Duration>> {
+    let config = config::get()?;
+    let Ok(ttl) = config.get_string("cache_ttl") else {
+        return Ok(Some(DEFAULT_TTL));
+    };
+    parse_ttl(&ttl)
+}
+
+fn parse_ttl(ttl: &str) -> Result<Option<Duration>> {
+    let ttl = ttl.trim().to_lowercase();
+    if matches!(ttl.as_str(), "never" | "infinite" | "inf" | "-1") {
+        return Ok(None);
+    }
+    parse_duration(&ttl)
+        .map(Some)
+        .ok_or_else(|| anyhow!("Invalid cache_ttl {ttl}, use seconds, 30m, 12h, 30d or never"))
+}
+
+// the time since the cache file was last modified
+fn age(cache: &Path) -> Duration {
+    let last_modified = fs::metadata(cache).map_or_else(
+        |_| SystemTime::now(),
+        |meta| meta.modified().unwrap_or_else(|_| SystemTime::now()),
+    );
+
+    SystemTime::now()
+        .duration_since(last_modified)
+        .unwrap_or(Duration::from_secs(0))
+}
+
+fn is_expired(age: Duration, ttl: Option<Duration>) -> bool {
+    ttl.is_some_and(|ttl| age >= ttl)
+}
+
+/// Whether the key is a cache key, the MD5 of the URL in lowercase hex
+#[must_use]
+pub fn is_key(key: &str) -> bool {
+    key.len() == 32 && key.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Get the path to the cache file ~/.ssh/vault/keys/`<key>`
+/// # Errors
+/// Return an error if the key is not a cache key or we can't get the path to
+/// the cache file
 fn get_cache_path(key: &str) -> Result<PathBuf> {
+    if !is_key(key) {
+        return Err(anyhow!("Invalid cache key {key}"));
+    }
     let ssh_vault = get_ssh_vault_path()?;
     Ok(ssh_vault.join("keys").join(key))
 }
 
+/// Get the path to the metadata file ~/.ssh/vault/keys/`<key>`.meta
+/// # Errors
+/// Return an error if the key is not a cache key or we can't get the path to
+/// the metadata file
+fn get_meta_path(key: &str) -> Result<PathBuf> {
+    Ok(get_cache_path(key)?.with_extension("meta"))
+}
+
 /// Get the path to the ssh-vault directory ~/.ssh/vault
 /// # Errors
 /// Return an error if we can't get the path to the ssh-vault directory
@@ -63,13 +228,20 @@ fn get_ssh_vault_path() -> Result<PathBuf> {
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
     use std::fs;
 
+    // the cache key of a name
+    fn key(name: &str) -> String {
+        format!("{:x}", md5::compute(name))
+    }
+
     #[test]
     fn test_get_cache_path() -> Result<(), Box<dyn std::error::Error>> {
-        let cache = get_cache_path("test")?;
+        let key = key("test");
+        let cache = get_cache_path(&key)?;
         assert!(!cache.is_dir());
         assert_eq!(
             cache.to_str(),
@@ -77,7 +249,7 @@ mod tests {
                 .join(".ssh")
                 .join("vault")
                 .join("keys")
-                .join("test")
+                .join(&key)
                 .to_str()
         );
         Ok(())
@@ -96,8 +268,9 @@ mod tests {
 
     #[test]
     fn test_put() -> Result<(), Box<dyn std::error::Error>> {
-        let cache = get_cache_path("test-2")?;
-        put("test-2", "test")?;
+        let key = key("test-2");
+        let cache = get_cache_path(&key)?;
+        put(&key, "test")?;
 
         assert!(cache.is_file());
         assert!(!cache.is_dir());
@@ -108,7 +281,7 @@ mod tests {
                 .join(".ssh")
                 .join("vault")
                 .join("keys")
-                .join("test-2")
+                .join(&key)
                 .to_str()
         );
         fs::remove_file(cache)?;
@@ -117,11 +290,138 @@ mod tests {
 
     #[test]
     fn test_get() -> Result<(), Box<dyn std::error::Error>> {
-        let cache = get_cache_path("test-3")?;
-        put("test-3", "test")?;
-        let response = get("test-3")?;
+        let key = key("test-3");
+        let cache = get_cache_path(&key)?;
+        put(&key, "test")?;
+        let response = get(&key)?;
         assert_eq!(response, "test");
         fs::remove_file(cache)?;
         Ok(())
     }
+
+    #[test]
+    fn test_parse_ttl() {
+        assert_eq!(parse_ttl("0").unwrap(), Some(Duration::ZERO));
+        assert_eq!(parse_ttl("90").unwrap(), Some(Duration::from_secs(90)));
+        assert_eq!(parse_ttl("30m").unwrap(), Some(Duration::from_mins(30)));
+        assert_eq!(parse_ttl("12h").unwrap(), Some(Duration::from_hours(12)));
+        assert_eq!(parse_ttl("30d").unwrap(), Some(DEFAULT_TTL));
+        assert_eq!(parse_ttl("never").unwrap(), None);
+        assert_eq!(parse_ttl("Infinite").unwrap(), None);
+        assert!(parse_ttl("soon").is_err());
+        assert!(parse_ttl("3w").is_err());
+    }
+
+    #[test]
+    fn test_ttl() {
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_CACHE_TTL", None),
+            ],
+            || {
+                let fresh = key("fresh");
+                assert_eq!(ttl().unwrap(), Some(DEFAULT_TTL));
+                put(&fresh, "test").unwrap();
+                assert_eq!(get(&fresh).unwrap(), "test");
+            },
+        );
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_CACHE_TTL", Some("0".as_ref())),
+            ],
+            || {
+                let fresh = key("fresh");
+                assert!(get(&fresh).is_err());
+                assert_eq!(read(&fresh).unwrap(), "test");
+            },
+        );
+    }
+
+    #[test]
+    fn test_store_list_remove() {
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_CACHE_TTL", Some("1h".as_ref())),
+            ],
+            || {
+                let (alice, legacy) = (key("alice"), key("legacy"));
+                assert!(list().unwrap().is_empty());
+
+                let meta = Meta {
+                    url: Some("https://github.com/alice.keys".to_string()),
+                    etag: Some("\"abc\"".to_string()),
+                    last_modified: None,
+                };
+                store(&alice, "ssh-ed25519 AAAA", &meta).unwrap();
+                put(&legacy, "ssh-ed25519 BBBB").unwrap();
+
+                // an hour old
+                File::options()
+                    .write(true)
+                    .open(get_cache_path(&legacy).unwrap())
+                    .unwrap()
+                    .set_modified(SystemTime::now() - Duration::from_hours(1))
+                    .unwrap();
+
+                let entries = list().unwrap();
+                assert_eq!(entries.len(), 2);
+
+                let first = entries.first().unwrap();
+                assert_eq!(first.key, legacy);
+                assert_eq!(first.meta, Meta::default());
+                assert!(first.expired);
+
+                let last = entries.last().unwrap();
+                assert_eq!(last.meta, meta);
+                assert_eq!(last.size, 16);
+                assert!(!last.expired);
+
+                touch(&legacy).unwrap();
+                assert!(get(&legacy).is_ok());
+
+                assert!(remove(&alice).unwrap());
+                assert!(!remove(&alice).unwrap());
+                assert!(super::meta(&alice).unwrap().is_none());
+                assert_eq!(list().unwrap().len(), 1);
+            },
+        );
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let home = tempfile::TempDir::new().unwrap();
+        let ssh = home.path().join(".ssh");
+        fs::create_dir_all(ssh.join("vault").join("keys")).unwrap();
+        fs::write(ssh.join("id_ed25519"), "private").unwrap();
+        fs::write(ssh.join("vault").join("keys").join("notes"), "notes").unwrap();
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            assert!(is_key(&key("alice")));
+            assert!(!is_key(&key("alice").to_uppercase()));
+            assert!(!is_key("alice"));
+
+            for key in [
+                "../../id_ed25519",
+                ssh.join("id_ed25519").to_str().unwrap(),
+                "notes",
+                "",
+            ] {
+                assert!(read(key).is_err());
+                assert!(remove(key).is_err());
+                assert!(put(key, "test").is_err());
+            }
+            assert!(ssh.join("id_ed25519").is_file());
+
+            // only the cache entries are listed
+            assert!(list().unwrap().is_empty());
+        });
+    }
 }
@@ -0,0 +1,267 @@
+use crate::cache;
+use crate::cli::actions::Action;
+use crate::vault::remote;
+use anyhow::{Result, anyhow};
+use std::time::Duration;
+
+/// Handle the cache action
+///
+/// # Errors
+///
+/// Returns an error if the cache can't be read or updated, an entry is not
+/// found or it can't be fetched again.
+pub fn handle(action: Action) -> Result<()> {
+    match action {
+        Action::Cache { command, entries } => {
+            let keys = entries
+                .iter()
+                .map(|entry| resolve(entry))
+                .collect::<Result<Vec<_>>>()?;
+
+            match command.as_str() {
+                "list" => {
+                    for entry in cache::list()? {
+                        println!(
+                            "{}  {:>4}  {:<7}  {}",
+                            entry.key,
+                            age(entry.age),
+                            if entry.expired { "expired" } else { "fresh" },
+                            entry.meta.url.as_deref().unwrap_or("-")
+                        );
+                    }
+                }
+                "show" => {
+                    for key in &keys {
+                        show(key)?;
+                    }
+                }
+                "clear" => {
+                    let keys = if keys.is_empty() {
+                        cache::list()?.into_iter().map(|entry| entry.key).collect()
+                    } else {
+                        keys
+                    };
+
+                    for key in &keys {
+                        if !cache::remove(key)? {
+                            return Err(anyhow!("Cache entry {key} not found"));
+                        }
+                    }
+
+                    println!("Removed {} cache entries", keys.len());
+                }
+                "refresh" => {
+                    let entries = cache::list()?;
+                    let mut failed = 0;
+
+                    for entry in &entries {
+                        if !keys.is_empty() && !keys.contains(&entry.key) {
+                            continue;
+                        }
+
+                        let Some(url) = &entry.meta.url else {
+                            eprintln!("Skipping {}, its URL is unknown", entry.key);
+                            continue;
+                        };
+
+                        // a failed entry doesn't stop the others
+                        match remote::refresh(url) {
+                            Ok(_) => println!("Refreshed {url}"),
+                            Err(err) => {
+                                eprintln!("Failed to refresh {url}: {err:#}");
+                                failed += 1;
+                            }
+                        }
+                    }
+
+                    if let Some(key) = keys
+                        .iter()
+                        .find(|key| !entries.iter().any(|entry| &entry.key == *key))
+                    {
+                        return Err(anyhow!("Cache entry {key} not found"));
+                    }
+
+                    if failed > 0 {
+                        return Err(anyhow!("Failed to refresh {failed} cache entries"));
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+// an entry is given by its key as listed, URL or user
+fn resolve(entry: &str) -> Result<String> {
+    if cache::list()?.iter().any(|cached| cached.key == entry) {
+        return Ok(entry.to_string());
+    }
+
+    remote::cache_key(entry)
+}
+
+fn show(key: &str) -> Result<()> {
+    let entry = cache::list()?
+        .into_iter()
+        .find(|entry| entry.key == key)
+        .ok_or_else(|| anyhow!("Cache entry {key} not found"))?;
+
+    println!("key:           {}", entry.key);
+    println!(
+        "url:           {}",
+        entry.meta.url.as_deref().unwrap_or("-")
+    );
+    println!(
+        "age:           {}{}",
+        age(entry.age),
+        if entry.expired { " (expired)" } else { "" }
+    );
+
+    if let Some(etag) = &entry.meta.etag {
+        println!("etag:          {etag}");
+    }
+
+    if let Some(last_modified) = &entry.meta.last_modified {
+        println!("last-modified: {last_modified}");
+    }
+
+    println!();
+    print!("{}", cache::read(key)?);
+
+    Ok(())
+}
+
+// the age in the largest unit, 45s, 12m, 3h or 5d
+fn age(age: Duration) -> String {
+    let seconds = age.as_secs();
+
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "http")]
+    use crate::vault::http::stand_in;
+
+    #[cfg(feature = "http")]
+    fn cache(command: &str, entries: &[&str]) -> Result<()> {
+        handle(Action::Cache {
+            command: command.to_string(),
+            entries: entries.iter().map(ToString::to_string).collect(),
+        })
+    }
+
+    #[test]
+    fn test_age() {
+        assert_eq!(age(Duration::from_secs(45)), "45s");
+        assert_eq!(age(Duration::from_mins(12)), "12m");
+        assert_eq!(age(Duration::from_hours(3)), "3h");
+        assert_eq!(age(Duration::from_hours(24 * 5)), "5d");
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn test_handle_cache() {
+        let server = stand_in::start(&[("/alice.keys", "ssh-ed25519 AAAA")]);
+        let home = tempfile::TempDir::new().unwrap();
+        let url = format!("{}/alice.keys", server.url);
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_CACHE_TTL", None),
+            ],
+            || {
+                let legacy = format!("{:x}", md5::compute("legacy"));
+                remote::get_keys(&url).unwrap();
+                cache::put(&legacy, "ssh-ed25519 BBBB").unwrap();
+
+                cache("list", &[]).unwrap();
+                cache("show", &[&url]).unwrap();
+                cache("show", &[&legacy]).unwrap();
+                assert!(cache("show", &["unknown"]).is_err());
+
+                cache("refresh", &[]).unwrap();
+                assert_eq!(server.requests().len(), 2);
+                cache("refresh", &[&url]).unwrap();
+                assert_eq!(server.requests().len(), 3);
+                assert!(cache("refresh", &["gitlab:alice"]).is_err());
+
+                cache("clear", &[&legacy]).unwrap();
+                assert_eq!(cache::list().unwrap().len(), 1);
+                assert!(cache("clear", &[&legacy]).is_err());
+
+                // paths are never taken as keys
+                let private_key = home.path().join(".ssh").join("id_ed25519");
+                std::fs::write(&private_key, "private").unwrap();
+                for entry in ["../../id_ed25519", private_key.to_str().unwrap()] {
+                    assert!(cache("clear", &[entry]).is_err());
+                    assert!(cache("show", &[entry]).is_err());
+                }
+                assert!(private_key.is_file());
+
+                cache("clear", &[]).unwrap();
+                assert!(cache::list().unwrap().is_empty());
+            },
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn test_handle_cache_refresh_failed() {
+        let server = stand_in::start(&[("/alice.keys", "ssh-ed25519 AAAA")]);
+        let home = tempfile::TempDir::new().unwrap();
+        let url = format!("{}/alice.keys", server.url);
+        let gone = format!("{}/gone.keys", server.url);
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_CACHE_TTL", None),
+            ],
+            || {
+                remote::get_keys(&url).unwrap();
+                cache::store(
+                    &remote::cache_key(&gone).unwrap(),
+                    "ssh-ed25519 BBBB",
+                    &cache::Meta {
+                        url: Some(gone.clone()),
+                        ..cache::Meta::default()
+                    },
+                )
+                .unwrap();
+
+                // the failed entry is reported, the others are still refreshed
+                let err = cache("refresh", &[]).unwrap_err();
+                assert!(
+                    err.to_string()
+                        .contains("Failed to refresh 1 cache entries")
+                );
+
+                let requests = server.requests();
+                assert_eq!(requests.len(), 3);
+                assert!(
+                    requests
+                        .iter()
+                        .any(|request| request.contains("/gone.keys"))
+                );
+                assert_eq!(
+                    requests
+                        .iter()
+                        .filter(|request| request.contains("/alice.keys"))
+                        .count(),
+                    2
+                );
+            },
+        );
+    }
+}
@@ -1,16 +1,41 @@
-use crate::cli::actions::{Action, process_input};
-use crate::vault::{SshVault, crypto, dio, find, online, remote};
+use crate::cli::actions::{Action, persist, process_input, signing_key, vault_tempfile};
+use crate::config;
+use crate::vault::{
+    Metadata, SshKeyType, SshVault, VaultWriter, crypto, dio, find, metadata, multi, online,
+    remote, signature, trust,
+};
 use anyhow::{Result, anyhow};
 use secrecy::SecretSlice;
 use serde::{Deserialize, Serialize};
-use ssh_key::PublicKey;
-use std::io::{Read, Write};
+use ssh_key::{LineEnding, PrivateKey, PublicKey};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+use zeroize::Zeroize;
+
+// where the key of -u new comes from
+#[derive(Debug, PartialEq, Eq)]
+enum NewKey {
+    // ssh-keys.online or the sshkeys_online endpoint
+    Online,
+    // generated locally, the private key is in the JSON output
+    Inline,
+    // generated locally, the private key is written to the file
+    File(String),
+    // generated locally, the private key is uploaded to new_key_upload
+    Upload,
+}
+
+// inputs larger than this are encrypted in chunks without loading them in memory
+const STREAM_THRESHOLD: u64 = 1024 * 1024;
 
 #[derive(Serialize, Deserialize)]
 pub struct JsonVault {
-    vault: String,
+    pub vault: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    private_key: Option<String>,
+    pub private_key: Option<String>,
 }
 
 /// Handle the create action
@@ -22,51 +47,81 @@ pub struct JsonVault {
 pub fn handle(action: Action) -> Result<()> {
     match action {
         Action::Create {
+            agent,
+            content_type,
             fingerprint,
-            key,
-            user,
+            host,
+            keys,
+            known_hosts,
+            label,
+            new_key,
+            new_key_file,
+            passphrase,
+            sign_with,
+            users,
             vault,
             json,
             input,
         } => {
-            // print the url from where to download the key
-            let mut helper: Option<String> = None;
+            let new_key = new_key_mode(&users, new_key, new_key_file)?;
 
-            let ssh_key: PublicKey = if let Some(user) = user {
-                // if user equals "new" ignore the key and fingerprint
-                if user == "new" && (key.is_some() || fingerprint.is_some()) {
-                    return Err(anyhow!("Options -k and -f not required when using -u new"));
-                }
+            // loaded first, it may prompt for the passphrase
+            let signing_key = sign_with
+                .map(|key| signing_key(&key, passphrase))
+                .transpose()?;
 
-                let int_key: Option<u32> = key.as_ref().and_then(|s| s.parse::<u32>().ok());
+            // the generated private key is inline in the JSON
+            let json = json || new_key == Some(NewKey::Inline);
 
-                // get keys from GitHub or remote server
-                let keys = remote::get_keys(&user)?;
+            // when using -u, -k N is the index of the user key
+            let (indexes, paths): (Vec<String>, Vec<String>) = if users.is_empty() {
+                (Vec::new(), keys)
+            } else {
+                keys.into_iter().partition(|k| k.parse::<u32>().is_ok())
+            };
 
-                // search key using -k or -f options
-                let ssh_key = remote::get_user_key(&keys, int_key, &fingerprint)?;
+            check_users(
+                &users,
+                &indexes,
+                &paths,
+                fingerprint.as_deref(),
+                new_key.as_ref(),
+            )?;
 
-                // if user equals "new" then we need to create a new key
-                if let Ok(key) = online::get_private_key_id(&ssh_key, &user)
-                    && !key.is_empty()
-                {
-                    helper = Some(key);
-                }
+            let int_key: Option<u32> = indexes.first().and_then(|s| s.parse::<u32>().ok());
 
-                ssh_key
-            } else {
-                find::public_key(key)?
-            };
+            // the keys of the users, the helper to open the vault with -u new
+            let (mut ssh_keys, helper) =
+                user_keys(&users, new_key.as_ref(), int_key, fingerprint.as_ref())?;
 
-            let key_type = find::key_type(&ssh_key.algorithm())?;
+            // the host keys of the servers allowed to open the vault
+            for known_host in &known_hosts {
+                ssh_keys.push(find::host_public_key(known_host)?);
+            }
+
+            let mut vaults = ssh_keys
+                .into_iter()
+                .map(public_vault)
+                .collect::<Result<Vec<_>>>()?;
 
-            let v = SshVault::new(&key_type, Some(ssh_key), None)?;
+            // the default key is only used when there are no other recipients
+            // or when the identity of a host is requested
+            let default = vaults.is_empty() || host.is_some();
+            vaults.extend(local_vaults(paths, agent, default, host.as_deref())?);
 
             let mut buffer = Vec::new();
 
             // check if we need to skip the editor filename == "-"
             let skip_editor = input.as_ref().is_some_and(|stdin| stdin == "-");
 
+            // the metadata requires the SSH-VAULT-V2 format
+            let v2 = vaults.len() > 1 || label.is_some() || content_type.is_some();
+
+            let metadata = new_metadata(input.as_deref(), content_type, label)?;
+
+            // the vault file, streams are written next to it and renamed on success
+            let path = vault.clone().filter(|vault| vault != "-");
+
             // setup Reader(input) and Writer (output)
             let (mut input, output) = dio::setup_io(input, vault)?;
 
@@ -74,23 +129,53 @@ pub fn handle(action: Action) -> Result<()> {
                 return Err(anyhow!("Vault file already exists"));
             }
 
-            if input.is_terminal() {
-                if skip_editor {
-                    input.read_to_end(&mut buffer)?;
-                } else {
-                    // use editor to handle input
-                    process_input(&mut buffer, None)?;
-                }
+            let editor = input.is_terminal() && !skip_editor;
+
+            if editor {
+                // use editor to handle input
+                process_input(&mut buffer, None)?;
             } else {
-                // read from stdin
+                // read from stdin or file, only up to the stream threshold
+                (&mut input)
+                    .take(STREAM_THRESHOLD + 1)
+                    .read_to_end(&mut buffer)?;
+            }
+
+            // large inputs are streamed, the JSON and helper formats and the
+            // signature need the whole vault in memory
+            if u64::try_from(buffer.len())? > STREAM_THRESHOLD
+                && !json
+                && helper.is_none()
+                && signing_key.is_none()
+            {
+                return stream(
+                    output,
+                    path.as_deref().map(Path::new),
+                    &vaults,
+                    metadata,
+                    &mut buffer,
+                    (!editor).then_some(&mut input),
+                );
+            }
+
+            if !editor {
                 input.read_to_end(&mut buffer)?;
             }
 
             // generate password (32 rand chars)
             let password: SecretSlice<u8> = crypto::gen_password()?;
 
-            // create vault
-            let vault = v.create(password, &mut buffer)?;
+            // create vault, one recipient without metadata keeps the single key format
+            let vault = match vaults.as_slice() {
+                [v] if !v2 => v.create(password, &mut buffer)?,
+                _ => multi::create(&vaults, metadata, &password, &mut buffer)?,
+            };
+
+            // the signature is appended to the vault
+            let vault = match &signing_key {
+                Some(key) => signature::sign(&vault, key)?,
+                None => vault,
+            };
 
             // return JSON or plain text, the helper is used to decrypt the vault
             format(output, vault, json, helper)?;
@@ -100,6 +185,248 @@ pub fn handle(action: Action) -> Result<()> {
     Ok(())
 }
 
+// encrypt the buffer followed by the rest of the input in chunks, a vault file
+// is written next to it and only replaced once the stream is complete
+fn stream<W: Write, R: Read>(
+    output: W,
+    path: Option<&Path>,
+    vaults: &[SshVault],
+    metadata: Metadata,
+    buffer: &mut Vec<u8>,
+    input: Option<R>,
+) -> Result<()> {
+    let mut file = path.map(vault_tempfile).transpose()?;
+
+    let output: Box<dyn Write + '_> = match file.as_mut() {
+        Some(file) => Box::new(file),
+        None => Box::new(output),
+    };
+
+    let mut writer = VaultWriter::new(output, vaults, metadata)?;
+
+    writer.write_all(buffer)?;
+    buffer.zeroize();
+
+    if let Some(mut input) = input {
+        io::copy(&mut input, &mut writer)?;
+    }
+
+    writer.finish()?;
+
+    match (file, path) {
+        (Some(file), Some(path)) => persist(file, path),
+        _ => Ok(()),
+    }
+}
+
+// the keys of the users, with -u new also the helper printed to open the vault:
+// the url from where to download the key or how the private key is shared
+fn user_keys(
+    users: &[String],
+    new_key: Option<&NewKey>,
+    int_key: Option<u32>,
+    fingerprint: Option<&String>,
+) -> Result<(Vec<PublicKey>, Option<String>)> {
+    let mut helper: Option<String> = None;
+
+    let mut ssh_keys: Vec<PublicKey> = Vec::new();
+
+    for user in users {
+        // the key is generated locally and its private key shared
+        if let Some(new_key) = new_key.filter(|mode| **mode != NewKey::Online) {
+            let key = online::generate_key()?;
+            helper = Some(share_key(&key, new_key)?);
+            ssh_keys.push(key.public_key().clone());
+            continue;
+        }
+
+        // get keys from GitHub or remote server
+        let keys = remote::get_keys(user)?;
+
+        // the keys must match the ones pinned on first use
+        if user != "new" {
+            trust::check(user, &keys)?;
+        }
+
+        // search key using -k or -f options
+        let ssh_key = remote::get_user_key(&keys, int_key, &fingerprint.cloned())?;
+
+        // if user equals "new" then we need to create a new key
+        if let Ok(key) = online::get_private_key_id(&ssh_key, user)
+            && !key.is_empty()
+        {
+            helper = Some(key);
+        }
+
+        ssh_keys.push(ssh_key);
+    }
+
+    Ok((ssh_keys, helper))
+}
+
+// validate the combination of -u with -k and -f
+fn check_users(
+    users: &[String],
+    indexes: &[String],
+    paths: &[String],
+    fingerprint: Option<&str>,
+    new_key: Option<&NewKey>,
+) -> Result<()> {
+    if users.iter().any(|user| user == "new") {
+        // if user equals "new" ignore the key and fingerprint
+        if !indexes.is_empty() || !paths.is_empty() || fingerprint.is_some() {
+            return Err(anyhow!("Options -k and -f not required when using -u new"));
+        }
+
+        if users.len() > 1 {
+            return Err(anyhow!("-u new can't be combined with other recipients"));
+        }
+
+        if matches!(new_key, Some(NewKey::Online | NewKey::Upload)) && remote::is_offline()? {
+            return Err(anyhow!(
+                "-u new requires network access, not available offline, use --new-key inline or --new-key-file"
+            ));
+        }
+    }
+
+    if users.len() > 1 && (!indexes.is_empty() || fingerprint.is_some()) {
+        return Err(anyhow!(
+            "Options -k N and -f can only be used with a single -u"
+        ));
+    }
+
+    Ok(())
+}
+
+// the -u new mode from --new-key, --new-key-file or new_key in config.yml
+fn new_key_mode(
+    users: &[String],
+    new_key: Option<String>,
+    new_key_file: Option<String>,
+) -> Result<Option<NewKey>> {
+    if !users.iter().any(|user| user == "new") {
+        if new_key.is_some() || new_key_file.is_some() {
+            return Err(anyhow!(
+                "Options --new-key and --new-key-file require -u new"
+            ));
+        }
+
+        return Ok(None);
+    }
+
+    if let Some(path) = new_key_file {
+        return Ok(Some(NewKey::File(path)));
+    }
+
+    let mode = match new_key {
+        Some(mode) => mode,
+        None => config::get()?
+            .get_string("new_key")
+            .unwrap_or_else(|_| String::from("online")),
+    };
+
+    match mode.as_str() {
+        "online" => Ok(Some(NewKey::Online)),
+        "inline" => Ok(Some(NewKey::Inline)),
+        "upload" => Ok(Some(NewKey::Upload)),
+        _ => Err(anyhow!(
+            "Invalid new_key {mode}, use online, inline or upload"
+        )),
+    }
+}
+
+// share the private key of a generated key, returns the helper to open the vault
+fn share_key(key: &PrivateKey, new_key: &NewKey) -> Result<String> {
+    match new_key {
+        NewKey::Inline => Ok(key.to_openssh(LineEnding::LF)?.to_string()),
+        NewKey::File(path) => {
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+
+            // never replace a file, even one created since the check
+            let mut file = options.open(path).map_err(|err| match err.kind() {
+                io::ErrorKind::AlreadyExists => anyhow!("Private key file {path} already exists"),
+                _ => anyhow!("Could not create the private key file {path}: {err}"),
+            })?;
+
+            file.write_all(key.to_openssh(LineEnding::LF)?.as_bytes())?;
+
+            Ok(path.clone())
+        }
+        NewKey::Upload => online::upload_key(key),
+        NewKey::Online => unreachable!(),
+    }
+}
+
+fn public_vault(ssh_key: PublicKey) -> Result<SshVault> {
+    let key_type = find::key_type(&ssh_key.algorithm())?;
+    SshVault::new(&key_type, Some(ssh_key), None)
+}
+
+// the local keys, with --agent the password is wrapped through ssh-agent and
+// the first key of the agent is the default, otherwise the identity configured
+// in ~/.ssh/config for the host
+fn local_vaults(
+    paths: Vec<String>,
+    agent: bool,
+    default: bool,
+    host: Option<&str>,
+) -> Result<Vec<SshVault>> {
+    let mut ssh_keys = paths
+        .into_iter()
+        .map(|key| find::public_key(Some(key)))
+        .collect::<Result<Vec<_>>>()?;
+
+    if agent {
+        if ssh_keys.is_empty() {
+            ssh_keys.push(find::agent_key()?);
+        }
+
+        return ssh_keys
+            .into_iter()
+            .map(|ssh_key| SshVault::new(&SshKeyType::Agent, Some(ssh_key), None))
+            .collect();
+    }
+
+    if ssh_keys.is_empty() && default {
+        ssh_keys.push(find::default_public_key(host)?);
+    }
+
+    ssh_keys.into_iter().map(public_vault).collect()
+}
+
+// metadata stored in the header of SSH-VAULT-V2 vaults
+fn new_metadata(
+    input: Option<&str>,
+    content_type: Option<String>,
+    label: Option<String>,
+) -> Result<Metadata> {
+    let mut metadata = Metadata::now()?;
+
+    if let Some(filename) = input
+        .filter(|input| *input != "-")
+        .and_then(|input| Path::new(input).file_name())
+    {
+        metadata.insert(metadata::FILENAME, filename.to_string_lossy());
+    }
+
+    if let Some(content_type) = content_type {
+        metadata.insert(metadata::CONTENT_TYPE, content_type);
+    }
+
+    if let Some(label) = label {
+        metadata.insert(metadata::LABEL, label);
+    }
+
+    Ok(metadata)
+}
+
 fn format<W: Write>(
     mut output: W,
     vault: String,
@@ -117,7 +444,10 @@ fn format<W: Write>(
 
         output.write_all(json.as_bytes())?;
     } else if let Some(helper) = helper {
-        let format = format!("echo \"{vault}\" | ssh-vault view -k {helper}");
+        let format = format!(
+            "echo \"{vault}\" | ssh-vault view -k {}",
+            shell_words::quote(&helper)
+        );
         output.write_all(format.as_bytes())?;
     } else {
         output.write_all(vault.as_bytes())?;
@@ -127,9 +457,126 @@ fn format<W: Write>(
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_check_users() {
+        let strings = |s: &[&str]| s.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let home = tempfile::TempDir::new().unwrap();
+        let online = Some(&NewKey::Online);
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_OFFLINE", Some("false".as_ref())),
+            ],
+            || {
+                assert!(
+                    check_users(&strings(&["alice"]), &strings(&["1"]), &[], None, None).is_ok()
+                );
+                assert!(
+                    check_users(
+                        &strings(&["alice", "bob"]),
+                        &strings(&["1"]),
+                        &[],
+                        None,
+                        None
+                    )
+                    .is_err()
+                );
+                assert!(check_users(&strings(&["new", "bob"]), &[], &[], None, online).is_err());
+                assert!(
+                    check_users(&strings(&["new"]), &[], &[], Some("SHA256:x"), online).is_err()
+                );
+                assert_eq!(
+                    check_users(&strings(&["new"]), &[], &[], None, online).is_ok(),
+                    cfg!(feature = "http")
+                );
+            },
+        );
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_OFFLINE", Some("true".as_ref())),
+            ],
+            || {
+                let err = check_users(&strings(&["new"]), &[], &[], None, online).unwrap_err();
+                assert!(err.to_string().contains("offline"));
+                assert!(
+                    check_users(&strings(&["new"]), &[], &[], None, Some(&NewKey::Upload)).is_err()
+                );
+                assert!(
+                    check_users(&strings(&["new"]), &[], &[], None, Some(&NewKey::Inline)).is_ok()
+                );
+                assert!(check_users(&strings(&["alice"]), &[], &[], None, None).is_ok());
+            },
+        );
+    }
+
+    #[test]
+    fn test_new_key_mode() {
+        let strings = |s: &[&str]| s.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_NEW_KEY", None),
+            ],
+            || {
+                assert_eq!(
+                    new_key_mode(&strings(&["alice"]), None, None).unwrap(),
+                    None
+                );
+                assert!(
+                    new_key_mode(&strings(&["alice"]), Some("inline".to_string()), None).is_err()
+                );
+                assert_eq!(
+                    new_key_mode(&strings(&["new"]), None, None).unwrap(),
+                    Some(NewKey::Online)
+                );
+                assert_eq!(
+                    new_key_mode(&strings(&["new"]), Some("upload".to_string()), None).unwrap(),
+                    Some(NewKey::Upload)
+                );
+                assert_eq!(
+                    new_key_mode(&strings(&["new"]), None, Some("key".to_string())).unwrap(),
+                    Some(NewKey::File("key".to_string()))
+                );
+            },
+        );
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_NEW_KEY", Some("inline".as_ref())),
+            ],
+            || {
+                assert_eq!(
+                    new_key_mode(&strings(&["new"]), None, None).unwrap(),
+                    Some(NewKey::Inline)
+                );
+                assert_eq!(
+                    new_key_mode(&strings(&["new"]), Some("online".to_string()), None).unwrap(),
+                    Some(NewKey::Online)
+                );
+            },
+        );
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_NEW_KEY", Some("random".as_ref())),
+            ],
+            || {
+                assert!(new_key_mode(&strings(&["new"]), None, None).is_err());
+            },
+        );
+    }
+
     #[test]
     fn test_format() -> Result<(), Box<dyn std::error::Error>> {
         let mut output = Vec::new();
@@ -153,6 +600,16 @@ mod tests {
         format(&mut output, vault, json, helper)?;
 
         assert_eq!(output, b"echo \"vault\" | ssh-vault view -k helper");
+
+        let mut output = Vec::new();
+        let helper = Some("my keys/$(id).key".to_string());
+
+        format(&mut output, "vault".to_string(), json, helper)?;
+
+        assert_eq!(
+            output,
+            b"echo \"vault\" | ssh-vault view -k 'my keys/$(id).key'"
+        );
         Ok(())
     }
 
@@ -181,4 +638,60 @@ mod tests {
         assert_eq!(output, b"{\"vault\":\"vault\",\"private_key\":\"helper\"}");
         Ok(())
     }
+
+    // fails after the first block of the input was encrypted
+    struct FailingReader(bool);
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0 {
+                return Err(io::Error::other("read failed"));
+            }
+            self.0 = true;
+            buf.fill(0);
+            Ok(buf.len())
+        }
+    }
+
+    #[test]
+    fn test_stream() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("secret.vault");
+        fs::write(&path, "").unwrap();
+
+        let vaults = [crate::vault::test_vaults::public_vault(
+            "test_data/ed25519.pub",
+        )];
+
+        // a failed stream keeps the vault file untouched and cleans up
+        let mut buffer = vec![1; 1024];
+        let rs = stream(
+            io::sink(),
+            Some(&path),
+            &vaults,
+            Metadata::now().unwrap(),
+            &mut buffer,
+            Some(FailingReader(false)),
+        );
+        assert!(rs.is_err());
+        assert!(fs::read(&path).unwrap().is_empty());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let mut buffer = vec![1; 1024];
+        stream(
+            io::sink(),
+            Some(&path),
+            &vaults,
+            Metadata::now().unwrap(),
+            &mut buffer,
+            Some(&b"Machs na"[..]),
+        )
+        .unwrap();
+        assert!(
+            fs::read_to_string(&path)
+                .unwrap()
+                .starts_with("SSH-VAULT-V2;CHACHA20-POLY1305-STREAM;")
+        );
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
 }
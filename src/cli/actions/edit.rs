@@ -1,7 +1,7 @@
-use crate::cli::actions::{Action, process_input};
-use crate::vault::{SshVault, crypto, dio, find, parse, ssh::decrypt_private_key};
-use anyhow::Result;
-use secrecy::{SecretSlice, SecretString};
+use crate::cli::actions::{Action, process_input, recipient_vault};
+use crate::vault::{Version, crypto, dio, parse, signature};
+use anyhow::{Result, anyhow};
+use secrecy::{ExposeSecret, SecretSlice, SecretString};
 use std::io::{Read, Write};
 
 /// Handle the edit action
@@ -10,6 +10,7 @@ use std::io::{Read, Write};
 pub fn handle(action: Action) -> Result<()> {
     match action {
         Action::Edit {
+            host,
             key,
             vault,
             passphrase,
@@ -22,37 +23,44 @@ pub fn handle(action: Action) -> Result<()> {
             // read the vault content
             input.read_to_string(&mut vault_data)?;
 
-            // parse the vault
-            let (key_type, fingerprint, password, data) = parse(&vault_data)?;
+            // store the new encrypted data
+            let mut new_secret = Vec::new();
 
-            // find the private_key using the vault header AES256 or CHACHA20-POLY1305
-            let mut private_key = find::private_key_type(key, key_type)?;
+            // parse the vault
+            let parsed = parse(&vault_data)?;
 
-            // decrypt private_key if encrypted
-            if private_key.is_encrypted() {
-                private_key = decrypt_private_key(&private_key, passphrase)?;
+            // the signature can't be kept, it covers the old vault
+            if signature::split(&vault_data).1.is_some() {
+                eprintln!(
+                    "Warning: the vault signature is removed, the edited vault is not signed"
+                );
             }
 
-            // RSA or ED25519
-            let key_type = find::key_type(&private_key.algorithm())?;
-
-            // initialize the vault
-            let vault = SshVault::new(&key_type, None, Some(private_key))?;
+            // initialize the vault using ssh-agent or the private key
+            let vault = recipient_vault(key, passphrase, &parsed.recipients, host.as_deref())?;
 
             // decrypt the vault
-            let secret = vault.view(&password, &data, &fingerprint)?;
+            let secret = vault.view(&parsed)?;
 
-            // store the new encrypted data
-            let mut new_secret = Vec::new();
+            // binary secrets can't be edited
+            let secret = std::str::from_utf8(secret.expose_secret())
+                .map_err(|_| anyhow!("The vault contains binary data and can't be edited"))?;
 
             // use the EDITOR env var to edit the existing secret
             process_input(&mut new_secret, Some(SecretString::from(secret)))?;
 
-            // generate password (32 rand chars)
-            let password: SecretSlice<u8> = crypto::gen_password()?;
+            let out = if parsed.version == Version::V2 {
+                // the password and header are kept so all the recipients can still open the vault
+                let password = vault.password(&parsed)?;
+
+                parsed.reseal(&password, &mut new_secret)?.to_string()
+            } else {
+                // generate password (32 rand chars)
+                let password: SecretSlice<u8> = crypto::gen_password()?;
 
-            // create vault
-            let out = vault.create(password, &mut new_secret)?;
+                // create vault
+                vault.create(password, &mut new_secret)?
+            };
 
             // save the vault
             output.truncate()?;
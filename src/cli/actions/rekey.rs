@@ -0,0 +1,132 @@
+use crate::cli::actions::{Action, persist, recipient_vault, vault_tempfile};
+use crate::vault::{
+    Algorithm, Metadata, SshVault, VaultReader, VaultWriter, Version, crypto, find, multi, remote,
+    trust,
+};
+use anyhow::{Context, Result, anyhow};
+use secrecy::SecretString;
+use ssh_key::PublicKey;
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    path::Path,
+};
+use zeroize::Zeroizing;
+
+/// Handle the rekey action
+///
+/// # Errors
+///
+/// Returns an error if the new keys can't be loaded, a vault can't be
+/// decrypted with the private key or the new vault can't be written. Vaults
+/// are replaced one by one, the ones processed before the error are kept.
+pub fn handle(action: Action) -> Result<()> {
+    match action {
+        Action::Rekey {
+            key,
+            passphrase,
+            to_keys,
+            to_users,
+            vaults,
+        } => {
+            let mut ssh_keys: Vec<PublicKey> = Vec::new();
+
+            for user in &to_users {
+                if user == "new" {
+                    return Err(anyhow!("--to-user new is not supported, use a known user"));
+                }
+
+                // get keys from GitHub or remote server
+                let keys = remote::get_keys(user)?;
+
+                // the keys must match the ones pinned on first use
+                trust::check(user, &keys)?;
+
+                ssh_keys.push(remote::get_user_key(&keys, None, &None)?);
+            }
+
+            for key in to_keys {
+                ssh_keys.push(find::public_key(Some(key))?);
+            }
+
+            let recipients = ssh_keys
+                .into_iter()
+                .map(|ssh_key| {
+                    let key_type = find::key_type(&ssh_key.algorithm())?;
+                    SshVault::new(&key_type, Some(ssh_key), None)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            for vault in vaults {
+                rekey(
+                    Path::new(&vault),
+                    key.clone(),
+                    passphrase.clone(),
+                    &recipients,
+                )
+                .with_context(|| format!("Failed to rekey {vault}"))?;
+            }
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+// decrypt the vault and replace it with a new one for the recipients
+fn rekey(
+    path: &Path,
+    key: Option<String>,
+    passphrase: Option<SecretString>,
+    recipients: &[SshVault],
+) -> Result<()> {
+    let mut reader = VaultReader::new(BufReader::new(File::open(path)?))?;
+
+    // use ssh-agent or the private key matching one of the vault recipients
+    reader.unlock(&recipient_vault(
+        key,
+        passphrase,
+        &reader.vault().recipients,
+        None,
+    )?)?;
+
+    let parsed = reader.vault();
+
+    // write next to the vault so it can be renamed atomically
+    let mut output = vault_tempfile(path)?;
+
+    if parsed.algorithm == Algorithm::ChaCha20Poly1305Stream {
+        let mut writer = VaultWriter::new(&mut output, recipients, parsed.metadata.clone())?;
+        io::copy(&mut reader, &mut writer)?;
+        writer.finish()?;
+    } else {
+        // the signature can't be kept, it covers the old vault
+        if reader.is_signed() {
+            eprintln!(
+                "Warning: the signature of {} is removed, the rekeyed vault is not signed",
+                path.display()
+            );
+        }
+
+        let version = parsed.version;
+        let metadata = parsed.metadata.clone();
+
+        let mut secret = Zeroizing::new(Vec::new());
+        reader.read_to_end(&mut secret)?;
+
+        // generate password (32 rand chars)
+        let password = crypto::gen_password()?;
+
+        // keep the single key format when possible
+        let vault = match recipients {
+            [vault] if version == Version::V1 => vault.create(password, &mut secret)?,
+            _ if version == Version::V1 => {
+                multi::create(recipients, Metadata::now()?, &password, &mut secret)?
+            }
+            _ => multi::create(recipients, metadata, &password, &mut secret)?,
+        };
+
+        output.write_all(vault.as_bytes())?;
+    }
+
+    persist(output, path)
+}
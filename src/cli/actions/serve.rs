@@ -0,0 +1,39 @@
+use crate::cli::actions::Action;
+use crate::tools;
+use crate::vault::keyserver::{KeyServer, Store};
+use anyhow::{Context, Result};
+use std::{net::TcpListener, sync::Arc};
+
+/// Handle the serve action
+///
+/// # Errors
+///
+/// Returns an error if the address can't be bound or the store can't be
+/// created.
+pub fn handle(action: Action) -> Result<()> {
+    match action {
+        Action::Serve {
+            bind,
+            expire,
+            store,
+            max_keys,
+        } => {
+            let store = match store {
+                Some(dir) => Store::disk(&tools::expand_home(&dir)?)?,
+                None => Store::memory(),
+            };
+
+            let listener =
+                TcpListener::bind(&bind).with_context(|| format!("Could not listen on {bind}"))?;
+
+            eprintln!(
+                "Serving keys on http://{}/new, set sshkeys_online to use it",
+                listener.local_addr()?
+            );
+
+            Arc::new(KeyServer::new(store, expire).max_keys(max_keys)).serve(&listener);
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
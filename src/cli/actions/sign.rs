@@ -0,0 +1,272 @@
+use crate::cli::actions::{Action, signing_key};
+use crate::vault::{
+    dio,
+    signature::{self, Signers},
+    ssh::allowed_signers,
+};
+use anyhow::{Context, Result, anyhow};
+use ssh_key::{HashAlg, LineEnding, PublicKey};
+use std::{
+    fs,
+    io::{Read, Write},
+};
+
+/// Handle the sign and verify actions, the signatures are the armored SSHSIG
+/// of `ssh-keygen -Y sign`.
+///
+/// # Errors
+///
+/// Returns an error if the message or key can't be read, signing fails, or
+/// the signature is invalid or not made by a trusted signer.
+pub fn handle(action: Action) -> Result<()> {
+    match action {
+        Action::Sign {
+            input,
+            key,
+            namespace,
+            output,
+            passphrase,
+        } => {
+            let key = signing_key(&key, passphrase)?;
+
+            let mut message = Vec::new();
+            dio::InputSource::new(input)?.read_to_end(&mut message)?;
+
+            let signature = signature::sign_message(&key, &namespace, &message)
+                .context("Could not sign the message")?
+                .to_pem(LineEnding::LF)?;
+
+            let mut output = dio::OutputDestination::new(output)?;
+            output.truncate()?;
+            output.write_all(signature.as_bytes())?;
+        }
+        Action::Verify {
+            allowed_signers,
+            identity,
+            input,
+            keys,
+            namespace,
+            signature,
+            users,
+        } => {
+            let armored = fs::read_to_string(&signature)
+                .with_context(|| format!("Could not read the signature {signature}"))?;
+
+            let mut message = Vec::new();
+            dio::InputSource::new(input)?.read_to_end(&mut message)?;
+
+            let key = signature::verify_message(&namespace, &message, &armored)?;
+
+            let signer = match identity {
+                Some(identity) => {
+                    allowed_identity(&key, &identity, allowed_signers.as_deref(), &namespace)?
+                }
+                None => {
+                    trusted_signer(&key, &keys, &users, allowed_signers.as_deref(), &namespace)?
+                }
+            };
+
+            println!(
+                "Good \"{namespace}\" signature for {signer} with key {}",
+                key.fingerprint(HashAlg::Sha256)
+            );
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+// the identity must match the principals of an allowed_signers entry of the
+// key valid for the namespace, like ssh-keygen -Y verify -I
+fn allowed_identity(
+    key: &PublicKey,
+    identity: &str,
+    allowed_signers: Option<&str>,
+    namespace: &str,
+) -> Result<String> {
+    for path in signature::allowed_signers_paths(allowed_signers)? {
+        if allowed_signers::load(&path)?.iter().any(|signer| {
+            signer.key.key_data() == key.key_data()
+                && signer.allows(namespace)
+                && signer.matches_principal(identity)
+        }) {
+            return Ok(identity.to_string());
+        }
+    }
+
+    Err(anyhow!(
+        "The key {} is not allowed to sign for {identity} in the namespace {namespace}",
+        key.fingerprint(HashAlg::Sha256)
+    ))
+}
+
+// the name of the key file, user or allowed_signers principals holding the key
+fn trusted_signer(
+    key: &PublicKey,
+    keys: &[String],
+    users: &[String],
+    allowed_signers: Option<&str>,
+    namespace: &str,
+) -> Result<String> {
+    let signers = Signers::load(keys, users, allowed_signers, namespace)?;
+
+    if signers.is_empty() {
+        return Err(anyhow!(
+            "No trusted signers, use --key, --user or --allowed-signers"
+        ));
+    }
+
+    signers.find(key).map(ToString::to_string).ok_or_else(|| {
+        anyhow!(
+            "The signature is made by {}, not by a trusted signer",
+            key.fingerprint(HashAlg::Sha256)
+        )
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn sign(key: &str, namespace: &str) -> Result<String> {
+        let output = NamedTempFile::new().unwrap();
+
+        handle(Action::Sign {
+            input: Some("test_data/message.txt".to_string()),
+            key: key.to_string(),
+            namespace: namespace.to_string(),
+            output: Some(output.path().to_str().unwrap().to_string()),
+            passphrase: None,
+        })?;
+
+        Ok(fs::read_to_string(output.path()).unwrap())
+    }
+
+    fn verify(
+        signature: &str,
+        keys: &[&str],
+        allowed_signers: Option<&str>,
+        identity: Option<&str>,
+    ) -> Result<()> {
+        handle(Action::Verify {
+            allowed_signers: allowed_signers.map(ToString::to_string),
+            identity: identity.map(ToString::to_string),
+            input: Some("test_data/message.txt".to_string()),
+            keys: keys.iter().map(ToString::to_string).collect(),
+            namespace: "file".to_string(),
+            signature: signature.to_string(),
+            users: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_sign_as_ssh_keygen() {
+        // Ed25519 and RSA PKCS#1 v1.5 signatures are deterministic
+        for key in ["ed25519", "id_rsa"] {
+            assert_eq!(
+                sign(&format!("test_data/{key}"), "file").unwrap(),
+                fs::read_to_string(format!("test_data/message.txt.{key}.sig")).unwrap()
+            );
+        }
+
+        assert!(sign("test_data/ed25519", "").is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let home = tempfile::TempDir::new().unwrap();
+        let allowed_signers = home.path().join("allowed_signers");
+        fs::write(
+            &allowed_signers,
+            format!(
+                "alice@example.com {}bob@example.com namespaces=\"git\" {}",
+                fs::read_to_string("test_data/ecdsa_p256.pub").unwrap(),
+                fs::read_to_string("test_data/id_rsa.pub").unwrap()
+            ),
+        )
+        .unwrap();
+        let allowed_signers = allowed_signers.to_str();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_ALLOWED_SIGNERS", None),
+            ],
+            || {
+                let ed25519 = "test_data/message.txt.ed25519.sig";
+                let ecdsa = "test_data/message.txt.ecdsa_p256.sig";
+                let rsa = "test_data/message.txt.id_rsa.sig";
+
+                verify(ed25519, &["test_data/ed25519.pub"], None, None).unwrap();
+                verify(
+                    rsa,
+                    &["test_data/ed25519.pub", "test_data/id_rsa.pub"],
+                    None,
+                    None,
+                )
+                .unwrap();
+                assert!(verify(rsa, &["test_data/ed25519.pub"], None, None).is_err());
+
+                // no trusted signers
+                assert!(verify(ed25519, &[], None, None).is_err());
+
+                verify(ecdsa, &[], allowed_signers, None).unwrap();
+                verify(ecdsa, &[], allowed_signers, Some("alice@example.com")).unwrap();
+                assert!(verify(ecdsa, &[], allowed_signers, Some("bob@example.com")).is_err());
+
+                // bob may only sign for git
+                assert!(verify(rsa, &[], allowed_signers, None).is_err());
+                assert!(verify(rsa, &[], allowed_signers, Some("bob@example.com")).is_err());
+
+                // another message or namespace
+                let err = handle(Action::Verify {
+                    allowed_signers: None,
+                    identity: None,
+                    input: Some("test_data/ed25519.pub".to_string()),
+                    keys: vec!["test_data/ed25519.pub".to_string()],
+                    namespace: "file".to_string(),
+                    signature: ed25519.to_string(),
+                    users: Vec::new(),
+                })
+                .unwrap_err();
+                assert!(err.to_string().contains("Bad signature"));
+
+                let err = handle(Action::Verify {
+                    allowed_signers: None,
+                    identity: None,
+                    input: Some("test_data/message.txt".to_string()),
+                    keys: vec!["test_data/ed25519.pub".to_string()],
+                    namespace: "git".to_string(),
+                    signature: ed25519.to_string(),
+                    users: Vec::new(),
+                })
+                .unwrap_err();
+                assert!(err.to_string().contains("namespace"));
+            },
+        );
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            let signature = NamedTempFile::new().unwrap();
+            fs::write(
+                signature.path(),
+                sign("test_data/ecdsa_p256", "file").unwrap(),
+            )
+            .unwrap();
+
+            verify(
+                signature.path().to_str().unwrap(),
+                &["test_data/ecdsa_p256.pub"],
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+}
@@ -0,0 +1,109 @@
+use crate::cli::actions::Action;
+use crate::vault::{remote, trust};
+use anyhow::Result;
+
+/// Handle the trust and untrust actions
+///
+/// # Errors
+///
+/// Returns an error if the keys can't be fetched or the pinned keys can't be
+/// read or written.
+pub fn handle(action: Action) -> Result<()> {
+    match action {
+        Action::Trust { users } => {
+            if users.is_empty() {
+                for (user, fingerprint) in trust::pins()? {
+                    println!("{user} {fingerprint}");
+                }
+            }
+
+            for user in &users {
+                // skip the cache, it could hold the keys being replaced
+                let keys = remote::refresh_keys(user)?;
+
+                let fingerprints = trust::fingerprints(&keys)?;
+                let pinned = trust::pinned(user)?.unwrap_or_default();
+
+                trust::pin(user, &fingerprints)?;
+
+                println!("Trusted {} key(s) of {user}:", fingerprints.len());
+
+                for fingerprint in &fingerprints {
+                    let mark = if pinned.is_empty() || pinned.contains(fingerprint) {
+                        " "
+                    } else {
+                        "+"
+                    };
+                    println!("{mark} {fingerprint}");
+                }
+
+                for fingerprint in pinned.iter().filter(|f| !fingerprints.contains(f)) {
+                    println!("- {fingerprint}");
+                }
+            }
+        }
+        Action::Untrust { users } => {
+            for user in &users {
+                if !trust::unpin(user)? {
+                    eprintln!("No pinned keys for {user}");
+                }
+            }
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "http"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{cache, vault::http::stand_in};
+    use std::fs;
+
+    #[test]
+    fn test_handle_trust() {
+        let home = tempfile::TempDir::new().unwrap();
+        let ed25519 = fs::read_to_string("test_data/ed25519.pub").unwrap();
+        let rsa = fs::read_to_string("test_data/id_rsa.pub").unwrap();
+        let server = stand_in::start(&[("/alice.keys", &ed25519), ("/bob.keys", &rsa)]);
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            let alice = format!("{}/alice.keys", server.url);
+
+            trust::check(&alice, &ed25519).unwrap();
+
+            // a poisoned cache is detected
+            let cache_key = format!("{:x}", md5::compute(alice.as_bytes()));
+            cache::put(&cache_key, &rsa).unwrap();
+            let keys = remote::get_keys(&alice).unwrap();
+            assert!(trust::check(&alice, &keys).is_err());
+
+            // and replaced when trusting the keys
+            handle(Action::Trust {
+                users: vec![alice.clone()],
+            })
+            .unwrap();
+            assert_eq!(server.requests().len(), 1);
+
+            let keys = remote::get_keys(&alice).unwrap();
+            assert_eq!(keys, ed25519);
+            trust::check(&alice, &keys).unwrap();
+
+            handle(Action::Trust { users: Vec::new() }).unwrap();
+
+            handle(Action::Untrust {
+                users: vec![alice.clone(), format!("{}/bob.keys", server.url)],
+            })
+            .unwrap();
+            assert!(trust::pinned(&alice).unwrap().is_none());
+
+            assert!(
+                handle(Action::Trust {
+                    users: vec![format!("{}/carol.keys", server.url)],
+                })
+                .is_err()
+            );
+        });
+    }
+}
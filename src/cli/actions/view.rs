@@ -1,54 +1,423 @@
-use crate::cli::actions::Action;
-use crate::vault::{SshVault, dio, find, parse, ssh::decrypt_private_key};
-use anyhow::Result;
-use std::io::{Read, Write};
-use zeroize::Zeroize;
+use crate::cli::actions::{Action, create::JsonVault, recipient_vault};
+use crate::config;
+use crate::vault::{
+    Recipient, VaultReader, dio, remote,
+    signature::{self, Signers},
+    ssh::krl,
+    stream::STREAM_HEADER,
+};
+use anyhow::{Context, Result, anyhow};
+use ssh_key::HashAlg;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use zeroize::Zeroizing;
 
-/// Handle viewing a vault.
+// size of the first block of the secret checked before writing the output
+const BLOCK_SIZE: u64 = 8 * 1024;
+
+/// Handle viewing a vault from a file, stdin or URL. The output of `create
+/// --json` and the shell line of `create -u new` are accepted, their private
+/// key URL is used unless a key is given. The signature of signed vaults is
+/// verified and the signer reported.
 ///
 /// # Errors
 ///
-/// Returns an error if reading the vault fails, the key cannot be loaded or
-/// decrypted, or decryption of the vault content fails.
+/// Returns an error if reading the vault fails, a signer is required and the
+/// vault is not signed by one, the key cannot be loaded or decrypted,
+/// decryption of the vault content fails, or the secret is binary and the
+/// output is a terminal.
 pub fn handle(action: Action) -> Result<()> {
     match action {
         Action::View {
+            allowed_signers,
+            force,
+            host,
             key,
             output,
             vault,
             passphrase,
+            require_signer,
+            signer_keys,
+            signer_users,
         } => {
-            let mut data = String::new();
+            let (input, private_key) = open_vault(vault)?;
+
+            let require_signer =
+                require_signer || config::get()?.get_bool("require_signer").unwrap_or(false);
+
+            let signers = Signers::load(
+                &signer_keys,
+                &signer_users,
+                allowed_signers.as_deref(),
+                signature::NAMESPACE,
+            )?;
 
-            // setup Reader(input) and Writer (output)
-            let (mut input, mut output) = dio::setup_io(vault, output)?;
+            let input = check_signature(input, &signers, require_signer)?;
 
-            input.read_to_string(&mut data)?;
+            let key = key.or(private_key);
 
-            // parse vault
-            let (key_type, fingerprint, password, data) = parse(&data)?;
+            let mut output = dio::OutputDestination::new(output)?;
 
-            // find the private_key using the vault header AES256 or CHACHA20-POLY1305
-            let mut private_key = find::private_key_type(key, key_type)?;
+            // parse the vault header, stream vaults are decrypted in chunks
+            let mut reader = VaultReader::new(input)?;
 
-            // decrypt private_key if encrypted
-            if private_key.is_encrypted() {
-                private_key = decrypt_private_key(&private_key, passphrase)?;
+            // the vault can still be opened, the key may have been revoked later
+            for fingerprint in revoked_recipients(&reader.vault().recipients)? {
+                eprintln!("Warning: the vault is encrypted to the revoked key {fingerprint}");
             }
 
-            // RSA or ED25519
-            let key_type = find::key_type(&private_key.algorithm())?;
+            // use ssh-agent or the private key matching one of the vault recipients
+            reader.unlock(&recipient_vault(
+                key,
+                passphrase,
+                &reader.vault().recipients,
+                host.as_deref(),
+            )?)?;
 
-            let vault = SshVault::new(&key_type, None, Some(private_key))?;
+            // the secret is zeroized when dropped
+            let mut block = Zeroizing::new(Vec::new());
+            (&mut reader).take(BLOCK_SIZE).read_to_end(&mut block)?;
 
-            let mut data = vault.view(&password, &data, &fingerprint)?;
+            check_output(&block, output.is_terminal(), force)?;
 
-            output.write_all(data.as_bytes())?;
+            output.truncate()?;
+            output.write_all(&block)?;
 
-            // zeroize the secret
-            data.zeroize();
+            if let Err(err) = io::copy(&mut reader, &mut output) {
+                // don't leave a partially decrypted secret behind
+                output.truncate()?;
+                return Err(err.into());
+            }
         }
         _ => unreachable!(),
     }
     Ok(())
 }
+
+// read the vault from a file, stdin or URL (never cached), the JSON and shell
+// line envelopes are unwrapped returning the private key URL they hold
+fn open_vault(vault: Option<String>) -> Result<(Box<dyn BufRead>, Option<String>)> {
+    let mut input: Box<dyn BufRead> = match vault {
+        Some(url) if url.starts_with("http://") || url.starts_with("https://") => {
+            Box::new(Cursor::new(remote::request(&url, false)?.into_bytes()))
+        }
+        vault => Box::new(BufReader::new(dio::InputSource::new(vault)?)),
+    };
+
+    let start = input.fill_buf()?;
+
+    if !(start.starts_with(b"{") || start.starts_with(b"echo ")) {
+        return Ok((input, None));
+    }
+
+    let mut data = String::new();
+    input.read_to_string(&mut data)?;
+
+    let (vault, private_key) = unwrap_vault(&data)?;
+
+    Ok((Box::new(Cursor::new(vault.into_bytes())), private_key))
+}
+
+// verify the signature appended to the vault and report the signer, returns
+// the vault without the signature. Streams are never signed, they are too
+// large to be verified before decrypting.
+fn check_signature(
+    mut input: Box<dyn BufRead>,
+    signers: &Signers,
+    require: bool,
+) -> Result<Box<dyn BufRead>> {
+    let unsigned = || {
+        if require {
+            Err(anyhow!("The vault is not signed"))
+        } else {
+            Ok(())
+        }
+    };
+
+    if input.fill_buf()?.starts_with(STREAM_HEADER.as_bytes()) {
+        unsigned()?;
+        return Ok(input);
+    }
+
+    let mut data = String::new();
+    input.read_to_string(&mut data)?;
+
+    let (vault, signature) = signature::split(&data);
+
+    let Some(signature) = signature else {
+        unsigned()?;
+        return Ok(Box::new(Cursor::new(data.into_bytes())));
+    };
+
+    match signature::verify(vault, signature) {
+        Ok(key) => {
+            let fingerprint = key.fingerprint(HashAlg::Sha256);
+
+            match signers.find(&key) {
+                Some(signer) => eprintln!("Good signature by {signer} {fingerprint}"),
+                None if require && signers.is_empty() => {
+                    return Err(anyhow!(
+                        "The vault is signed by {fingerprint}, no trusted signers given, use --signer-key, --signer-user or --allowed-signers"
+                    ));
+                }
+                None if require => {
+                    return Err(anyhow!(
+                        "The vault is signed by {fingerprint}, not by a trusted signer"
+                    ));
+                }
+                None if signers.is_empty() => eprintln!("Signed by {fingerprint}"),
+                None => {
+                    eprintln!(
+                        "Warning: the vault is signed by {fingerprint}, not by a trusted signer"
+                    );
+                }
+            }
+        }
+        Err(err) if require => return Err(err),
+        Err(err) => eprintln!("Warning: {err:#}"),
+    }
+
+    Ok(Box::new(Cursor::new(vault.as_bytes().to_vec())))
+}
+
+// the vault and private key URL of `{"vault":..., "private_key":...}` or
+// `echo "<vault>" | ssh-vault view -k <url>`
+fn unwrap_vault(data: &str) -> Result<(String, Option<String>)> {
+    let data = data.trim();
+
+    if data.starts_with('{') {
+        let json: JsonVault = serde_json::from_str(data).context("Invalid JSON vault")?;
+        return Ok((json.vault, json.private_key));
+    }
+
+    let (vault, command) = data
+        .strip_prefix("echo \"")
+        .and_then(|rest| rest.rsplit_once("\" |"))
+        .ok_or_else(|| anyhow!("Invalid vault, expected echo \"<vault>\" | ssh-vault view"))?;
+
+    let args = shell_words::split(command)?;
+
+    let private_key = args
+        .iter()
+        .position(|arg| arg == "-k" || arg == "--key")
+        .and_then(|index| args.get(index + 1))
+        .cloned();
+
+    Ok((vault.to_string(), private_key))
+}
+
+// the fingerprints of the recipients revoked in the configured revoked_keys
+fn revoked_recipients(recipients: &[Recipient]) -> Result<Vec<&str>> {
+    let Some(krl) = krl::load()? else {
+        return Ok(Vec::new());
+    };
+
+    Ok(recipients
+        .iter()
+        .map(|recipient| recipient.fingerprint.as_str())
+        .filter(|fingerprint| krl.is_fingerprint_revoked(fingerprint))
+        .collect())
+}
+
+// refuse to dump binary data to a terminal unless forced, the data may end in
+// the middle of a UTF-8 char
+fn check_output(data: &[u8], is_terminal: bool, force: bool) -> Result<()> {
+    if is_terminal
+        && !force
+        && std::str::from_utf8(data).is_err_and(|err| err.error_len().is_some())
+    {
+        return Err(anyhow!(
+            "The vault contains binary data, use -o to write it to a file or --force to print it"
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::vault::{find, fingerprint};
+
+    #[test]
+    fn test_unwrap_vault() {
+        let (vault, private_key) =
+            unwrap_vault(r#"{"vault":"SSH-VAULT;AES256;abc","private_key":"https://k"}"#).unwrap();
+        assert_eq!(vault, "SSH-VAULT;AES256;abc");
+        assert_eq!(private_key.as_deref(), Some("https://k"));
+
+        let (vault, private_key) = unwrap_vault(r#"{"vault":"SSH-VAULT;AES256;abc"}"#).unwrap();
+        assert_eq!(vault, "SSH-VAULT;AES256;abc");
+        assert!(private_key.is_none());
+
+        let (vault, private_key) = unwrap_vault(
+            "echo \"SSH-VAULT;AES256;abc\ndef\" | ssh-vault view -k https://ssh-keys.online/key/xyz\n",
+        )
+        .unwrap();
+        assert_eq!(vault, "SSH-VAULT;AES256;abc\ndef");
+        assert_eq!(
+            private_key.as_deref(),
+            Some("https://ssh-keys.online/key/xyz")
+        );
+
+        assert!(unwrap_vault("{invalid").is_err());
+        assert!(unwrap_vault("echo SSH-VAULT").is_err());
+    }
+
+    #[test]
+    fn test_check_output() {
+        let binary = [0xff, 0xfe, 0x00];
+
+        assert!(check_output(b"secret", true, false).is_ok());
+        assert!(check_output(&binary, true, false).is_err());
+        assert!(check_output(&binary, true, true).is_ok());
+        assert!(check_output(&binary, false, false).is_ok());
+
+        // a block can end in the middle of a char
+        assert!(check_output(b"\xc3", true, false).is_ok());
+    }
+
+    #[test]
+    fn test_revoked_recipients() {
+        let recipients = ["test_data/ed25519.pub", "test_data/ecdsa_p384.pub"].map(|key| {
+            let public_key = ssh_key::PublicKey::read_openssh_file(key.as_ref()).unwrap();
+
+            Recipient {
+                key_type: find::key_type(&public_key.algorithm()).unwrap(),
+                fingerprint: fingerprint::vault_fingerprint(&public_key).unwrap(),
+                ephemeral_key: None,
+                wrapped_key: Vec::new(),
+            }
+        });
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                (
+                    "SSH_VAULT_REVOKED_KEYS",
+                    Some("test_data/revoked.krl".as_ref()),
+                ),
+            ],
+            || {
+                let [ed25519, ecdsa] = &recipients;
+                assert_eq!(
+                    revoked_recipients(&recipients).unwrap(),
+                    vec![ecdsa.fingerprint.as_str()]
+                );
+                assert!(
+                    revoked_recipients(std::slice::from_ref(ed25519))
+                        .unwrap()
+                        .is_empty()
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn test_view_envelopes() {
+        use crate::cli::actions::create;
+        use crate::vault::http::stand_in;
+        use std::fs;
+        use tempfile::NamedTempFile;
+
+        let home = tempfile::TempDir::new().unwrap();
+        let input = NamedTempFile::new().unwrap();
+        fs::write(input.path(), "the secret").unwrap();
+        let vault_file = NamedTempFile::new().unwrap();
+
+        create::handle(Action::Create {
+            agent: false,
+            content_type: None,
+            fingerprint: None,
+            host: None,
+            input: Some(input.path().to_str().unwrap().to_string()),
+            json: false,
+            keys: vec!["test_data/ed25519.pub".to_string()],
+            known_hosts: Vec::new(),
+            label: None,
+            new_key: None,
+            new_key_file: None,
+            passphrase: None,
+            sign_with: None,
+            users: Vec::new(),
+            vault: Some(vault_file.path().to_str().unwrap().to_string()),
+        })
+        .unwrap();
+
+        let vault = fs::read_to_string(vault_file.path()).unwrap();
+        let private_key = fs::read_to_string("test_data/ed25519").unwrap();
+        let server = stand_in::start(&[("/key", &private_key)]);
+        let key_url = format!("{}/key", server.url);
+
+        let json = serde_json::to_string(&JsonVault {
+            vault: vault.clone(),
+            private_key: Some(key_url.clone()),
+        })
+        .unwrap();
+        let shell = format!("echo \"{vault}\" | ssh-vault view -k {key_url}");
+
+        let server = stand_in::start(&[
+            ("/key", &private_key),
+            ("/vault.json", &json),
+            ("/vault.sh", &shell),
+        ]);
+        let json_file = NamedTempFile::new().unwrap();
+        fs::write(json_file.path(), &json).unwrap();
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            for vault in [
+                json_file.path().to_str().unwrap().to_string(),
+                format!("{}/vault.json", server.url),
+                format!("{}/vault.sh", server.url),
+            ] {
+                let output = NamedTempFile::new().unwrap();
+
+                handle(Action::View {
+                    allowed_signers: None,
+                    force: false,
+                    host: None,
+                    key: None,
+                    output: Some(output.path().to_str().unwrap().to_string()),
+                    passphrase: None,
+                    require_signer: false,
+                    signer_keys: Vec::new(),
+                    signer_users: Vec::new(),
+                    vault: Some(vault),
+                })
+                .unwrap();
+
+                assert_eq!(fs::read_to_string(output.path()).unwrap(), "the secret");
+            }
+
+            // the vault is not cached
+            handle(Action::View {
+                allowed_signers: None,
+                force: false,
+                host: None,
+                key: Some("test_data/ed25519".to_string()),
+                output: Some(
+                    NamedTempFile::new()
+                        .unwrap()
+                        .path()
+                        .to_str()
+                        .unwrap()
+                        .to_string(),
+                ),
+                passphrase: None,
+                require_signer: false,
+                signer_keys: Vec::new(),
+                signer_users: Vec::new(),
+                vault: Some(format!("{}/vault.sh", server.url)),
+            })
+            .unwrap();
+            let vaults = server
+                .requests()
+                .iter()
+                .filter(|request| request.contains("/vault.sh"))
+                .count();
+            assert_eq!(vaults, 2);
+        });
+    }
+}
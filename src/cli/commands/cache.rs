@@ -0,0 +1,82 @@
+use clap::{Arg, Command};
+
+pub fn subcommand_cache() -> Command {
+    let entries = || {
+        Arg::new("entry")
+            .help("Cache key, URL or user (<provider>:<user>)")
+            .num_args(1..)
+    };
+
+    Command::new("cache")
+        .about("Manage the cache of fetched keys")
+        .after_help(
+            r"Examples:
+
+List the cached keys with their age and origin:
+
+    ssh-vault cache list
+
+Fetch again the keys of Alice:
+
+    ssh-vault cache refresh alice
+
+The entries expire after cache_ttl (30d by default) in config.yml, expired
+entries are revalidated with the server.
+",
+        )
+        .subcommand_required(true)
+        .subcommand(Command::new("list").about("List the cache entries"))
+        .subcommand(
+            Command::new("show")
+                .about("Show a cache entry")
+                .arg(entries().required(true).num_args(1)),
+        )
+        .subcommand(
+            Command::new("clear")
+                .about("Remove the given cache entries or all of them")
+                .arg(entries()),
+        )
+        .subcommand(
+            Command::new("refresh")
+                .about("Fetch again the given cache entries or all of them")
+                .arg(entries()),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subcommand_cache() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_cache());
+        let matches =
+            app.try_get_matches_from(vec!["ssh-vault", "cache", "clear", "alice", "bob"])?;
+
+        let m = matches
+            .subcommand_matches("cache")
+            .ok_or("No cache subcommand")?
+            .subcommand_matches("clear")
+            .ok_or("No clear subcommand")?
+            .to_owned();
+        assert_eq!(
+            m.get_many::<String>("entry")
+                .ok_or("No entry")?
+                .collect::<Vec<_>>(),
+            vec!["alice", "bob"]
+        );
+
+        let app = Command::new("ssh-vault").subcommand(subcommand_cache());
+        assert!(
+            app.try_get_matches_from(vec!["ssh-vault", "cache"])
+                .is_err()
+        );
+
+        let app = Command::new("ssh-vault").subcommand(subcommand_cache());
+        assert!(
+            app.try_get_matches_from(vec!["ssh-vault", "cache", "show"])
+                .is_err()
+        );
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use clap::{Arg, Command, builder::ValueParser};
+use clap::{Arg, ArgAction, Command, builder::ValueParser};
 use regex::Regex;
 
 const REGEX_MD5_FINGERPRINT: &str = r"^([0-9a-f]{2}:){15}([0-9a-f]{2})$";
@@ -17,25 +17,65 @@ pub fn validator_fingerprint() -> ValueParser {
     })
 }
 
-pub fn subcommand_create() -> Command {
-    Command::new("create")
-        .about("Create a new vault")
-        .after_help(
-            r#"Examples:
+const EXAMPLES: &str = r#"Examples:
 
 Share a secret:
 
     echo "secret" | ssh-vault create -u new | pbcopy
 
+Share a secret with a key generated locally, the private key is in the JSON:
+
+    echo "secret" | ssh-vault create -u new --new-key inline
+
+Share a secret with a key generated locally and saved to a file:
+
+    echo "secret" | ssh-vault create -u new --new-key-file secret.key
+
+Share a secret with a key generated locally and uploaded to new_key_upload:
+
+    echo "secret" | ssh-vault create -u new --new-key upload
+
 Share a secret with a known user in GitHub:
 
     echo "secret" | ssh-vault create -u alice
 
+Share a secret with a user in GitLab or a self-hosted Gitea:
+
+    echo "secret" | ssh-vault create -u gitlab:alice -u gitea@git.example.com:bob
+
 Share a secret with Alice using its second key:
 
     echo "secret" | ssh-vault create -u alice -k 2
-"#,
-        )
+
+Share a secret with Alice, Bob and a local key:
+
+    echo "secret" | ssh-vault create -u alice -u bob -k ~/.ssh/id_ed25519.pub
+
+Share a secret with Alice and yourself using the key in ssh-agent:
+
+    echo "secret" | ssh-vault create -u alice --agent
+
+Share a secret with the key configured in ~/.ssh/config for a host:
+
+    echo "secret" | ssh-vault create --host work
+
+Share a secret with a server using its host key from known_hosts:
+
+    echo "secret" | ssh-vault create --known-host vault.example.com:2222
+
+Add a label to the vault:
+
+    echo "secret" | ssh-vault create --label "db password" -u alice
+
+Sign the vault, view reports the signer:
+
+    echo "secret" | ssh-vault create -u alice --sign-with ~/.ssh/id_ed25519
+"#;
+
+pub fn subcommand_create() -> Command {
+    Command::new("create")
+        .about("Create a new vault")
+        .after_help(EXAMPLES)
         .visible_alias("c")
         .arg(
             Arg::new("fingerprint")
@@ -49,14 +89,35 @@ Share a secret with Alice using its second key:
             Arg::new("key")
                 .short('k')
                 .long("key")
-                .help("Path to public ssh key or index when using option -u")
+                .help("Path to public ssh key or certificate, or index when using option -u, can be repeated")
+                .action(ArgAction::Append)
                 .conflicts_with("fingerprint"),
         )
         .arg(
             Arg::new("user")
                 .short('u')
                 .long("user")
-                .help("GitHub username or URL, optional [-k N] where N is the key index"),
+                .help("GitHub username, <provider>:<user> (gitlab, codeberg, srht, lp, gitea@<host>) or URL, optional [-k N] where N is the key index, can be repeated")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("agent")
+                .long("agent")
+                .help("Wrap the password through ssh-agent for the local Ed25519 keys (-k) or the first key of the agent, the vault can be opened with only the agent")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("host")
+                .long("host")
+                .help("Use the IdentityFile configured in ~/.ssh/config for the host alias")
+                .value_name("ALIAS"),
+        )
+        .arg(
+            Arg::new("known-host")
+                .long("known-host")
+                .help("Encrypt to the host key of a server in known_hosts, only the server can open the vault, can be repeated")
+                .value_name("HOST")
+                .action(ArgAction::Append),
         )
         .arg(
             Arg::new("json")
@@ -65,6 +126,20 @@ Share a secret with Alice using its second key:
                 .help("When using option -u and user 'new', output the vault in JSON format")
                 .number_of_values(0),
         )
+        .arg(
+            Arg::new("new-key")
+                .long("new-key")
+                .help("With -u new, where the key comes from: online (ssh-keys.online or sshkeys_online) or generated locally with the private key inline in the JSON or uploaded to new_key_upload, defaults to new_key in config.yml or online")
+                .value_parser(["online", "inline", "upload"])
+                .value_name("MODE")
+                .conflicts_with("new-key-file"),
+        )
+        .arg(
+            Arg::new("new-key-file")
+                .long("new-key-file")
+                .help("With -u new, generate the key locally and write the private key to the file")
+                .value_name("FILE"),
+        )
         .arg(
             Arg::new("input")
                 .short('i')
@@ -72,6 +147,31 @@ Share a secret with Alice using its second key:
                 .help("Create a vault form an existing file")
                 .value_name("FILE"),
         )
+        .arg(
+            Arg::new("label")
+                .long("label")
+                .help("Label stored in the vault header, not encrypted but authenticated")
+                .value_name("LABEL"),
+        )
+        .arg(
+            Arg::new("content-type")
+                .long("content-type")
+                .help("MIME type of the secret stored in the vault header")
+                .value_name("TYPE"),
+        )
+        .arg(
+            Arg::new("sign-with")
+                .long("sign-with")
+                .help("Sign the vault with the private ssh key, the SSHSIG signature is appended to the vault")
+                .value_name("KEY"),
+        )
+        .arg(
+            Arg::new("passphrase")
+                .short('p')
+                .long("passphrase")
+                .env("SSH_VAULT_PASSPHRASE")
+                .help("Passphrase of the private ssh key used with --sign-with"),
+        )
         .arg(Arg::new("vault").help("file to store the vault or writes to stdout if not specified"))
 }
 
@@ -209,10 +309,149 @@ mod tests {
         assert!(matches.is_err());
     }
 
+    #[test]
+    fn test_subcommand_create_multiple_recipients() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_create());
+        let matches = app.try_get_matches_from(vec![
+            "ssh-vault",
+            "create",
+            "-u",
+            "alice",
+            "-u",
+            "bob",
+            "-k",
+            "id_rsa.pub",
+            "--key",
+            "id_ed25519.pub",
+        ])?;
+
+        let m = matches
+            .subcommand_matches("create")
+            .ok_or("No create subcommand")?;
+        assert_eq!(
+            m.get_many::<String>("user")
+                .ok_or("No user")?
+                .collect::<Vec<_>>(),
+            ["alice", "bob"]
+        );
+        assert_eq!(
+            m.get_many::<String>("key")
+                .ok_or("No key")?
+                .collect::<Vec<_>>(),
+            ["id_rsa.pub", "id_ed25519.pub"]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_subcommand_create_with_key() {
         let app = Command::new("ssh-vault").subcommand(subcommand_create());
         let matches = app.try_get_matches_from(vec!["ssh-vault", "create", "-u", "new", "-k", "0"]);
         assert!(matches.is_ok());
     }
+
+    #[test]
+    fn test_subcommand_create_with_host() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_create());
+        let matches = app.try_get_matches_from(vec!["ssh-vault", "create", "--host", "work"])?;
+
+        let m = matches
+            .subcommand_matches("create")
+            .ok_or("No create subcommand")?;
+        assert_eq!(m.get_one::<String>("host").ok_or("No host")?, "work");
+        Ok(())
+    }
+
+    #[test]
+    fn test_subcommand_create_with_known_host() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_create());
+        let matches = app.try_get_matches_from(vec![
+            "ssh-vault",
+            "create",
+            "--known-host",
+            "a.example.com",
+            "--known-host",
+            "[b.example.com]:2222",
+        ])?;
+
+        let m = matches
+            .subcommand_matches("create")
+            .ok_or("No create subcommand")?;
+        assert_eq!(
+            m.get_many::<String>("known-host")
+                .ok_or("No known host")?
+                .collect::<Vec<_>>(),
+            ["a.example.com", "[b.example.com]:2222"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_subcommand_create_with_new_key() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_create());
+        let matches = app.try_get_matches_from(vec![
+            "ssh-vault",
+            "create",
+            "-u",
+            "new",
+            "--new-key",
+            "inline",
+        ])?;
+
+        let m = matches
+            .subcommand_matches("create")
+            .ok_or("No create subcommand")?;
+        assert_eq!(
+            m.get_one::<String>("new-key").ok_or("No new key")?,
+            "inline"
+        );
+
+        let app = Command::new("ssh-vault").subcommand(subcommand_create());
+        assert!(
+            app.try_get_matches_from(vec!["ssh-vault", "create", "--new-key", "random"])
+                .is_err()
+        );
+
+        let app = Command::new("ssh-vault").subcommand(subcommand_create());
+        assert!(
+            app.try_get_matches_from(vec![
+                "ssh-vault",
+                "create",
+                "--new-key",
+                "inline",
+                "--new-key-file",
+                "secret.key"
+            ])
+            .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_subcommand_create_with_sign_with() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_create());
+        let matches = app.try_get_matches_from(vec![
+            "ssh-vault",
+            "create",
+            "-u",
+            "alice",
+            "--sign-with",
+            "/path/to/id_ed25519",
+            "-p",
+            "secret",
+        ])?;
+
+        let m = matches
+            .subcommand_matches("create")
+            .ok_or("No create subcommand")?;
+        assert_eq!(
+            m.get_one::<String>("sign-with").ok_or("No sign with")?,
+            "/path/to/id_ed25519"
+        );
+        assert_eq!(
+            m.get_one::<String>("passphrase").ok_or("No passphrase")?,
+            "secret"
+        );
+        Ok(())
+    }
 }
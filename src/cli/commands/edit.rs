@@ -18,6 +18,12 @@ Edit a secret:
                 .long("key")
                 .help("Path to the private ssh key to use for decyrpting"),
         )
+        .arg(
+            Arg::new("host")
+                .long("host")
+                .help("Try first the IdentityFile entries configured in ~/.ssh/config for the host alias")
+                .value_name("ALIAS"),
+        )
         .arg(
             Arg::new("passphrase")
                 .short('p')
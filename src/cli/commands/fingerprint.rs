@@ -25,7 +25,7 @@ pub fn subcommand_fingerprint() -> Command {
             Arg::new("user")
                 .short('u')
                 .long("user")
-                .help("GitHub username or URL, optional [-k N] where N is the key index")
+                .help("GitHub username, <provider>:<user> (gitlab, codeberg, srht, lp, gitea@<host>) or URL, optional [-k N] where N is the key index")
                 .value_parser(validator_user()),
         )
 }
@@ -1,10 +1,15 @@
+pub mod cache;
 pub mod create;
 pub mod edit;
 pub mod fingerprint;
+pub mod rekey;
+pub mod serve;
+pub mod sign;
+pub mod trust;
 pub mod view;
 
 use clap::{
-    ColorChoice, Command,
+    Arg, ArgAction, ColorChoice, Command,
     builder::styling::{AnsiColor, Effects, Styles},
 };
 
@@ -23,9 +28,23 @@ pub fn new() -> Command {
         .version(env!("CARGO_PKG_VERSION"))
         .color(ColorChoice::Auto)
         .styles(styles)
+        .arg(
+            Arg::new("offline")
+                .long("offline")
+                .help("Never access the network, only the cached keys are used")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .subcommand(cache::subcommand_cache())
         .subcommand(create::subcommand_create())
         .subcommand(edit::subcommand_edit())
         .subcommand(fingerprint::subcommand_fingerprint())
+        .subcommand(rekey::subcommand_rekey())
+        .subcommand(serve::subcommand_serve())
+        .subcommand(sign::subcommand_sign())
+        .subcommand(trust::subcommand_trust())
+        .subcommand(trust::subcommand_untrust())
+        .subcommand(sign::subcommand_verify())
         .subcommand(view::subcommand_view())
 }
 
@@ -45,4 +64,17 @@ mod tests {
             assert_eq!(version.to_string(), env!("CARGO_PKG_VERSION"));
         }
     }
+
+    #[test]
+    fn test_new_offline() -> Result<(), Box<dyn std::error::Error>> {
+        let matches = new().try_get_matches_from(vec!["ssh-vault", "view", "--offline"])?;
+        assert!(matches.get_flag("offline"));
+
+        let matches = new().try_get_matches_from(vec!["ssh-vault", "--offline", "view"])?;
+        assert!(matches.get_flag("offline"));
+
+        let matches = new().try_get_matches_from(vec!["ssh-vault", "view"])?;
+        assert!(!matches.get_flag("offline"));
+        Ok(())
+    }
 }
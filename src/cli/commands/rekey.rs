@@ -0,0 +1,117 @@
+use clap::{Arg, ArgAction, ArgGroup, Command};
+
+pub fn subcommand_rekey() -> Command {
+    Command::new("rekey")
+        .about("Re-encrypt existing vaults for different ssh keys")
+        .after_help(
+            r"Examples:
+
+Move a vault from id_rsa to id_ed25519:
+
+    ssh-vault rekey -k ~/.ssh/id_rsa --to-key ~/.ssh/id_ed25519.pub secret.vault
+
+Share all the vaults with Alice and Bob:
+
+    ssh-vault rekey --to-user alice --to-user bob *.vault
+
+The vaults are replaced atomically, the secret is never written to disk.
+",
+        )
+        .arg(
+            Arg::new("key")
+                .short('k')
+                .long("key")
+                .help("Path to the private ssh key to use for decyrpting"),
+        )
+        .arg(
+            Arg::new("passphrase")
+                .short('p')
+                .long("passphrase")
+                .env("SSH_VAULT_PASSPHRASE")
+                .help("Passphrase of the private ssh key"),
+        )
+        .arg(
+            Arg::new("to-key")
+                .long("to-key")
+                .help("Path to the new public ssh key, can be repeated")
+                .value_name("KEY")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("to-user")
+                .long("to-user")
+                .help("GitHub username, <provider>:<user> or URL of the new recipient, can be repeated")
+                .value_name("USER")
+                .action(ArgAction::Append),
+        )
+        .group(
+            ArgGroup::new("recipients")
+                .args(["to-key", "to-user"])
+                .required(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::new("vault")
+                .required(true)
+                .help("Path of the vaults to re-encrypt")
+                .num_args(1..),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subcommand_rekey() {
+        let app = Command::new("ssh-vault").subcommand(subcommand_rekey());
+        let matches = app.try_get_matches_from(vec!["ssh-vault", "rekey", "/tmp/vault"]);
+        assert!(matches.is_err());
+
+        let app = Command::new("ssh-vault").subcommand(subcommand_rekey());
+        let matches = app.try_get_matches_from(vec!["ssh-vault", "rekey", "--to-user", "alice"]);
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn test_subcommand_rekey_ok() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_rekey());
+        let matches = app.try_get_matches_from(vec![
+            "ssh-vault",
+            "rekey",
+            "-k",
+            "test",
+            "--to-key",
+            "new.pub",
+            "--to-user",
+            "alice",
+            "/tmp/a.vault",
+            "/tmp/b.vault",
+        ])?;
+
+        let m = matches
+            .subcommand_matches("rekey")
+            .ok_or("No rekey subcommand")?
+            .to_owned();
+        assert_eq!(m.get_one::<String>("key").ok_or("No key")?, "test");
+        assert_eq!(
+            m.get_many::<String>("to-key")
+                .ok_or("No to-key")?
+                .collect::<Vec<_>>(),
+            vec!["new.pub"]
+        );
+        assert_eq!(
+            m.get_many::<String>("to-user")
+                .ok_or("No to-user")?
+                .collect::<Vec<_>>(),
+            vec!["alice"]
+        );
+        assert_eq!(
+            m.get_many::<String>("vault")
+                .ok_or("No vault")?
+                .collect::<Vec<_>>(),
+            vec!["/tmp/a.vault", "/tmp/b.vault"]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use crate::tools::parse_duration;
+use clap::{Arg, Command, builder::ValueParser, value_parser};
+use std::time::Duration;
+
+pub fn validator_duration() -> ValueParser {
+    ValueParser::from(move |s: &str| -> std::result::Result<Duration, String> {
+        parse_duration(s).ok_or_else(|| "Invalid duration, use seconds, 30m, 12h or 1d".into())
+    })
+}
+
+pub fn subcommand_serve() -> Command {
+    Command::new("serve")
+        .about("Serve one-time keys for -u new, a self-hosted ssh-keys.online")
+        .after_help(
+            r"Examples:
+
+Serve the keys on port 8080 of localhost, keeping them in memory:
+
+    ssh-vault serve
+
+Keep the keys on disk, removing the ones not fetched within 10 minutes:
+
+    ssh-vault serve --bind 0.0.0.0:8080 --store /var/lib/ssh-vault --expire 10m
+
+To use it set sshkeys_online in ~/.config/ssh-vault/config.yml:
+
+    sshkeys_online: http://localhost:8080/new
+
+GET /new generates a key, GET /key/<id> returns its private key once and
+PUT /key/<id> stores a key uploaded with create --new-key upload.
+",
+        )
+        .arg(
+            Arg::new("bind")
+                .long("bind")
+                .help("Address to listen on")
+                .value_name("ADDR")
+                .default_value("127.0.0.1:8080"),
+        )
+        .arg(
+            Arg::new("store")
+                .long("store")
+                .help("Directory to keep the keys in, in memory if not specified")
+                .value_name("DIR"),
+        )
+        .arg(
+            Arg::new("expire")
+                .long("expire")
+                .help("Remove the keys not fetched after this time")
+                .value_name("DURATION")
+                .value_parser(validator_duration())
+                .default_value("1h"),
+        )
+        .arg(
+            Arg::new("max-keys")
+                .long("max-keys")
+                .help("Keys kept at once, new keys are refused until some are fetched or expire")
+                .value_name("N")
+                .value_parser(value_parser!(usize))
+                .default_value("1000"),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subcommand_serve() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_serve());
+        let matches = app.try_get_matches_from(vec!["ssh-vault", "serve", "--expire", "10m"])?;
+
+        let m = matches
+            .subcommand_matches("serve")
+            .ok_or("No serve subcommand")?;
+        assert_eq!(
+            m.get_one::<Duration>("expire").ok_or("No expire")?,
+            &Duration::from_mins(10)
+        );
+        assert_eq!(
+            m.get_one::<String>("bind").ok_or("No bind")?,
+            "127.0.0.1:8080"
+        );
+        assert_eq!(m.get_one::<usize>("max-keys").ok_or("No max-keys")?, &1000);
+
+        let app = Command::new("ssh-vault").subcommand(subcommand_serve());
+        assert!(
+            app.try_get_matches_from(vec!["ssh-vault", "serve", "--expire", "soon"])
+                .is_err()
+        );
+        Ok(())
+    }
+}
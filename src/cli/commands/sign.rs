@@ -0,0 +1,196 @@
+use crate::cli::commands::fingerprint::validator_user;
+use clap::{Arg, ArgAction, Command};
+
+pub fn subcommand_sign() -> Command {
+    Command::new("sign")
+        .about("Sign a file with an ssh key, compatible with ssh-keygen -Y sign")
+        .after_help(
+            r"Examples:
+
+Sign a release:
+
+    ssh-vault sign -n file -k ~/.ssh/id_ed25519 release.tar.gz > release.tar.gz.sig
+
+The signature can be checked with ssh-vault verify or ssh-keygen:
+
+    ssh-keygen -Y verify -f allowed_signers -I alice@example.com -n file -s release.tar.gz.sig < release.tar.gz
+",
+        )
+        .arg(
+            Arg::new("namespace")
+                .short('n')
+                .long("namespace")
+                .help("Namespace of the signature, for example file or git")
+                .required(true),
+        )
+        .arg(
+            Arg::new("key")
+                .short('k')
+                .long("key")
+                .help("Path to the private ssh key to sign with")
+                .required(true),
+        )
+        .arg(
+            Arg::new("passphrase")
+                .short('p')
+                .long("passphrase")
+                .env("SSH_VAULT_PASSPHRASE")
+                .help("Passphrase of the private ssh key"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Write the signature to file instead of stdout"),
+        )
+        .arg(Arg::new("input").help("file to sign or reads from stdin if not specified"))
+}
+
+pub fn subcommand_verify() -> Command {
+    Command::new("verify")
+        .about("Verify a signature made with ssh-vault sign or ssh-keygen -Y sign")
+        .after_help(
+            r"Examples:
+
+Verify a release signed by Alice, using her keys on GitHub:
+
+    ssh-vault verify -n file -s release.tar.gz.sig -u alice release.tar.gz
+
+Verify it with an allowed_signers file, like ssh-keygen -Y verify:
+
+    ssh-vault verify -n file -s release.tar.gz.sig --allowed-signers allowed_signers -I alice@example.com < release.tar.gz
+",
+        )
+        .arg(
+            Arg::new("namespace")
+                .short('n')
+                .long("namespace")
+                .help("Namespace of the signature, for example file or git")
+                .required(true),
+        )
+        .arg(
+            Arg::new("signature")
+                .short('s')
+                .long("signature")
+                .help("Path to the signature")
+                .required(true),
+        )
+        .arg(
+            Arg::new("key")
+                .short('k')
+                .long("key")
+                .help("Public ssh key trusted to sign, can be repeated")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("user")
+                .short('u')
+                .long("user")
+                .help("GitHub username, <provider>:<user> or URL whose keys are trusted to sign (pinned like the recipients), can be repeated")
+                .value_parser(validator_user())
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("allowed-signers")
+                .long("allowed-signers")
+                .help("allowed_signers file (see ssh-keygen) of the keys trusted to sign, defaults to allowed_signers in config.yml")
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("identity")
+                .short('I')
+                .long("identity")
+                .help("Identity the signer must have in the allowed_signers file")
+                .conflicts_with_all(["key", "user"]),
+        )
+        .arg(Arg::new("input").help("signed file or reads from stdin if not specified"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subcommand_sign() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_sign());
+        let matches = app.try_get_matches_from(vec![
+            "ssh-vault",
+            "sign",
+            "-n",
+            "file",
+            "-k",
+            "/path/to/id_ed25519",
+            "release.tar.gz",
+        ])?;
+
+        let m = matches
+            .subcommand_matches("sign")
+            .ok_or("No sign subcommand")?;
+        assert_eq!(
+            m.get_one::<String>("namespace").ok_or("No namespace")?,
+            "file"
+        );
+        assert_eq!(
+            m.get_one::<String>("key").ok_or("No key")?,
+            "/path/to/id_ed25519"
+        );
+        assert_eq!(
+            m.get_one::<String>("input").ok_or("No input")?,
+            "release.tar.gz"
+        );
+
+        let app = Command::new("ssh-vault").subcommand(subcommand_sign());
+        assert!(
+            app.try_get_matches_from(vec!["ssh-vault", "sign", "-k", "/path/to/id_ed25519"])
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_subcommand_verify() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_verify());
+        let matches = app.try_get_matches_from(vec![
+            "ssh-vault",
+            "verify",
+            "-n",
+            "file",
+            "-s",
+            "release.tar.gz.sig",
+            "-u",
+            "alice",
+            "-k",
+            "bob.pub",
+        ])?;
+
+        let m = matches
+            .subcommand_matches("verify")
+            .ok_or("No verify subcommand")?;
+        assert_eq!(
+            m.get_one::<String>("signature").ok_or("No signature")?,
+            "release.tar.gz.sig"
+        );
+        assert_eq!(m.get_one::<String>("user").ok_or("No user")?, "alice");
+        assert_eq!(m.get_one::<String>("key").ok_or("No key")?, "bob.pub");
+        assert_eq!(m.get_one::<String>("input"), None);
+
+        // -I is checked against the allowed_signers file
+        let app = Command::new("ssh-vault").subcommand(subcommand_verify());
+        assert!(
+            app.try_get_matches_from(vec![
+                "ssh-vault",
+                "verify",
+                "-n",
+                "file",
+                "-s",
+                "release.tar.gz.sig",
+                "-u",
+                "alice",
+                "-I",
+                "alice@example.com",
+            ])
+            .is_err()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use crate::cli::commands::fingerprint::validator_user;
+use clap::{Arg, Command};
+
+pub fn subcommand_trust() -> Command {
+    Command::new("trust")
+        .about("Pin the current keys of a user, without users list the pinned keys")
+        .after_help(
+            r"Examples:
+
+Accept the new keys of Alice:
+
+    ssh-vault trust alice
+
+List the pinned keys:
+
+    ssh-vault trust
+
+The keys of a user are pinned the first time they are used, creating a vault
+for a user whose keys changed fails until the new keys are trusted.
+",
+        )
+        .arg(
+            Arg::new("user")
+                .help("GitHub username, <provider>:<user> or URL")
+                .value_parser(validator_user())
+                .num_args(1..),
+        )
+}
+
+pub fn subcommand_untrust() -> Command {
+    Command::new("untrust")
+        .about("Remove the pinned keys of a user, they are pinned again on next use")
+        .arg(
+            Arg::new("user")
+                .required(true)
+                .help("GitHub username, <provider>:<user> or URL")
+                .num_args(1..),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subcommand_trust() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_trust());
+        let matches =
+            app.try_get_matches_from(vec!["ssh-vault", "trust", "alice", "gitlab:bob"])?;
+
+        let m = matches
+            .subcommand_matches("trust")
+            .ok_or("No trust subcommand")?
+            .to_owned();
+        assert_eq!(
+            m.get_many::<String>("user")
+                .ok_or("No user")?
+                .collect::<Vec<_>>(),
+            vec!["alice", "gitlab:bob"]
+        );
+
+        let app = Command::new("ssh-vault").subcommand(subcommand_trust());
+        assert!(app.try_get_matches_from(vec!["ssh-vault", "trust"]).is_ok());
+
+        let app = Command::new("ssh-vault").subcommand(subcommand_trust());
+        assert!(
+            app.try_get_matches_from(vec!["ssh-vault", "trust", "new"])
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_subcommand_untrust() {
+        let app = Command::new("ssh-vault").subcommand(subcommand_untrust());
+        assert!(
+            app.try_get_matches_from(vec!["ssh-vault", "untrust"])
+                .is_err()
+        );
+
+        let app = Command::new("ssh-vault").subcommand(subcommand_untrust());
+        assert!(
+            app.try_get_matches_from(vec!["ssh-vault", "untrust", "alice"])
+                .is_ok()
+        );
+    }
+}
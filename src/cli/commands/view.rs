@@ -1,4 +1,4 @@
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, Command};
 
 pub fn subcommand_view() -> Command {
     Command::new("view")
@@ -9,6 +9,19 @@ pub fn subcommand_view() -> Command {
 View a secret:
 
     ssh-vault view < /path/to/secret.vault
+
+View a binary secret:
+
+    ssh-vault view -o image.png /path/to/image.vault
+
+View a shared vault, the output of create --json and -u new is accepted:
+
+    ssh-vault view https://example.com/secret.vault
+    echo secret | ssh-vault create -u new | ssh-vault view
+
+Only open vaults signed by Alice:
+
+    ssh-vault view --signer-user alice --require-signer < secret.vault
 ",
         )
         .visible_alias("v")
@@ -24,6 +37,18 @@ View a secret:
                 .long("output")
                 .help("Write output to file instead of stdout"),
         )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .help("Write binary data to the terminal")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("host")
+                .long("host")
+                .help("Try first the IdentityFile entries configured in ~/.ssh/config for the host alias")
+                .value_name("ALIAS"),
+        )
         .arg(
             Arg::new("passphrase")
                 .short('p')
@@ -31,9 +56,35 @@ View a secret:
                 .env("SSH_VAULT_PASSPHRASE")
                 .help("Passphrase of the private ssh key"),
         )
+        .arg(
+            Arg::new("signer-key")
+                .long("signer-key")
+                .help("Public ssh key trusted to sign the vault, can be repeated")
+                .value_name("FILE")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("signer-user")
+                .long("signer-user")
+                .help("User whose keys are trusted to sign the vault (pinned like the recipients), can be repeated")
+                .value_name("USER")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("allowed-signers")
+                .long("allowed-signers")
+                .help("allowed_signers file (see ssh-keygen) of the keys trusted to sign the vault, defaults to allowed_signers in config.yml")
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("require-signer")
+                .long("require-signer")
+                .help("Refuse vaults not signed by a trusted signer, defaults to require_signer in config.yml")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("vault")
-                .help("file to read the vault from or reads from stdin if not specified"),
+                .help("file or URL to read the vault from or reads from stdin if not specified"),
         )
 }
 
@@ -91,6 +142,64 @@ mod tests {
         assert_eq!(m.get_one::<String>("vault"), None);
         assert_eq!(m.get_one::<String>("passphrase"), None);
         assert_eq!(m.get_one::<String>("output"), None);
+        assert!(!m.get_flag("force"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_subcommand_view_force() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_view());
+
+        let matches = app.try_get_matches_from(vec!["ssh-vault", "view", "--force"])?;
+
+        let m = matches
+            .subcommand_matches("view")
+            .ok_or("No view subcommand")?
+            .to_owned();
+
+        assert!(m.get_flag("force"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_subcommand_view_signers() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_view());
+
+        let matches = app.try_get_matches_from(vec![
+            "ssh-vault",
+            "view",
+            "--signer-key",
+            "alice.pub",
+            "--signer-key",
+            "bob.pub",
+            "--signer-user",
+            "carol",
+            "--allowed-signers",
+            "allowed_signers",
+            "--require-signer",
+        ])?;
+
+        let m = matches
+            .subcommand_matches("view")
+            .ok_or("No view subcommand")?
+            .to_owned();
+
+        assert_eq!(
+            m.get_many::<String>("signer-key")
+                .ok_or("No signer key")?
+                .collect::<Vec<_>>(),
+            vec!["alice.pub", "bob.pub"]
+        );
+        assert_eq!(
+            m.get_one::<String>("signer-user").ok_or("No signer user")?,
+            "carol"
+        );
+        assert_eq!(
+            m.get_one::<String>("allowed-signers")
+                .ok_or("No allowed signers")?,
+            "allowed_signers"
+        );
+        assert!(m.get_flag("require-signer"));
         Ok(())
     }
 
@@ -185,4 +294,20 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_subcommand_view_host() -> Result<(), Box<dyn std::error::Error>> {
+        let app = Command::new("ssh-vault").subcommand(subcommand_view());
+
+        let matches = app.try_get_matches_from(vec!["ssh-vault", "view", "--host", "work"])?;
+
+        let m = matches
+            .subcommand_matches("view")
+            .ok_or("No view subcommand")?
+            .to_owned();
+
+        assert_eq!(m.get_one::<String>("host").ok_or("No host")?, "work");
+        assert_eq!(m.get_one::<String>("key"), None);
+        Ok(())
+    }
 }
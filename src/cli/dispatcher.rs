@@ -2,6 +2,7 @@ use crate::cli::actions::Action;
 
 use anyhow::{Context, Result};
 use secrecy::SecretString;
+use std::time::Duration;
 
 pub fn dispatch(matches: &clap::ArgMatches) -> Result<Action> {
     // Closure to return subcommand matches
@@ -12,6 +13,21 @@ pub fn dispatch(matches: &clap::ArgMatches) -> Result<Action> {
     };
 
     match matches.subcommand_name() {
+        Some("cache") => {
+            let (command, sub_m) = sub_m("cache")?
+                .subcommand()
+                .context("cache command not found")?;
+            Ok(Action::Cache {
+                command: command.to_string(),
+                // list has no entries
+                entries: sub_m
+                    .try_get_many::<String>("entry")
+                    .ok()
+                    .flatten()
+                    .map(|entries| entries.cloned().collect())
+                    .unwrap_or_default(),
+            })
+        }
         Some("fingerprint") => {
             let sub_m = sub_m("fingerprint")?;
             Ok(Action::Fingerprint {
@@ -19,52 +35,151 @@ pub fn dispatch(matches: &clap::ArgMatches) -> Result<Action> {
                 user: sub_m.get_one::<String>("user").cloned(),
             })
         }
-        Some("create") => {
-            let sub_m = sub_m("create")?;
-            Ok(Action::Create {
-                fingerprint: sub_m.get_one::<String>("fingerprint").cloned(),
-                input: sub_m.get_one::<String>("input").cloned(),
-                json: sub_m.get_one("json").copied().unwrap_or(false),
-                key: sub_m.get_one::<String>("key").cloned(),
-                user: sub_m.get_one::<String>("user").cloned(),
-                vault: sub_m.get_one::<String>("vault").cloned(),
-            })
-        }
-        Some("view") => {
-            let sub_m = sub_m("view")?;
-            Ok(Action::View {
-                key: sub_m.get_one::<String>("key").cloned(),
-                vault: sub_m.get_one::<String>("vault").cloned(),
-                output: sub_m.get_one::<String>("output").cloned(),
-                passphrase: sub_m
-                    .get_one("passphrase")
-                    .map(|s: &String| SecretString::from(s.clone())),
-            })
-        }
+        Some("create") => Ok(create(sub_m("create")?)),
+        Some("view") => Ok(view(sub_m("view")?)),
         Some("edit") => {
             let sub_m = sub_m("edit")?;
             Ok(Action::Edit {
+                host: sub_m.get_one::<String>("host").cloned(),
                 key: sub_m.get_one::<String>("key").cloned(),
-                passphrase: sub_m
-                    .get_one("passphrase")
-                    .map(|s: &String| SecretString::from(s.clone())),
+                passphrase: passphrase(sub_m),
                 vault: sub_m
                     .get_one::<String>("vault")
                     .cloned()
                     .ok_or_else(|| anyhow::anyhow!("Vault path required"))?,
             })
         }
+        Some("rekey") => {
+            let sub_m = sub_m("rekey")?;
+            Ok(Action::Rekey {
+                key: sub_m.get_one::<String>("key").cloned(),
+                passphrase: passphrase(sub_m),
+                to_keys: values(sub_m, "to-key"),
+                to_users: values(sub_m, "to-user"),
+                vaults: values(sub_m, "vault"),
+            })
+        }
+        Some("serve") => {
+            let sub_m = sub_m("serve")?;
+            Ok(Action::Serve {
+                bind: sub_m
+                    .get_one::<String>("bind")
+                    .cloned()
+                    .context("bind address not found")?,
+                expire: sub_m
+                    .get_one::<Duration>("expire")
+                    .copied()
+                    .context("expire not found")?,
+                store: sub_m.get_one::<String>("store").cloned(),
+                max_keys: sub_m
+                    .get_one::<usize>("max-keys")
+                    .copied()
+                    .context("max-keys not found")?,
+            })
+        }
+        Some("sign") => sign(sub_m("sign")?),
+        Some("verify") => verify(sub_m("verify")?),
+        Some("trust") => Ok(Action::Trust {
+            users: values(sub_m("trust")?, "user"),
+        }),
+        Some("untrust") => Ok(Action::Untrust {
+            users: values(sub_m("untrust")?, "user"),
+        }),
         _ => Ok(Action::Help),
     }
 }
 
+fn create(sub_m: &clap::ArgMatches) -> Action {
+    Action::Create {
+        agent: sub_m.get_flag("agent"),
+        content_type: sub_m.get_one::<String>("content-type").cloned(),
+        fingerprint: sub_m.get_one::<String>("fingerprint").cloned(),
+        host: sub_m.get_one::<String>("host").cloned(),
+        input: sub_m.get_one::<String>("input").cloned(),
+        json: sub_m.get_one("json").copied().unwrap_or(false),
+        keys: values(sub_m, "key"),
+        known_hosts: values(sub_m, "known-host"),
+        label: sub_m.get_one::<String>("label").cloned(),
+        new_key: sub_m.get_one::<String>("new-key").cloned(),
+        new_key_file: sub_m.get_one::<String>("new-key-file").cloned(),
+        passphrase: passphrase(sub_m),
+        sign_with: sub_m.get_one::<String>("sign-with").cloned(),
+        users: values(sub_m, "user"),
+        vault: sub_m.get_one::<String>("vault").cloned(),
+    }
+}
+
+fn view(sub_m: &clap::ArgMatches) -> Action {
+    Action::View {
+        allowed_signers: sub_m.get_one::<String>("allowed-signers").cloned(),
+        force: sub_m.get_flag("force"),
+        host: sub_m.get_one::<String>("host").cloned(),
+        key: sub_m.get_one::<String>("key").cloned(),
+        vault: sub_m.get_one::<String>("vault").cloned(),
+        output: sub_m.get_one::<String>("output").cloned(),
+        passphrase: passphrase(sub_m),
+        require_signer: sub_m.get_flag("require-signer"),
+        signer_keys: values(sub_m, "signer-key"),
+        signer_users: values(sub_m, "signer-user"),
+    }
+}
+
+fn sign(sub_m: &clap::ArgMatches) -> Result<Action> {
+    Ok(Action::Sign {
+        input: sub_m.get_one::<String>("input").cloned(),
+        key: sub_m
+            .get_one::<String>("key")
+            .cloned()
+            .context("key not found")?,
+        namespace: sub_m
+            .get_one::<String>("namespace")
+            .cloned()
+            .context("namespace not found")?,
+        output: sub_m.get_one::<String>("output").cloned(),
+        passphrase: passphrase(sub_m),
+    })
+}
+
+fn verify(sub_m: &clap::ArgMatches) -> Result<Action> {
+    Ok(Action::Verify {
+        allowed_signers: sub_m.get_one::<String>("allowed-signers").cloned(),
+        identity: sub_m.get_one::<String>("identity").cloned(),
+        input: sub_m.get_one::<String>("input").cloned(),
+        keys: values(sub_m, "key"),
+        namespace: sub_m
+            .get_one::<String>("namespace")
+            .cloned()
+            .context("namespace not found")?,
+        signature: sub_m
+            .get_one::<String>("signature")
+            .cloned()
+            .context("signature not found")?,
+        users: values(sub_m, "user"),
+    })
+}
+
+// the passphrase of the private key, from the argument or the environment
+fn passphrase(matches: &clap::ArgMatches) -> Option<SecretString> {
+    matches
+        .get_one::<String>("passphrase")
+        .map(|s| SecretString::from(s.clone()))
+}
+
+// the values of an argument that can be repeated
+fn values(matches: &clap::ArgMatches, id: &str) -> Vec<String> {
+    matches
+        .get_many::<String>(id)
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
     use crate::cli::{
         actions::Action,
-        commands::{create, edit, fingerprint, view},
+        commands::{cache, create, edit, fingerprint, rekey, serve, sign, trust, view},
     };
     use clap::Command;
     use secrecy::ExposeSecret;
@@ -150,18 +265,36 @@ mod tests {
         let action = dispatch(&matches).unwrap();
         match action {
             Action::Create {
+                agent,
+                content_type,
                 fingerprint,
+                host,
                 input,
                 json,
-                key,
-                user,
+                keys,
+                known_hosts,
+                label,
+                new_key,
+                new_key_file,
+                passphrase,
+                sign_with,
+                users,
                 vault,
             } => {
+                assert!(!agent);
+                assert_eq!(content_type, None);
+                assert_eq!(label, None);
+                assert_eq!(new_key, None);
+                assert_eq!(new_key_file, None);
+                assert!(passphrase.is_none());
+                assert_eq!(sign_with, None);
                 assert_eq!(fingerprint, None);
+                assert_eq!(host, None);
                 assert_eq!(input, None);
                 assert!(!json);
-                assert_eq!(key, None);
-                assert_eq!(user, None);
+                assert!(keys.is_empty());
+                assert!(known_hosts.is_empty());
+                assert!(users.is_empty());
                 assert_eq!(vault, None);
             }
             _ => unreachable!("Wrong action"),
@@ -177,24 +310,94 @@ mod tests {
         let action = dispatch(&matches).unwrap();
         match action {
             Action::Create {
+                agent,
+                content_type,
                 fingerprint,
+                host,
                 input,
                 json,
-                key,
-                user,
+                keys,
+                known_hosts,
+                label,
+                new_key,
+                new_key_file,
+                passphrase,
+                sign_with,
+                users,
                 vault,
             } => {
+                assert!(!agent);
+                assert_eq!(content_type, None);
+                assert_eq!(label, None);
+                assert_eq!(new_key, None);
+                assert_eq!(new_key_file, None);
+                assert!(passphrase.is_none());
+                assert_eq!(sign_with, None);
                 assert_eq!(fingerprint, None);
+                assert_eq!(host, None);
                 assert_eq!(input, None);
                 assert!(json);
-                assert_eq!(key, None);
-                assert_eq!(user, None);
+                assert!(keys.is_empty());
+                assert!(known_hosts.is_empty());
+                assert!(users.is_empty());
                 assert_eq!(vault, None);
             }
             _ => unreachable!("Wrong action"),
         }
     }
 
+    #[test]
+    fn test_dispatch_create_with_recipients() {
+        let cmd = Command::new("test").subcommand(create::subcommand_create());
+        let matches = cmd.try_get_matches_from(vec![
+            "test",
+            "create",
+            "-u",
+            "alice",
+            "-u",
+            "bob",
+            "-k",
+            "test_data/id_rsa.pub",
+        ]);
+        assert!(matches.is_ok());
+        let matches = matches.unwrap();
+        let action = dispatch(&matches).unwrap();
+        match action {
+            Action::Create { keys, users, .. } => {
+                assert_eq!(keys, vec!["test_data/id_rsa.pub".to_string()]);
+                assert_eq!(users, vec!["alice".to_string(), "bob".to_string()]);
+            }
+            _ => unreachable!("Wrong action"),
+        }
+    }
+
+    #[test]
+    fn test_dispatch_create_with_metadata() {
+        let cmd = Command::new("test").subcommand(create::subcommand_create());
+        let matches = cmd.try_get_matches_from(vec![
+            "test",
+            "create",
+            "--label",
+            "db password",
+            "--content-type",
+            "text/plain",
+        ]);
+        assert!(matches.is_ok());
+        let matches = matches.unwrap();
+        let action = dispatch(&matches).unwrap();
+        match action {
+            Action::Create {
+                content_type,
+                label,
+                ..
+            } => {
+                assert_eq!(content_type, Some("text/plain".to_string()));
+                assert_eq!(label, Some("db password".to_string()));
+            }
+            _ => unreachable!("Wrong action"),
+        }
+    }
+
     #[test]
     fn test_dispatch_edit() {
         let cmd = Command::new("test").subcommand(edit::subcommand_edit());
@@ -205,10 +408,12 @@ mod tests {
         let action = dispatch(&matches).unwrap();
         match action {
             Action::Edit {
+                host,
                 key,
                 passphrase,
                 vault,
             } => {
+                assert_eq!(host, None);
                 assert_eq!(key, None);
                 assert_eq!("secret", passphrase.unwrap().expose_secret());
                 assert_eq!(vault, String::from("test_data/id_rsa"));
@@ -224,6 +429,182 @@ mod tests {
         assert!(matches.is_err());
     }
 
+    #[test]
+    fn test_dispatch_rekey() {
+        let cmd = Command::new("test").subcommand(rekey::subcommand_rekey());
+        let matches = cmd.try_get_matches_from(vec![
+            "test",
+            "rekey",
+            "--to-key",
+            "test_data/ed25519.pub",
+            "a.vault",
+            "b.vault",
+        ]);
+        assert!(matches.is_ok());
+        let matches = matches.unwrap();
+        let action = dispatch(&matches).unwrap();
+        match action {
+            Action::Rekey {
+                key,
+                passphrase,
+                to_keys,
+                to_users,
+                vaults,
+            } => {
+                assert_eq!(key, None);
+                assert!(passphrase.is_none());
+                assert_eq!(to_keys, vec!["test_data/ed25519.pub".to_string()]);
+                assert!(to_users.is_empty());
+                assert_eq!(vaults, vec!["a.vault".to_string(), "b.vault".to_string()]);
+            }
+            _ => unreachable!("Wrong action"),
+        }
+    }
+
+    #[test]
+    fn test_dispatch_cache() {
+        let cmd = Command::new("test").subcommand(cache::subcommand_cache());
+        let matches = cmd
+            .clone()
+            .try_get_matches_from(vec!["test", "cache", "show", "alice"])
+            .unwrap();
+        match dispatch(&matches).unwrap() {
+            Action::Cache { command, entries } => {
+                assert_eq!(command, "show");
+                assert_eq!(entries, vec!["alice".to_string()]);
+            }
+            _ => unreachable!("Wrong action"),
+        }
+
+        let matches = cmd
+            .try_get_matches_from(vec!["test", "cache", "list"])
+            .unwrap();
+        match dispatch(&matches).unwrap() {
+            Action::Cache { command, entries } => {
+                assert_eq!(command, "list");
+                assert!(entries.is_empty());
+            }
+            _ => unreachable!("Wrong action"),
+        }
+    }
+
+    #[test]
+    fn test_dispatch_serve() {
+        let cmd = Command::new("test").subcommand(serve::subcommand_serve());
+        let matches = cmd
+            .try_get_matches_from(vec![
+                "test",
+                "serve",
+                "--store",
+                "/tmp/keys",
+                "--max-keys",
+                "10",
+            ])
+            .unwrap();
+        match dispatch(&matches).unwrap() {
+            Action::Serve {
+                bind,
+                expire,
+                store,
+                max_keys,
+            } => {
+                assert_eq!(bind, "127.0.0.1:8080");
+                assert_eq!(expire, Duration::from_hours(1));
+                assert_eq!(store, Some("/tmp/keys".to_string()));
+                assert_eq!(max_keys, 10);
+            }
+            _ => unreachable!("Wrong action"),
+        }
+    }
+
+    #[test]
+    fn test_dispatch_sign_verify() {
+        let cmd = Command::new("test")
+            .subcommand(sign::subcommand_sign())
+            .subcommand(sign::subcommand_verify());
+        let matches = cmd
+            .clone()
+            .try_get_matches_from(vec!["test", "sign", "-n", "file", "-k", "id_ed25519"])
+            .unwrap();
+        match dispatch(&matches).unwrap() {
+            Action::Sign {
+                input,
+                key,
+                namespace,
+                output,
+                passphrase,
+            } => {
+                assert_eq!(input, None);
+                assert_eq!(key, "id_ed25519");
+                assert_eq!(namespace, "file");
+                assert_eq!(output, None);
+                assert!(passphrase.is_none());
+            }
+            _ => unreachable!("Wrong action"),
+        }
+
+        let matches = cmd
+            .try_get_matches_from(vec![
+                "test",
+                "verify",
+                "-n",
+                "file",
+                "-s",
+                "file.sig",
+                "--allowed-signers",
+                "allowed_signers",
+                "-I",
+                "alice@example.com",
+                "file",
+            ])
+            .unwrap();
+        match dispatch(&matches).unwrap() {
+            Action::Verify {
+                allowed_signers,
+                identity,
+                input,
+                keys,
+                namespace,
+                signature,
+                users,
+            } => {
+                assert_eq!(allowed_signers, Some("allowed_signers".to_string()));
+                assert_eq!(identity, Some("alice@example.com".to_string()));
+                assert_eq!(input, Some("file".to_string()));
+                assert!(keys.is_empty());
+                assert_eq!(namespace, "file");
+                assert_eq!(signature, "file.sig");
+                assert!(users.is_empty());
+            }
+            _ => unreachable!("Wrong action"),
+        }
+    }
+
+    #[test]
+    fn test_dispatch_trust() {
+        let cmd = Command::new("test")
+            .subcommand(trust::subcommand_trust())
+            .subcommand(trust::subcommand_untrust());
+        let matches = cmd
+            .clone()
+            .try_get_matches_from(vec!["test", "trust", "alice"])
+            .unwrap();
+        match dispatch(&matches).unwrap() {
+            Action::Trust { users } => assert_eq!(users, vec!["alice".to_string()]),
+            _ => unreachable!("Wrong action"),
+        }
+
+        let matches = cmd
+            .try_get_matches_from(vec!["test", "untrust", "alice", "bob"])
+            .unwrap();
+        match dispatch(&matches).unwrap() {
+            Action::Untrust { users } => {
+                assert_eq!(users, vec!["alice".to_string(), "bob".to_string()]);
+            }
+            _ => unreachable!("Wrong action"),
+        }
+    }
+
     #[test]
     fn test_dispatch_view() {
         let cmd = Command::new("test").subcommand(view::subcommand_view());
@@ -233,11 +614,23 @@ mod tests {
         let action = dispatch(&matches).unwrap();
         match action {
             Action::View {
+                allowed_signers,
+                force,
+                host,
                 key,
                 vault,
                 output,
                 passphrase,
+                require_signer,
+                signer_keys,
+                signer_users,
             } => {
+                assert_eq!(allowed_signers, None);
+                assert!(!require_signer);
+                assert!(signer_keys.is_empty());
+                assert!(signer_users.is_empty());
+                assert!(!force);
+                assert_eq!(host, None);
                 assert_eq!(key, None);
                 assert_eq!(vault, None);
                 assert_eq!(output, None);
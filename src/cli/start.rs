@@ -1,4 +1,7 @@
-use crate::cli::{actions::Action, commands, dispatcher};
+use crate::{
+    cli::{actions::Action, commands, dispatcher},
+    vault::remote,
+};
 use anyhow::Result;
 
 /// Start the CLI
@@ -9,6 +12,11 @@ use anyhow::Result;
 pub fn start() -> Result<Action> {
     let cmd = commands::new();
     let matches = cmd.get_matches();
+
+    if matches.get_flag("offline") {
+        remote::set_offline();
+    }
+
     let action = dispatcher::dispatch(&matches)?;
     Ok(action)
 }
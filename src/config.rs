@@ -1,6 +1,7 @@
 use crate::tools;
 use anyhow::Result;
 use config::Config;
+use std::{env, path::PathBuf};
 
 /// Load configuration from environment and config file.
 ///
@@ -23,6 +24,26 @@ pub fn get() -> Result<Config> {
     }
 }
 
+/// Read a list of paths, a list in `config.yml` or a path list in the
+/// environment (`SSH_VAULT_<KEY>`), `~` is expanded.
+///
+/// # Errors
+///
+/// Returns an error if the home directory can't be found.
+pub fn get_paths(config: &Config, key: &str) -> Result<Vec<PathBuf>> {
+    let paths: Vec<String> = if let Ok(paths) = config.get::<Vec<String>>(key) {
+        paths
+    } else if let Ok(paths) = config.get_string(key) {
+        env::split_paths(&paths)
+            .map(|path| path.display().to_string())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    paths.iter().map(|path| tools::expand_home(path)).collect()
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
@@ -35,4 +56,24 @@ mod tests {
             assert_eq!(config.get_string("sshkeys_online").unwrap(), "localhost");
         });
     }
+
+    #[test]
+    fn test_config_get_paths() {
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_TEST_PATHS", Some("~/a:/b".as_ref())),
+            ],
+            || {
+                let config = get().unwrap();
+                assert_eq!(
+                    get_paths(&config, "test_paths").unwrap(),
+                    vec![home.path().join("a"), PathBuf::from("/b")]
+                );
+                assert!(get_paths(&config, "missing").unwrap().is_empty());
+            },
+        );
+    }
 }
@@ -1,5 +1,5 @@
 use anyhow::{Result, anyhow};
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 /// Return the user's home directory.
 ///
@@ -10,6 +10,21 @@ pub fn get_home() -> Result<PathBuf> {
     home::home_dir().map_or_else(|| Err(anyhow!("Could not find home directory")), Ok)
 }
 
+/// Expand a leading `~` to the user's home directory.
+///
+/// # Errors
+///
+/// Returns an error if the home directory cannot be determined.
+pub fn expand_home(path: &str) -> Result<PathBuf> {
+    match path.strip_prefix('~') {
+        Some("") => get_home(),
+        Some(rest) if rest.starts_with('/') || rest.starts_with(std::path::MAIN_SEPARATOR) => {
+            Ok(get_home()?.join(rest.trim_start_matches(['/', std::path::MAIN_SEPARATOR])))
+        }
+        _ => Ok(PathBuf::from(path)),
+    }
+}
+
 /// Filter fetched text to only include supported SSH public keys.
 ///
 /// # Errors
@@ -19,19 +34,49 @@ pub fn filter_fetched_keys(response: &str) -> Result<String> {
     let mut filtered_keys = String::new();
 
     for line in response.lines() {
-        if line.starts_with("ssh-rsa") || line.starts_with("ssh-ed25519") {
+        if line.starts_with("ssh-rsa")
+            || line.starts_with("ssh-ed25519")
+            || line.starts_with("ecdsa-sha2-nistp")
+        {
             filtered_keys.push_str(line);
             filtered_keys.push('\n'); // Add a newline to separate the lines
         }
     }
 
     if filtered_keys.is_empty() {
-        Err(anyhow!("No SSH keys (ssh-rsa or ssh-ed25519) found"))
+        Err(anyhow!(
+            "No SSH keys (ssh-rsa, ssh-ed25519 or ecdsa-sha2-nistp*) found"
+        ))
     } else {
         Ok(filtered_keys)
     }
 }
 
+/// Parse a duration in seconds or with a unit: `90`, `90s`, `30m`, `12h` or
+/// `30d`. `None` if the duration is invalid.
+#[must_use]
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+
+    let (number, unit) = duration.split_at(
+        duration
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(duration.len()),
+    );
+
+    let number: u64 = number.parse().ok()?;
+
+    let seconds = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+
+    Some(Duration::from_secs(number.saturating_mul(seconds)))
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
@@ -43,4 +88,41 @@ mod tests {
         assert!(home.is_dir());
         Ok(())
     }
+
+    #[test]
+    fn test_expand_home() {
+        let home = get_home().unwrap();
+        assert_eq!(expand_home("~").unwrap(), home);
+        assert_eq!(expand_home("~/.ssh").unwrap(), home.join(".ssh"));
+        assert_eq!(expand_home("/tmp/~").unwrap(), PathBuf::from("/tmp/~"));
+        assert_eq!(expand_home("~user").unwrap(), PathBuf::from("~user"));
+    }
+
+    #[test]
+    fn test_filter_fetched_keys() {
+        let response = "<html>\n\
+ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIKdb5/i8sIEZ84k+LpJCAxRwxUZsP2MHFWApeB2TSUux\n\
+ssh-dss AAAAB3NzaC1kc3MAAACBAP\n\
+ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTY=\n";
+
+        let keys = filter_fetched_keys(response).unwrap();
+        assert_eq!(keys.lines().count(), 2);
+        assert!(
+            keys.lines()
+                .any(|line| line.starts_with("ecdsa-sha2-nistp256"))
+        );
+
+        assert!(filter_fetched_keys("ssh-dss AAAAB3NzaC1kc3MAAACBAP").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Some(Duration::from_mins(30)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_hours(1)));
+        assert_eq!(parse_duration("2d"), Some(Duration::from_hours(48)));
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_duration("3w"), None);
+    }
 }
@@ -0,0 +1,334 @@
+use anyhow::{Result, anyhow};
+use ssh_key::{PublicKey, Signature};
+use std::{
+    env,
+    io::{Read, Write},
+    path::Path,
+};
+
+// ssh-agent protocol messages
+// <https://datatracker.ietf.org/doc/html/draft-miller-ssh-agent>
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+// replies bigger than this are rejected
+const MAX_MESSAGE_SIZE: usize = 256 * 1024;
+
+trait Stream: Read + Write {}
+impl<T: Read + Write> Stream for T {}
+
+/// Minimal ssh-agent client, only listing keys and signing are supported
+pub struct Agent {
+    stream: Box<dyn Stream>,
+}
+
+impl Agent {
+    /// Connect to the agent listening on `SSH_AUTH_SOCK`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `SSH_AUTH_SOCK` is not set or the socket can't be
+    /// opened.
+    pub fn connect() -> Result<Self> {
+        let socket = env::var_os("SSH_AUTH_SOCK")
+            .filter(|socket| !socket.is_empty())
+            .ok_or_else(|| anyhow!("SSH_AUTH_SOCK is not set, is ssh-agent running?"))?;
+
+        Self::connect_to(Path::new(&socket))
+    }
+
+    /// Connect to the agent listening on the given socket.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket can't be opened.
+    #[cfg(unix)]
+    pub fn connect_to(socket: &Path) -> Result<Self> {
+        let stream = std::os::unix::net::UnixStream::connect(socket).map_err(|err| {
+            anyhow!(
+                "Could not connect to ssh-agent at {}: {err}",
+                socket.display()
+            )
+        })?;
+
+        Ok(Self {
+            stream: Box::new(stream),
+        })
+    }
+
+    /// Connect to the agent listening on the given socket.
+    ///
+    /// # Errors
+    ///
+    /// Always returns an error, only Unix sockets are supported.
+    #[cfg(not(unix))]
+    pub fn connect_to(socket: &Path) -> Result<Self> {
+        Err(anyhow!(
+            "Could not connect to ssh-agent at {}: only Unix sockets are supported",
+            socket.display()
+        ))
+    }
+
+    /// List the public keys held by the agent, keys that can't be parsed are
+    /// skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the agent fails or the reply is malformed.
+    pub fn identities(&mut self) -> Result<Vec<PublicKey>> {
+        let reply = self.request(&[SSH_AGENTC_REQUEST_IDENTITIES])?;
+
+        let mut reply = reply_body(&reply, SSH_AGENT_IDENTITIES_ANSWER)?;
+
+        let count = read_u32(&mut reply)?;
+
+        let mut keys = Vec::new();
+
+        for _ in 0..count {
+            let blob = read_string(&mut reply)?;
+            let comment = read_string(&mut reply)?;
+
+            if let Ok(mut key) = PublicKey::from_bytes(blob) {
+                key.set_comment(String::from_utf8_lossy(comment));
+                keys.push(key);
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Sign the data with the private key matching the public key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the agent doesn't hold the key, refuses to sign or
+    /// the reply is malformed.
+    pub fn sign(&mut self, key: &PublicKey, data: &[u8]) -> Result<Signature> {
+        let mut message = vec![SSH_AGENTC_SIGN_REQUEST];
+        write_string(&mut message, &key.to_bytes()?)?;
+        write_string(&mut message, data)?;
+        // flags
+        message.extend_from_slice(&0_u32.to_be_bytes());
+
+        let reply = self.request(&message)?;
+
+        let mut reply = reply_body(&reply, SSH_AGENT_SIGN_RESPONSE)?;
+
+        Ok(Signature::try_from(read_string(&mut reply)?)?)
+    }
+
+    // send a message and read the reply
+    fn request(&mut self, message: &[u8]) -> Result<Vec<u8>> {
+        let mut request = Vec::with_capacity(message.len() + 4);
+        write_string(&mut request, message)?;
+
+        self.stream.write_all(&request)?;
+        self.stream.flush()?;
+
+        let mut len = [0_u8; 4];
+        self.stream.read_exact(&mut len)?;
+
+        let len = usize::try_from(u32::from_be_bytes(len))?;
+        if len > MAX_MESSAGE_SIZE {
+            return Err(anyhow!("Invalid ssh-agent reply: message too long"));
+        }
+
+        let mut reply = vec![0_u8; len];
+        self.stream.read_exact(&mut reply)?;
+
+        Ok(reply)
+    }
+}
+
+// check the type of the reply and return the rest of the message
+fn reply_body(reply: &[u8], expected: u8) -> Result<&[u8]> {
+    match reply.split_first() {
+        Some((kind, body)) if *kind == expected => Ok(body),
+        Some((&SSH_AGENT_FAILURE, _)) => Err(anyhow!("ssh-agent refused the request")),
+        _ => Err(anyhow!("Invalid ssh-agent reply")),
+    }
+}
+
+fn read_u32(data: &mut &[u8]) -> Result<u32> {
+    let (value, rest) = data
+        .split_first_chunk::<4>()
+        .ok_or_else(|| anyhow!("Invalid ssh-agent reply: message too short"))?;
+
+    *data = rest;
+
+    Ok(u32::from_be_bytes(*value))
+}
+
+fn read_string<'a>(data: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = usize::try_from(read_u32(data)?)?;
+
+    let (value, rest) = data
+        .split_at_checked(len)
+        .ok_or_else(|| anyhow!("Invalid ssh-agent reply: message too short"))?;
+
+    *data = rest;
+
+    Ok(value)
+}
+
+fn write_string(buffer: &mut Vec<u8>, data: &[u8]) -> Result<()> {
+    buffer.extend_from_slice(&u32::try_from(data.len())?.to_be_bytes());
+    buffer.extend_from_slice(data);
+
+    Ok(())
+}
+
+/// A local ssh-agent stand-in used by the tests, it holds the given private
+/// keys and listens on `<dir>/agent.sock`.
+#[cfg(all(test, unix))]
+#[allow(
+    clippy::unwrap_used,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate
+)]
+pub mod stand_in {
+    use super::*;
+    use ssh_key::PrivateKey;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    pub struct StandIn {
+        pub socket: PathBuf,
+        _dir: TempDir,
+    }
+
+    /// Start an agent holding the private keys.
+    pub fn start(keys: &[&str]) -> StandIn {
+        let keys: Vec<PrivateKey> = keys
+            .iter()
+            .map(|key| PrivateKey::read_openssh_file(Path::new(key)).unwrap())
+            .collect();
+
+        let dir = TempDir::new().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let keys = keys.clone();
+                std::thread::spawn(move || serve(stream, &keys));
+            }
+        });
+
+        StandIn { socket, _dir: dir }
+    }
+
+    fn serve(mut stream: UnixStream, keys: &[PrivateKey]) {
+        loop {
+            let mut len = [0_u8; 4];
+            if stream.read_exact(&mut len).is_err() {
+                return;
+            }
+
+            let mut message = vec![0_u8; u32::from_be_bytes(len) as usize];
+            stream.read_exact(&mut message).unwrap();
+
+            let reply = reply(&message, keys).unwrap_or_else(|| vec![SSH_AGENT_FAILURE]);
+
+            let mut response = Vec::new();
+            write_string(&mut response, &reply).unwrap();
+            stream.write_all(&response).unwrap();
+        }
+    }
+
+    fn reply(message: &[u8], keys: &[PrivateKey]) -> Option<Vec<u8>> {
+        let (kind, mut body) = message.split_first()?;
+
+        match *kind {
+            SSH_AGENTC_REQUEST_IDENTITIES => {
+                let mut reply = vec![SSH_AGENT_IDENTITIES_ANSWER];
+                reply.extend_from_slice(&u32::try_from(keys.len()).ok()?.to_be_bytes());
+
+                for key in keys {
+                    write_string(&mut reply, &key.public_key().to_bytes().ok()?).ok()?;
+                    write_string(&mut reply, key.comment().as_bytes()).ok()?;
+                }
+
+                Some(reply)
+            }
+            SSH_AGENTC_SIGN_REQUEST => {
+                let blob = read_string(&mut body).ok()?;
+                let data = read_string(&mut body).ok()?;
+
+                let key = keys
+                    .iter()
+                    .find(|key| key.public_key().to_bytes().ok().as_deref() == Some(blob))?;
+
+                // only Ed25519 keys can sign
+                let keypair = key.key_data().ed25519()?;
+                let signing_key =
+                    ed25519_dalek::SigningKey::from_bytes(&keypair.private.to_bytes());
+                let signature = Signature::new(
+                    ssh_key::Algorithm::Ed25519,
+                    ed25519_dalek::Signer::sign(&signing_key, data).to_bytes(),
+                )
+                .ok()?;
+
+                let mut reply = vec![SSH_AGENT_SIGN_RESPONSE];
+                write_string(&mut reply, &Vec::<u8>::try_from(signature).ok()?).ok()?;
+
+                Some(reply)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent() {
+        let agent = stand_in::start(&["test_data/ed25519"]);
+
+        let mut client = Agent::connect_to(&agent.socket).unwrap();
+
+        let keys = client.identities().unwrap();
+        let public_key = PublicKey::read_openssh_file(Path::new("test_data/ed25519.pub")).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys.first().unwrap().key_data(), public_key.key_data());
+
+        // Ed25519 signatures are deterministic
+        let signature = client.sign(&public_key, b"challenge").unwrap();
+        assert_eq!(signature, client.sign(&public_key, b"challenge").unwrap());
+        assert_ne!(signature, client.sign(&public_key, b"other").unwrap());
+    }
+
+    #[test]
+    fn test_agent_unknown_key() {
+        let agent = stand_in::start(&["test_data/ed25519"]);
+
+        let mut client = Agent::connect_to(&agent.socket).unwrap();
+
+        let public_key = PublicKey::read_openssh_file(Path::new("test_data/id_rsa.pub")).unwrap();
+        let sign = client.sign(&public_key, b"challenge");
+        assert!(sign.unwrap_err().to_string().contains("refused"));
+    }
+
+    #[test]
+    fn test_agent_not_running() {
+        temp_env::with_var("SSH_AUTH_SOCK", None::<&str>, || {
+            assert!(Agent::connect().is_err());
+        });
+
+        assert!(Agent::connect_to(Path::new("/nonexistent/agent.sock")).is_err());
+    }
+
+    #[test]
+    fn test_read_string() {
+        let mut data: &[u8] = &[0, 0, 0, 2, b'o', b'k', 0, 0, 0, 9, b'x'];
+        assert_eq!(read_string(&mut data).unwrap(), b"ok");
+        assert!(read_string(&mut data).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 pub mod aes256;
 pub mod chacha20poly1305;
+pub mod stream;
 
 use anyhow::{Result, anyhow};
 use hkdf::Hkdf;
@@ -0,0 +1,281 @@
+use anyhow::{Result, anyhow};
+use chacha20poly1305::{
+    ChaCha20Poly1305,
+    aead::{
+        KeyInit, Payload,
+        stream::{DecryptorBE32, EncryptorBE32},
+    },
+};
+use rand::{TryRng, rngs::SysRng};
+use secrecy::{ExposeSecret, SecretSlice};
+
+/// Size of the plaintext chunks
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Size of the encrypted chunks (plaintext + Poly1305 tag)
+pub const ENCRYPTED_CHUNK_SIZE: usize = CHUNK_SIZE + 16;
+
+/// Size of the random nonce prefix, the remaining 5 bytes of the nonce are a
+/// 32-bit big-endian chunk counter and the last chunk flag
+pub const NONCE_PREFIX_SIZE: usize = 7;
+
+/// Encrypts a stream of chunks using the STREAM construction
+///
+/// Every chunk except the last must be exactly [`CHUNK_SIZE`] bytes, the last
+/// chunk can be empty. Reordering, dropping or truncating chunks makes the
+/// decryption fail.
+pub struct StreamEncryptor {
+    encryptor: EncryptorBE32<ChaCha20Poly1305>,
+    aad: Vec<u8>,
+}
+
+impl StreamEncryptor {
+    /// Create a new encryptor, the returned nonce prefix must be stored
+    /// before the encrypted chunks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if random bytes cannot be generated.
+    pub fn new(key: &SecretSlice<u8>, aad: &[u8]) -> Result<(Self, [u8; NONCE_PREFIX_SIZE])> {
+        let mut prefix = [0_u8; NONCE_PREFIX_SIZE];
+        SysRng
+            .try_fill_bytes(&mut prefix)
+            .map_err(|err| anyhow!("Error generating random nonce: {err}"))?;
+
+        let cipher = ChaCha20Poly1305::new(key.expose_secret().into());
+
+        Ok((
+            Self {
+                encryptor: EncryptorBE32::from_aead(cipher, (&prefix).into()),
+                aad: aad.to_vec(),
+            },
+            prefix,
+        ))
+    }
+
+    /// Encrypt a chunk that is not the last one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if encryption fails or the chunk counter overflows.
+    pub fn encrypt_next(&mut self, chunk: &[u8]) -> Result<Vec<u8>> {
+        self.encryptor
+            .encrypt_next(Payload {
+                msg: chunk,
+                aad: &self.aad,
+            })
+            .map_err(|_| anyhow!("Failed to encrypt data"))
+    }
+
+    /// Encrypt the last chunk and consume the encryptor.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if encryption fails.
+    pub fn encrypt_last(self, chunk: &[u8]) -> Result<Vec<u8>> {
+        self.encryptor
+            .encrypt_last(Payload {
+                msg: chunk,
+                aad: &self.aad,
+            })
+            .map_err(|_| anyhow!("Failed to encrypt data"))
+    }
+}
+
+/// Decrypts a stream of chunks created by [`StreamEncryptor`]
+pub struct StreamDecryptor {
+    decryptor: DecryptorBE32<ChaCha20Poly1305>,
+    aad: Vec<u8>,
+}
+
+impl StreamDecryptor {
+    /// Create a new decryptor using the nonce prefix stored before the chunks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the nonce prefix is not [`NONCE_PREFIX_SIZE`] bytes.
+    pub fn new(key: &SecretSlice<u8>, prefix: &[u8], aad: &[u8]) -> Result<Self> {
+        let prefix: &[u8; NONCE_PREFIX_SIZE] = prefix.try_into().map_err(|_| {
+            anyhow!(
+                "Invalid encrypted data: too short (expected at least {NONCE_PREFIX_SIZE} bytes, got {})",
+                prefix.len()
+            )
+        })?;
+
+        let cipher = ChaCha20Poly1305::new(key.expose_secret().into());
+
+        Ok(Self {
+            decryptor: DecryptorBE32::from_aead(cipher, prefix.into()),
+            aad: aad.to_vec(),
+        })
+    }
+
+    /// Decrypt a chunk that is not the last one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if authentication fails.
+    pub fn decrypt_next(&mut self, chunk: &[u8]) -> Result<Vec<u8>> {
+        self.decryptor
+            .decrypt_next(Payload {
+                msg: chunk,
+                aad: &self.aad,
+            })
+            .map_err(|err| anyhow!("Error decrypting data: {err}"))
+    }
+
+    /// Decrypt the last chunk and consume the decryptor.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if authentication fails, including when the stream was
+    /// truncated.
+    pub fn decrypt_last(self, chunk: &[u8]) -> Result<Vec<u8>> {
+        self.decryptor
+            .decrypt_last(Payload {
+                msg: chunk,
+                aad: &self.aad,
+            })
+            .map_err(|err| anyhow!("Error decrypting data: {err}"))
+    }
+}
+
+/// In-memory ChaCha20-Poly1305 STREAM encryption, the output is the nonce
+/// prefix followed by the encrypted chunks.
+pub struct ChaCha20Poly1305StreamCrypto {
+    key: SecretSlice<u8>,
+}
+
+impl super::Crypto for ChaCha20Poly1305StreamCrypto {
+    fn new(key: SecretSlice<u8>) -> Self {
+        Self { key }
+    }
+
+    // Encrypts data with a key and a fingerprint
+    fn encrypt(&self, data: &[u8], fingerprint: &[u8]) -> Result<Vec<u8>> {
+        let (mut encryptor, prefix) = StreamEncryptor::new(&self.key, fingerprint)?;
+
+        let mut encrypted_data = prefix.to_vec();
+
+        // the last chunk is never empty unless the data is empty
+        let mut chunks = data.chunks(CHUNK_SIZE).peekable();
+        let mut last: &[u8] = &[];
+
+        while let Some(chunk) = chunks.next() {
+            if chunks.peek().is_some() {
+                encrypted_data.extend(encryptor.encrypt_next(chunk)?);
+            } else {
+                last = chunk;
+            }
+        }
+
+        encrypted_data.extend(encryptor.encrypt_last(last)?);
+
+        Ok(encrypted_data)
+    }
+
+    // Decrypts data with a key and a fingerprint
+    fn decrypt(&self, data: &[u8], fingerprint: &[u8]) -> Result<Vec<u8>> {
+        let (prefix, data) = data.split_at_checked(NONCE_PREFIX_SIZE).ok_or_else(|| {
+            anyhow!(
+                "Invalid encrypted data: too short (expected at least {NONCE_PREFIX_SIZE} bytes, got {})",
+                data.len()
+            )
+        })?;
+
+        let mut decryptor = StreamDecryptor::new(&self.key, prefix, fingerprint)?;
+
+        let mut decrypted_data = Vec::with_capacity(data.len());
+
+        let mut chunks = data.chunks(ENCRYPTED_CHUNK_SIZE).peekable();
+        let mut last: &[u8] = &[];
+
+        while let Some(chunk) = chunks.next() {
+            if chunks.peek().is_some() {
+                decrypted_data.extend(decryptor.decrypt_next(chunk)?);
+            } else {
+                last = chunk;
+            }
+        }
+
+        decrypted_data.extend(decryptor.decrypt_last(last)?);
+
+        Ok(decrypted_data)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::indexing_slicing)]
+mod tests {
+    use super::*;
+    use crate::vault::crypto::{Crypto, gen_password};
+
+    const FINGERPRINT: &str = "SHA256:hgIL5fEHz5zuOWY1CDlUuotdaUl4MvYG7vAgE4q4TzM";
+
+    #[test]
+    fn test_stream() {
+        let crypto = ChaCha20Poly1305StreamCrypto::new(gen_password().unwrap());
+
+        for len in [
+            0,
+            1,
+            CHUNK_SIZE - 1,
+            CHUNK_SIZE,
+            CHUNK_SIZE + 1,
+            CHUNK_SIZE * 3,
+        ] {
+            let data: Vec<u8> = (0..len).map(|i| u8::try_from(i % 251).unwrap()).collect();
+
+            let encrypted_data = crypto.encrypt(&data, FINGERPRINT.as_bytes()).unwrap();
+            assert_eq!(
+                encrypted_data.len(),
+                NONCE_PREFIX_SIZE + len + len.div_ceil(CHUNK_SIZE).max(1) * 16
+            );
+
+            let decrypted_data = crypto
+                .decrypt(&encrypted_data, FINGERPRINT.as_bytes())
+                .unwrap();
+            assert_eq!(data, decrypted_data, "len {len}");
+        }
+    }
+
+    #[test]
+    fn test_stream_wrong_fingerprint() {
+        let crypto = ChaCha20Poly1305StreamCrypto::new(gen_password().unwrap());
+
+        let encrypted_data = crypto.encrypt(b"data", FINGERPRINT.as_bytes()).unwrap();
+        assert!(crypto.decrypt(&encrypted_data, b"SHA256:invalid").is_err());
+    }
+
+    #[test]
+    fn test_stream_truncated() {
+        let crypto = ChaCha20Poly1305StreamCrypto::new(gen_password().unwrap());
+
+        let data = vec![1u8; CHUNK_SIZE * 2 + 10];
+        let encrypted_data = crypto.encrypt(&data, FINGERPRINT.as_bytes()).unwrap();
+
+        // drop the last chunk, the previous one is not flagged as last
+        let truncated = &encrypted_data[..NONCE_PREFIX_SIZE + ENCRYPTED_CHUNK_SIZE * 2];
+        assert!(crypto.decrypt(truncated, FINGERPRINT.as_bytes()).is_err());
+
+        // swap the first two chunks
+        let mut swapped = encrypted_data.clone();
+        let (first, second) = swapped[NONCE_PREFIX_SIZE..].split_at_mut(ENCRYPTED_CHUNK_SIZE);
+        first.swap_with_slice(&mut second[..ENCRYPTED_CHUNK_SIZE]);
+        assert!(crypto.decrypt(&swapped, FINGERPRINT.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_stream_short_data() {
+        let crypto = ChaCha20Poly1305StreamCrypto::new(gen_password().unwrap());
+
+        for len in 0..NONCE_PREFIX_SIZE {
+            let result = crypto.decrypt(&vec![0u8; len], FINGERPRINT.as_bytes());
+            assert!(result.unwrap_err().to_string().contains("too short"));
+        }
+
+        // prefix without a tag
+        let result = crypto.decrypt(&[0u8; NONCE_PREFIX_SIZE], FINGERPRINT.as_bytes());
+        assert!(result.is_err());
+    }
+}
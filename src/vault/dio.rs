@@ -64,6 +64,11 @@ impl OutputDestination {
         Ok(Self::Stdout)
     }
 
+    #[must_use]
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Stdout) && io::stdout().is_terminal()
+    }
+
     /// Truncate the underlying file (if any).
     ///
     /// # Errors
@@ -135,6 +140,7 @@ mod tests {
         assert!(matches!(input, InputSource::Stdin));
         assert_eq!(input.is_terminal(), stdin_is_terminal);
         assert!(matches!(output, OutputDestination::Stdout));
+        assert_eq!(output.is_terminal(), io::stdout().is_terminal());
 
         let (input, output) = setup_io(Some("-".to_string()), None).unwrap();
         assert!(matches!(input, InputSource::Stdin));
@@ -165,6 +171,7 @@ mod tests {
         .unwrap();
         assert!(!input.is_terminal());
         assert!(matches!(output, OutputDestination::File(_)));
+        assert!(!output.is_terminal());
 
         // File is directory
         let rs = setup_io(Some("Cargo.toml".to_string()), Some("/".to_string()));
@@ -44,14 +44,25 @@ impl fmt::Display for Fingerprint {
 ///
 /// Returns an error if any key cannot be read or parsed.
 pub fn fingerprints() -> Result<Vec<Fingerprint>> {
+    let home = tools::get_home()?;
+    fingerprints_in(&Path::new(&home).join(".ssh"))
+}
+
+/// Collect fingerprints for all public keys (`*.pub`) in a directory, sorted
+/// by file name. A missing directory has no keys.
+///
+/// # Errors
+///
+/// Returns an error if any key cannot be read or parsed.
+pub fn fingerprints_in(dir: &Path) -> Result<Vec<Fingerprint>> {
     // Create a vector to store Fingerprint structs
     let mut fingerprints: Vec<Fingerprint> = Vec::new();
 
-    let home = tools::get_home()?;
-    let ssh_home = Path::new(&home).join(".ssh");
-    if let Ok(entries) = fs::read_dir(ssh_home) {
-        for entry in entries.flatten() {
-            let path = entry.path();
+    if let Ok(entries) = fs::read_dir(dir) {
+        let mut paths: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+        paths.sort();
+
+        for path in paths {
             if let Some(ext) = path.extension()
                 && ext == "pub"
                 && let Ok(key) = PublicKey::read_openssh_file(&path)
@@ -176,6 +187,20 @@ pub fn get_remote_fingerprints(keys: &str, key: Option<u32>) -> Result<Vec<Finge
     Ok(fingerprints)
 }
 
+/// Return the fingerprint used to identify a key in the vault header, MD5 for
+/// RSA keys and SHA256 for the rest.
+///
+/// # Errors
+///
+/// Returns an error if the key cannot be encoded.
+pub fn vault_fingerprint(key: &PublicKey) -> Result<String> {
+    if let Some(key_data) = key.key_data().rsa() {
+        md5_fingerprint(&RsaPublicKey::try_from(key_data)?)
+    } else {
+        Ok(key.fingerprint(HashAlg::Sha256).to_string())
+    }
+}
+
 /// Calculate the MD5 fingerprint of a RSA public key and format it as a colon
 /// separated string.
 ///
@@ -224,6 +249,10 @@ mod tests {
                 key: "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAINixf2m2nj8TDeazbWuemUY8ZHNg7znA7hVPN8TJLr2W",
                 fingerprint: "SHA256:hgIL5fEHz5zuOWY1CDlUuotdaUl4MvYG7vAgE4q4TzM",
             },
+            Test {
+                key: "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBLfuS9KO/ESjlm6khuGcwNSA8sitAbLfys/bIGDqrrXG2M+rR9y3E+ENQIczHKPy6LSLZ6Spg3y5V5anz91nLc8= ssh-vault",
+                fingerprint: "SHA256:hyJjDHSpBfNYFNUT5OcF9Msb4hBHSN4lUBEzYTBWn/g",
+            },
         ];
 
         for test in &tests {
@@ -233,10 +262,13 @@ mod tests {
                 let key_data = public_key.key_data().rsa().unwrap();
                 let rsa_public_key = RsaPublicKey::try_from(key_data).unwrap();
                 fingerprint = md5_fingerprint(&rsa_public_key).unwrap();
-            } else if public_key.algorithm().as_str() == "ssh-ed25519" {
+            } else if public_key.algorithm().as_str() == "ssh-ed25519"
+                || public_key.algorithm().is_ecdsa()
+            {
                 fingerprint = public_key.fingerprint(HashAlg::Sha256).to_string();
             }
             assert_eq!(fingerprint, test.fingerprint);
+            assert_eq!(vault_fingerprint(&public_key).unwrap(), test.fingerprint);
         }
     }
 }
@@ -0,0 +1,875 @@
+use crate::{cache, config, tools, vault::remote};
+use ::config::{Map, Value, ValueKind};
+use anyhow::{Context, Result, anyhow};
+use reqwest::{
+    StatusCode,
+    header::{
+        ETAG, HeaderMap, HeaderName, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+        LOCATION,
+    },
+};
+use std::{env, fs, process::Command, thread, time::Duration};
+use url::Url;
+
+// the redirects followed by a request
+const MAX_REDIRECTS: usize = 10;
+
+/// GET the URL skipping the cache, with a cache key the response is cached.
+///
+/// # Errors
+///
+/// Returns an error if the request fails or the response cannot be read.
+pub fn get(url: &Url, cache_key: Option<&str>) -> Result<String> {
+    let res = fetch(url, &HeaderMap::new())?;
+
+    response_body(url, res, cache_key)
+}
+
+/// GET the URL revalidating the cache entry with its `ETag` or
+/// `Last-Modified`, a 304 resets the age of the entry.
+///
+/// # Errors
+///
+/// Returns an error if the request fails or the response cannot be read.
+pub fn revalidate(url: &Url, cache_key: &str) -> Result<String> {
+    let validators = validators(cache_key)?;
+
+    let res = fetch(url, &validators)?;
+
+    if res.status() == StatusCode::NOT_MODIFIED && !validators.is_empty() {
+        cache::touch(cache_key)?;
+        return cache::read(cache_key);
+    }
+
+    response_body(url, res, Some(cache_key))
+}
+
+/// Perform a GET request with the configured HTTP client and the headers for
+/// the URL, server errors, timeouts and failed connections are retried
+/// `http_retries` times (default 2) waiting `http_retry_delay` milliseconds
+/// (default 500), doubled on every attempt. The `headers` are added to this
+/// request only.
+///
+/// # Errors
+///
+/// Returns an error in offline mode, if the client can't be configured or the
+/// request fails after the retries.
+pub fn fetch(url: &Url, headers: &HeaderMap) -> Result<reqwest::blocking::Response> {
+    send(url, headers, None)
+}
+
+/// PUT the body to the URL, retried like [`fetch`].
+///
+/// # Errors
+///
+/// Returns an error in offline mode, if the request fails or the server
+/// doesn't accept the body.
+pub fn put(url: &Url, body: &str) -> Result<()> {
+    let res = send(url, &HeaderMap::new(), Some(body))?;
+
+    if !res.status().is_success() {
+        return Err(anyhow!("Upload failed with status: {}", res.status()));
+    }
+
+    Ok(())
+}
+
+// a GET request or a PUT with the body, the redirects are followed here so
+// the http_headers of a host are never sent to the host it redirects to
+fn send(url: &Url, headers: &HeaderMap, body: Option<&str>) -> Result<reqwest::blocking::Response> {
+    // the single place where requests are sent
+    if remote::is_offline()? {
+        return Err(anyhow!("Offline mode, refusing to fetch {url}"));
+    }
+
+    let mut url = url.clone();
+    let mut body = body;
+
+    for _ in 0..=MAX_REDIRECTS {
+        let client = client(get_headers(&url)?)?;
+
+        let res = send_retrying(&client, &url, headers, body)?;
+
+        let location = match res.status() {
+            StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT => res.headers().get(LOCATION),
+            _ => None,
+        };
+
+        let Some(location) = location else {
+            return Ok(res);
+        };
+
+        let next = url
+            .join(location.to_str()?)
+            .with_context(|| format!("Invalid redirect from {url}"))?;
+
+        // see other is fetched with a GET
+        if res.status() == StatusCode::SEE_OTHER {
+            body = None;
+        }
+
+        url = next;
+    }
+
+    Err(anyhow!("Too many redirects fetching {url}"))
+}
+
+// send the request retrying server errors, timeouts and failed connections
+fn send_retrying(
+    client: &reqwest::blocking::Client,
+    url: &Url,
+    headers: &HeaderMap,
+    body: Option<&str>,
+) -> Result<reqwest::blocking::Response> {
+    let config = config::get()?;
+
+    let retries = get_number(&config, "http_retries")?.unwrap_or(2);
+    let delay = Duration::from_millis(get_number(&config, "http_retry_delay")?.unwrap_or(500));
+
+    let mut attempt: u32 = 0;
+
+    loop {
+        let request = match body {
+            Some(body) => client.put(url.clone()).body(body.to_string()),
+            None => client.get(url.clone()),
+        };
+
+        let result = request.headers(headers.clone()).send();
+
+        let retry = match &result {
+            Ok(res) => res.status().is_server_error(),
+            Err(err) => err.is_timeout() || err.is_connect(),
+        };
+
+        if !retry || u64::from(attempt) >= retries {
+            return Ok(result?);
+        }
+
+        thread::sleep(delay.saturating_mul(2_u32.saturating_pow(attempt)));
+
+        attempt += 1;
+    }
+}
+
+/// Build the HTTP client from `~/.config/ssh-vault/config.yml` or the
+/// environment:
+///
+/// - `proxy`: proxy URL for the requests, skipped for the hosts in `no_proxy`
+/// - `http_connect_timeout`, `http_read_timeout`: timeouts in seconds
+/// - `http_ca_certs`: PEM files with extra root certificates
+/// - `http_client_cert`: PEM client certificate for mutual TLS, with the key
+///   in `http_client_key` if it is not in the same file
+///
+/// The client doesn't follow redirects, the headers are for one URL only.
+///
+/// # Errors
+///
+/// Returns an error if a setting is invalid or a file can't be read.
+pub fn client(headers: HeaderMap) -> Result<reqwest::blocking::Client> {
+    let config = config::get()?;
+
+    let mut builder = reqwest::blocking::Client::builder()
+        .user_agent("ssh-vault")
+        .default_headers(headers)
+        .redirect(reqwest::redirect::Policy::none());
+
+    if let Ok(proxy) = config.get_string("proxy") {
+        let no_proxy = config
+            .get_string("no_proxy")
+            .ok()
+            .and_then(|hosts| reqwest::NoProxy::from_string(&hosts));
+
+        builder = builder.proxy(
+            reqwest::Proxy::all(&proxy)
+                .with_context(|| format!("Invalid proxy {proxy}"))?
+                .no_proxy(no_proxy),
+        );
+    }
+
+    if let Some(timeout) = get_number(&config, "http_connect_timeout")? {
+        builder = builder.connect_timeout(Duration::from_secs(timeout));
+    }
+
+    if let Some(timeout) = get_number(&config, "http_read_timeout")? {
+        builder = builder.timeout(Duration::from_secs(timeout));
+    }
+
+    for path in config::get_paths(&config, "http_ca_certs")? {
+        let pem = fs::read(&path)
+            .with_context(|| format!("Could not read the CA certificates {}", path.display()))?;
+
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("Invalid CA certificates {}", path.display()))?;
+
+        if certs.is_empty() {
+            return Err(anyhow!("No CA certificates found in {}", path.display()));
+        }
+
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    if let Ok(cert) = config.get_string("http_client_cert") {
+        let path = tools::expand_home(&cert)?;
+        let mut pem = fs::read(&path)
+            .with_context(|| format!("Could not read the client certificate {}", path.display()))?;
+
+        if let Ok(key) = config.get_string("http_client_key") {
+            let path = tools::expand_home(&key)?;
+            pem.push(b'\n');
+            pem.extend(
+                fs::read(&path)
+                    .with_context(|| format!("Could not read the client key {}", path.display()))?,
+            );
+        }
+
+        builder = builder.identity(
+            reqwest::Identity::from_pem(&pem)
+                .context("Invalid client certificate, a PEM certificate and key are required")?,
+        );
+    }
+
+    Ok(builder.build()?)
+}
+
+// a number setting, None if it is not set
+fn get_number(config: &::config::Config, key: &str) -> Result<Option<u64>> {
+    match config.get::<u64>(key) {
+        Ok(number) => Ok(Some(number)),
+        Err(::config::ConfigError::NotFound(_)) => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("Invalid {key}, a number is required")),
+    }
+}
+
+fn response_body(
+    url: &Url,
+    res: reqwest::blocking::Response,
+    cache_key: Option<&str>,
+) -> Result<String> {
+    if !res.status().is_success() {
+        return Err(anyhow!("Request failed with status: {}", res.status()));
+    }
+
+    let header = |name| {
+        res.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string)
+    };
+
+    let meta = cache::Meta {
+        url: Some(url.to_string()),
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+
+    // Read the response body
+    let body = res.text()?;
+
+    if let Some(key) = cache_key {
+        cache::store(key, &body, &meta)?;
+    }
+
+    Ok(body)
+}
+
+// the conditional request headers to revalidate a cache entry
+fn validators(key: &str) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+
+    if cache::read(key).is_err() {
+        return Ok(headers);
+    }
+
+    if let Some(meta) = cache::meta(key)? {
+        if let Some(etag) = meta.etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+
+        if let Some(date) = meta
+            .last_modified
+            .and_then(|date| HeaderValue::from_str(&date).ok())
+        {
+            headers.insert(IF_MODIFIED_SINCE, date);
+        }
+    }
+
+    Ok(headers)
+}
+
+// the HTTP headers for the URL from the http_headers setting, a host or URL
+// prefix entry holds the headers of the matching requests, a plain entry is
+// an error, it would be sent to any host
+fn get_headers(url: &Url) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+
+    // get the config from ~/.config/ssh-vault/config.yml
+    let config = config::get()?;
+
+    let Ok(http_headers) = config.get_table("http_headers") else {
+        return Ok(headers);
+    };
+
+    let mut scopes = Vec::new();
+
+    for (key, value) in http_headers {
+        match value.kind {
+            ValueKind::Table(table) if !is_secret(&table) => {
+                if scope_matches(&key, url) {
+                    scopes.push((key, table));
+                }
+            }
+            _ => {
+                return Err(anyhow!(
+                    "The HTTP header {key} in http_headers must be under a host or URL prefix:\n\n\
+                     http_headers:\n  keys.example.com:\n    {key}: ...\n  \"https://example.com/keys/\":\n    {key}: ..."
+                ));
+            }
+        }
+    }
+
+    // the most specific scope wins
+    scopes.sort_by_key(|(scope, _)| scope.len());
+
+    for (_, table) in scopes {
+        for (key, value) in table {
+            insert_header(&mut headers, &key, value)?;
+        }
+    }
+
+    Ok(headers)
+}
+
+// a scope is a host (optionally with the port) or a URL prefix, a prefix
+// matches the same scheme, host and port and a path below its path
+fn scope_matches(scope: &str, url: &Url) -> bool {
+    if scope.contains("://") {
+        let Ok(scope) = Url::parse(scope) else {
+            return false;
+        };
+
+        let path = scope.path();
+
+        return scope.scheme() == url.scheme()
+            && scope.host_str().is_some()
+            && scope.host_str() == url.host_str()
+            && scope.port_or_known_default() == url.port_or_known_default()
+            && url.path().strip_prefix(path).is_some_and(|rest| {
+                path.ends_with('/') || rest.is_empty() || rest.starts_with('/')
+            });
+    }
+
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+
+    scope.eq_ignore_ascii_case(host)
+        || url
+            .port_or_known_default()
+            .is_some_and(|port| scope.eq_ignore_ascii_case(&format!("{host}:{port}")))
+}
+
+// a header value read from an environment variable or a command
+fn is_secret(table: &Map<String, Value>) -> bool {
+    table.len() == 1 && (table.contains_key("env") || table.contains_key("command"))
+}
+
+fn insert_header(headers: &mut HeaderMap, name: &str, value: Value) -> Result<()> {
+    let (value, sensitive) = match value.kind {
+        ValueKind::Table(table) => (secret(&table)?, true),
+        _ => (value.to_string(), false),
+    };
+
+    let mut value = HeaderValue::from_str(&value)
+        .map_err(|_| anyhow!("Invalid value for the HTTP header {name}"))?;
+    value.set_sensitive(sensitive);
+
+    headers.insert(
+        HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| anyhow!("Invalid HTTP header name {name}"))?,
+        value,
+    );
+
+    Ok(())
+}
+
+fn secret(table: &Map<String, Value>) -> Result<String> {
+    if let Some(var) = table.get("env") {
+        let var = var.clone().into_string()?;
+        return env::var(&var).with_context(|| format!("Environment variable {var} not set"));
+    }
+
+    let command = table
+        .get("command")
+        .ok_or_else(|| anyhow!("Expected env or command"))?
+        .clone()
+        .into_string()?;
+
+    let args = shell_words::split(&command)?;
+    let (program, args) = args
+        .split_first()
+        .ok_or_else(|| anyhow!("Empty HTTP header command"))?;
+
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Could not run {program}"))?;
+
+    if !output.status.success() {
+        return Err(anyhow!("HTTP header command {program} failed"));
+    }
+
+    Ok(String::from_utf8(output.stdout)?.trim_end().to_string())
+}
+
+/// A local HTTP server stand-in used by the tests, it answers GET requests for
+/// the given paths (404 otherwise) with an `ETag`, revalidated with a 304, and
+/// records the requests.
+#[cfg(test)]
+#[allow(
+    clippy::unwrap_used,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate
+)]
+pub mod stand_in {
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    pub struct StandIn {
+        pub url: String,
+        requests: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl StandIn {
+        /// The request line and headers of every request received.
+        pub fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    /// Start a server answering the paths with the bodies.
+    pub fn start(routes: &[(&str, &str)]) -> StandIn {
+        start_failing(0, routes)
+    }
+
+    /// Start a server answering the first `failures` requests with a 503.
+    pub fn start_failing(failures: usize, routes: &[(&str, &str)]) -> StandIn {
+        let mut routes: HashMap<String, String> = routes
+            .iter()
+            .map(|(path, body)| ((*path).to_string(), (*body).to_string()))
+            .collect();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&requests);
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream);
+                let mut head = String::new();
+
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    head.push_str(&line);
+                }
+
+                let path = head.split_whitespace().nth(1).unwrap_or("/").to_string();
+
+                // the body of a PUT is served on the next GET of the path
+                if head.starts_with("PUT ") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|length| length.trim().parse::<usize>().unwrap_or(0))
+                        })
+                        .unwrap_or(0);
+
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+                    routes.insert(path.clone(), String::from_utf8(body).unwrap());
+                }
+
+                let received = {
+                    let mut log = log.lock().unwrap();
+                    log.push(head);
+                    log.len()
+                };
+
+                let (status, body) = if received <= failures {
+                    ("503 Service Unavailable", "")
+                } else {
+                    routes
+                        .get(&path)
+                        .map_or(("404 Not Found", ""), |body| ("200 OK", body.as_str()))
+                };
+
+                // the bodies are tagged, matching conditional requests get a 304
+                let etag = format!("\"{:x}\"", md5::compute(body));
+                let (status, body) = if status.starts_with("200")
+                    && log.lock().unwrap().last().is_some_and(|head| {
+                        head.to_lowercase()
+                            .contains(&format!("if-none-match: {etag}"))
+                    }) {
+                    ("304 Not Modified", "")
+                } else {
+                    (status, body)
+                };
+
+                // a body starting with "=> " redirects to the rest of it
+                let (status, location, body) = match body.strip_prefix("=> ") {
+                    Some(location) => ("302 Found", format!("Location: {location}\r\n"), ""),
+                    None => (status, String::new(), body),
+                };
+
+                let _ = write!(
+                    reader.into_inner(),
+                    "HTTP/1.1 {status}\r\nETag: {etag}\r\n{location}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+
+        StandIn { url, requests }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::vault::remote::request;
+
+    #[test]
+    fn test_get_headers() {
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            let headers = get_headers(&Url::parse("https://github.com").unwrap()).unwrap();
+            assert!(headers.is_empty());
+        });
+    }
+
+    const HTTP_HEADERS: &str = r#"
+http_headers:
+  keys.example.com:
+    Authorization:
+      env: SSH_VAULT_TEST_TOKEN
+  "keys.example.com:8443":
+    X-Port: port
+  "https://keys.example.com/api/":
+    X-Api-Key:
+      command: echo "api key"
+    X-Port: prefix
+"#;
+
+    fn write_config(home: &std::path::Path, config: &str) {
+        let dir = home.join(".config").join("ssh-vault");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("config.yml"), config).unwrap();
+    }
+
+    #[test]
+    fn test_get_headers_scoped() {
+        let home = tempfile::TempDir::new().unwrap();
+        write_config(home.path(), HTTP_HEADERS);
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_TEST_TOKEN", Some("Bearer secret".as_ref())),
+            ],
+            || {
+                let headers = |url: &str| get_headers(&Url::parse(url).unwrap()).unwrap();
+
+                // only the matching hosts and prefixes
+                assert!(headers("https://other.example.com/alice.keys").is_empty());
+                assert!(headers("https://github.com/alice.keys").is_empty());
+                assert!(headers("https://gitlab.com/alice.keys").is_empty());
+
+                let host = headers("https://KEYS.example.com/alice.keys");
+                assert_eq!(host.get("authorization").unwrap(), "Bearer secret");
+                assert!(host.get("authorization").unwrap().is_sensitive());
+                assert_eq!(host.get("x-port"), None);
+
+                let port = headers("https://keys.example.com:8443/alice.keys");
+                assert_eq!(port.get("x-port").unwrap(), "port");
+                // a host without port matches every port
+                assert_eq!(port.get("authorization").unwrap(), "Bearer secret");
+
+                let prefix = headers("https://keys.example.com/api/alice");
+                assert_eq!(prefix.get("x-api-key").unwrap(), "api key");
+                assert_eq!(prefix.get("x-port").unwrap(), "prefix");
+                assert_eq!(prefix.get("authorization").unwrap(), "Bearer secret");
+
+                // a prefix matches the exact host and a whole path segment
+                for url in [
+                    "https://keys.example.com.evil.com/api/alice",
+                    "https://keys.example.com@evil.com/api/alice",
+                    "http://keys.example.com/api/alice",
+                    "https://keys.example.com:444/api/alice",
+                    "https://keys.example.com/apix",
+                    "https://keys.example.com/api",
+                ] {
+                    assert_eq!(headers(url).get("x-api-key"), None, "{url}");
+                }
+                assert!(headers("https://keys.example.com.evil.com/").is_empty());
+                assert!(headers("https://keys.example.com@evil.com/").is_empty());
+            },
+        );
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_TEST_TOKEN", None),
+            ],
+            || {
+                assert!(get_headers(&Url::parse("https://keys.example.com").unwrap()).is_err());
+            },
+        );
+    }
+
+    #[test]
+    fn test_get_headers_unscoped() {
+        let home = tempfile::TempDir::new().unwrap();
+        write_config(home.path(), "http_headers:\n  Private-Token: secret\n");
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            // a plain entry would be sent to any host, the scoped syntax is shown
+            let err = get_headers(&Url::parse("https://keys.example.com").unwrap()).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("must be under a host or URL prefix")
+            );
+            assert!(
+                err.to_string()
+                    .contains("keys.example.com:\n    Private-Token: ...")
+            );
+        });
+    }
+
+    #[test]
+    fn test_request_headers() {
+        let server = stand_in::start(&[("/alice.keys", "ssh-ed25519 AAAA")]);
+        let home = tempfile::TempDir::new().unwrap();
+        let host = server.url.trim_start_matches("http://").to_string();
+        write_config(
+            home.path(),
+            &format!("http_headers:\n  \"{host}\":\n    Authorization: Bearer secret\n"),
+        );
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            get(
+                &Url::parse(&format!("{}/alice.keys", server.url)).unwrap(),
+                None,
+            )
+            .unwrap();
+
+            let requests = server.requests();
+            assert!(
+                requests
+                    .first()
+                    .unwrap()
+                    .to_lowercase()
+                    .contains("authorization: bearer secret")
+            );
+        });
+    }
+
+    #[test]
+    fn test_redirect_headers() {
+        let target = stand_in::start(&[("/alice.keys", "ssh-ed25519 AAAA")]);
+        let server = stand_in::start(&[("/alice.keys", &format!("=> {}/alice.keys", target.url))]);
+        let home = tempfile::TempDir::new().unwrap();
+        let host = server.url.trim_start_matches("http://").to_string();
+        write_config(
+            home.path(),
+            &format!("http_headers:\n  \"{host}\":\n    Private-Token: secret\n"),
+        );
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            let body = get(
+                &Url::parse(&format!("{}/alice.keys", server.url)).unwrap(),
+                None,
+            )
+            .unwrap();
+            assert_eq!(body, "ssh-ed25519 AAAA");
+
+            let sent = server.requests();
+            assert!(
+                sent.first()
+                    .unwrap()
+                    .to_lowercase()
+                    .contains("private-token: secret")
+            );
+
+            // the headers of the host are not sent where it redirects
+            let redirected = target.requests();
+            assert_eq!(redirected.len(), 1);
+            assert!(
+                !redirected
+                    .first()
+                    .unwrap()
+                    .to_lowercase()
+                    .contains("private-token")
+            );
+        });
+    }
+
+    #[test]
+    fn test_fetch_retries() {
+        let server = stand_in::start_failing(2, &[("/alice.keys", "ssh-ed25519 AAAA")]);
+        let home = tempfile::TempDir::new().unwrap();
+        let url = Url::parse(&format!("{}/alice.keys", server.url)).unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_HTTP_RETRY_DELAY", Some("1".as_ref())),
+            ],
+            || {
+                let res = fetch(&url, &HeaderMap::new()).unwrap();
+                assert!(res.status().is_success());
+                assert_eq!(res.text().unwrap(), "ssh-ed25519 AAAA");
+                assert_eq!(server.requests().len(), 3);
+            },
+        );
+
+        let server = stand_in::start_failing(2, &[("/alice.keys", "ssh-ed25519 AAAA")]);
+        let url = format!("{}/alice.keys", server.url);
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_HTTP_RETRY_DELAY", Some("1".as_ref())),
+                ("SSH_VAULT_HTTP_RETRIES", Some("1".as_ref())),
+            ],
+            || {
+                let err = request(&url, false).unwrap_err();
+                assert!(err.to_string().contains("503"));
+                assert_eq!(server.requests().len(), 2);
+            },
+        );
+
+        // client errors are not retried
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_HTTP_RETRY_DELAY", Some("1".as_ref())),
+            ],
+            || {
+                assert!(request(&format!("{}/bob.keys", server.url), false).is_err());
+                assert_eq!(server.requests().len(), 3);
+            },
+        );
+    }
+
+    #[test]
+    fn test_fetch_read_timeout() {
+        // accepts connections but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/alice.keys",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_HTTP_READ_TIMEOUT", Some("1".as_ref())),
+                ("SSH_VAULT_HTTP_RETRIES", Some("0".as_ref())),
+            ],
+            || {
+                let err = fetch(&url, &HeaderMap::new()).unwrap_err();
+                assert!(err.downcast_ref::<reqwest::Error>().unwrap().is_timeout());
+            },
+        );
+    }
+
+    #[test]
+    fn test_fetch_proxy() {
+        let proxy = stand_in::start(&[("http://keys.example.com/alice.keys", "ssh-ed25519 AAAA")]);
+        let server = stand_in::start(&[("/alice.keys", "ssh-ed25519 BBBB")]);
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_PROXY", Some(proxy.url.as_ref())),
+                ("SSH_VAULT_NO_PROXY", Some("127.0.0.1".as_ref())),
+            ],
+            || {
+                assert_eq!(
+                    request("http://keys.example.com/alice.keys", false).unwrap(),
+                    "ssh-ed25519 AAAA"
+                );
+
+                // no_proxy hosts are fetched directly
+                assert_eq!(
+                    request(&format!("{}/alice.keys", server.url), false).unwrap(),
+                    "ssh-ed25519 BBBB"
+                );
+                assert_eq!(proxy.requests().len(), 1);
+                assert_eq!(server.requests().len(), 1);
+            },
+        );
+    }
+
+    #[test]
+    fn test_client() {
+        let home = tempfile::TempDir::new().unwrap();
+        let client = |vars: &[(&str, Option<&str>)]| {
+            let mut vars = vars.to_vec();
+            vars.push(("HOME", home.path().to_str()));
+            temp_env::with_vars(vars, || client(HeaderMap::new()))
+        };
+
+        assert!(client(&[]).is_ok());
+        assert!(
+            client(&[
+                ("SSH_VAULT_HTTP_CONNECT_TIMEOUT", Some("5")),
+                ("SSH_VAULT_HTTP_READ_TIMEOUT", Some("10")),
+                ("SSH_VAULT_HTTP_CA_CERTS", Some("test_data/tls/ca.pem")),
+                (
+                    "SSH_VAULT_HTTP_CLIENT_CERT",
+                    Some("test_data/tls/client.pem")
+                ),
+                (
+                    "SSH_VAULT_HTTP_CLIENT_KEY",
+                    Some("test_data/tls/client.key")
+                ),
+            ])
+            .is_ok()
+        );
+
+        assert!(client(&[("SSH_VAULT_HTTP_CONNECT_TIMEOUT", Some("soon"))]).is_err());
+        assert!(client(&[("SSH_VAULT_HTTP_CA_CERTS", Some("test_data/tls/missing.pem"))]).is_err());
+        assert!(client(&[("SSH_VAULT_HTTP_CA_CERTS", Some("test_data/ed25519.pub"))]).is_err());
+        // the key is required
+        assert!(
+            client(&[(
+                "SSH_VAULT_HTTP_CLIENT_CERT",
+                Some("test_data/tls/client.pem")
+            )])
+            .is_err()
+        );
+        assert!(client(&[("SSH_VAULT_PROXY", Some("not a url"))]).is_err());
+    }
+}
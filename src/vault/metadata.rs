@@ -0,0 +1,185 @@
+use anyhow::{Result, anyhow};
+use base64ct::{Base64, Encoding};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Unix time (seconds) when the vault was created
+pub const CREATED: &str = "created";
+/// MIME type of the secret
+pub const CONTENT_TYPE: &str = "content-type";
+/// Name of the file the secret was read from
+pub const FILENAME: &str = "filename";
+/// Free-form label
+pub const LABEL: &str = "label";
+
+/// Optional metadata stored in the header of `SSH-VAULT-V2` vaults
+///
+/// The metadata is not encrypted but it is part of the associated data of the
+/// payload, any modification makes the vault fail to decrypt. Unknown keys are
+/// preserved so vaults created by newer versions can still be opened.
+///
+/// It is encoded as `key=<base64 value>` entries sorted by key and separated
+/// by `,`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata(BTreeMap<String, String>);
+
+impl Metadata {
+    /// Create metadata with the creation time set to now.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the system time is before the Unix epoch.
+    pub fn now() -> Result<Self> {
+        let mut metadata = Self::default();
+        metadata.insert(
+            CREATED,
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)?
+                .as_secs()
+                .to_string(),
+        );
+        Ok(metadata)
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub fn insert(&mut self, key: &str, value: impl Into<String>) {
+        self.0.insert(key.to_string(), value.into());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    #[must_use]
+    pub fn created(&self) -> Option<u64> {
+        self.get(CREATED).and_then(|created| created.parse().ok())
+    }
+
+    #[must_use]
+    pub fn content_type(&self) -> Option<&str> {
+        self.get(CONTENT_TYPE)
+    }
+
+    #[must_use]
+    pub fn filename(&self) -> Option<&str> {
+        self.get(FILENAME)
+    }
+
+    #[must_use]
+    pub fn label(&self) -> Option<&str> {
+        self.get(LABEL)
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<String> = self
+            .0
+            .iter()
+            .map(|(key, value)| format!("{key}={}", Base64::encode_string(value.as_bytes())))
+            .collect();
+
+        f.write_str(&entries.join(","))
+    }
+}
+
+impl FromStr for Metadata {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut metadata = Self::default();
+
+        if s.is_empty() {
+            return Ok(metadata);
+        }
+
+        for entry in s.split(',') {
+            let (key, value) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid vault metadata: {entry}"))?;
+
+            if key.is_empty()
+                || !key
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            {
+                return Err(anyhow!("Invalid vault metadata key: {key}"));
+            }
+
+            // keys must be sorted and unique so the encoding is canonical
+            if metadata
+                .0
+                .last_key_value()
+                .is_some_and(|(last, _)| last.as_str() >= key)
+            {
+                return Err(anyhow!("Invalid vault metadata: keys must be sorted"));
+            }
+
+            let value = String::from_utf8(Base64::decode_vec(value)?)?;
+            metadata.insert(key, value);
+        }
+
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata() {
+        let mut metadata = Metadata::now().unwrap();
+        metadata.insert(LABEL, "db password; prod, eu=1");
+        metadata.insert(FILENAME, "secret.txt");
+        metadata.insert(CONTENT_TYPE, "text/plain");
+
+        assert!(metadata.created().is_some());
+        assert_eq!(metadata.label(), Some("db password; prod, eu=1"));
+        assert_eq!(metadata.filename(), Some("secret.txt"));
+        assert_eq!(metadata.content_type(), Some("text/plain"));
+
+        let encoded = metadata.to_string();
+        assert!(!encoded.contains(';'));
+        assert_eq!(encoded.parse::<Metadata>().unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_metadata_empty() {
+        let metadata: Metadata = "".parse().unwrap();
+        assert!(metadata.is_empty());
+        assert_eq!(metadata.to_string(), "");
+    }
+
+    #[test]
+    fn test_metadata_unknown_keys() {
+        let metadata: Metadata = "label=dGVzdA==,x-custom=eA==".parse().unwrap();
+        assert_eq!(metadata.label(), Some("test"));
+        assert_eq!(metadata.get("x-custom"), Some("x"));
+        assert_eq!(metadata.to_string(), "label=dGVzdA==,x-custom=eA==");
+    }
+
+    #[test]
+    fn test_metadata_invalid() {
+        assert!("label".parse::<Metadata>().is_err());
+        assert!("=dGVzdA==".parse::<Metadata>().is_err());
+        assert!("Label=dGVzdA==".parse::<Metadata>().is_err());
+        assert!("label=???".parse::<Metadata>().is_err());
+        assert!("label=dGVzdA==,created=MQ==".parse::<Metadata>().is_err());
+        assert!("label=dGVzdA==,label=dGVzdA==".parse::<Metadata>().is_err());
+    }
+}
@@ -1,25 +1,60 @@
+pub mod agent;
 pub mod crypto;
 pub mod dio;
 pub mod find;
 pub mod fingerprint;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod keyserver;
+pub mod metadata;
+pub mod multi;
 pub mod online;
 pub mod remote;
+pub mod signature;
 pub mod ssh;
+pub mod stream;
+pub mod trust;
 
 pub mod parse;
-pub use self::parse::parse;
+pub use self::metadata::Metadata;
+pub use self::parse::{Algorithm, ParsedVault, Version, parse};
+pub use self::stream::{VaultReader, VaultWriter};
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use secrecy::SecretSlice;
 use ssh_key::{PrivateKey, PublicKey};
 
 /// SSH key types supported by ssh-vault
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SshKeyType {
     /// Ed25519 keys using X25519 Diffie-Hellman and ChaCha20-Poly1305
     Ed25519,
     /// RSA keys using RSA-OAEP and AES-256-GCM
     Rsa,
+    /// Ed25519 keys held by ssh-agent, the password is wrapped with a key
+    /// derived from a signature (`SSH-VAULT-V2` only)
+    Agent,
+    /// ECDSA keys (nistp256, nistp384, nistp521) using ECDH and
+    /// ChaCha20-Poly1305 (`SSH-VAULT-V2` only)
+    Ecdsa,
+}
+
+/// The vault password wrapped for a single recipient key
+///
+/// A recipient holds everything needed by the owner of the matching private
+/// key to recover the password used to encrypt the vault payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipient {
+    /// Key type used to wrap the password
+    pub key_type: SshKeyType,
+    /// Fingerprint of the recipient public key (MD5 for RSA, SHA256 otherwise)
+    pub fingerprint: String,
+    /// Ephemeral X25519 public key (Ed25519 recipients), ephemeral SEC1
+    /// public key (ECDSA recipients) or the challenge signed by the agent
+    /// (agent recipients)
+    pub ephemeral_key: Option<Vec<u8>>,
+    /// The encrypted password
+    pub wrapped_key: Vec<u8>,
 }
 
 /// Main vault interface for encrypting and decrypting data using SSH keys
@@ -36,7 +71,7 @@ impl SshVault {
     ///
     /// # Arguments
     ///
-    /// * `key_type` - The SSH key type (Ed25519 or RSA)
+    /// * `key_type` - The SSH key type (Ed25519, RSA, ECDSA or Ed25519 through ssh-agent)
     /// * `public` - Optional public key for encryption operations
     /// * `private` - Optional private key for decryption operations
     ///
@@ -72,6 +107,12 @@ impl SshVault {
             SshKeyType::Rsa => {
                 Box::new(ssh::rsa::RsaVault::new(public, private)?) as Box<dyn Vault>
             }
+            SshKeyType::Agent => {
+                Box::new(ssh::agent::AgentVault::new(public, private)?) as Box<dyn Vault>
+            }
+            SshKeyType::Ecdsa => {
+                Box::new(ssh::ecdsa::EcdsaVault::new(public, private)?) as Box<dyn Vault>
+            }
         };
         Ok(Self { vault })
     }
@@ -104,22 +145,73 @@ impl SshVault {
     ///
     /// # Arguments
     ///
-    /// * `password` - Encrypted password bytes from the vault
-    /// * `data` - Encrypted data bytes from the vault
-    /// * `fingerprint` - Expected key fingerprint for verification
+    /// * `vault` - The parsed vault
     ///
     /// # Returns
     ///
-    /// Returns the decrypted data as a UTF-8 string.
+    /// Returns the decrypted data as bytes, it may be binary. The bytes are
+    /// zeroized when dropped.
     ///
     /// # Errors
     ///
     /// Returns an error if:
-    /// - The fingerprint doesn't match the private key
+    /// - The private key fingerprint doesn't match any vault recipient
     /// - Decryption fails (wrong key or corrupted data)
-    /// - The decrypted data is not valid UTF-8
-    pub fn view(&self, password: &[u8], data: &[u8], fingerprint: &str) -> Result<String> {
-        self.vault.view(password, data, fingerprint)
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ssh_vault::vault::{ParsedVault, SshVault, SshKeyType};
+    /// use ssh_key::PrivateKey;
+    /// use std::path::Path;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let private_key = PrivateKey::read_openssh_file(Path::new("id_ed25519"))?;
+    /// let vault = SshVault::new(&SshKeyType::Ed25519, None, Some(private_key))?;
+    /// let parsed: ParsedVault = std::fs::read_to_string("secret.vault")?.parse()?;
+    /// let secret = vault.view(&parsed)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn view(&self, vault: &ParsedVault) -> Result<SecretSlice<u8>> {
+        self.vault.view(vault)
+    }
+
+    /// Recovers the password of the vault using the matching recipient
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no recipient matches the key or unwrapping fails.
+    pub fn password(&self, vault: &ParsedVault) -> Result<SecretSlice<u8>> {
+        self.vault.password(vault)
+    }
+
+    /// Returns the fingerprint of the vault key as stored in the vault header
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fingerprint cannot be computed.
+    pub fn fingerprint(&self) -> Result<String> {
+        self.vault.fingerprint()
+    }
+
+    /// Wraps the password for the vault key
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the password cannot be encrypted.
+    pub fn wrap(&self, password: &SecretSlice<u8>) -> Result<Recipient> {
+        self.vault.wrap(password)
+    }
+
+    /// Recovers the password from a recipient wrapped for the vault key
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the private key is missing, the recipient doesn't
+    /// match the key or decryption fails.
+    pub fn unwrap(&self, recipient: &Recipient) -> Result<SecretSlice<u8>> {
+        self.vault.unwrap(recipient)
     }
 }
 
@@ -134,19 +226,91 @@ pub trait Vault {
     where
         Self: Sized;
 
+    /// Returns the algorithm used for single recipient vaults
+    fn algorithm(&self) -> Algorithm;
+
+    /// Returns the fingerprint of the key as used in the vault header
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fingerprint cannot be computed.
+    fn fingerprint(&self) -> Result<String>;
+
+    /// Wraps (encrypts) the password for the key
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if encryption fails.
+    fn wrap(&self, password: &SecretSlice<u8>) -> Result<Recipient>;
+
+    /// Unwraps (decrypts) the password using the private key
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the private key is missing or decryption fails.
+    fn unwrap(&self, recipient: &Recipient) -> Result<SecretSlice<u8>>;
+
     /// Encrypts data and creates a vault string
     ///
     /// # Errors
     ///
     /// Returns an error if encryption fails.
-    fn create(&self, password: SecretSlice<u8>, data: &mut [u8]) -> Result<String>;
+    fn create(&self, password: SecretSlice<u8>, data: &mut [u8]) -> Result<String> {
+        let recipient = self.wrap(&password)?;
+        let vault = ParsedVault::seal(self.algorithm(), vec![recipient], &password, data)?;
+        Ok(vault.to_string())
+    }
 
-    /// Decrypts vault contents
+    /// Recovers the password from the recipient matching the key
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no recipient matches the key or unwrapping fails.
+    fn password(&self, vault: &ParsedVault) -> Result<SecretSlice<u8>> {
+        let fingerprint = self.fingerprint()?;
+
+        let recipient = vault
+            .recipient(&fingerprint)
+            .ok_or_else(|| anyhow!("Fingerprint mismatch, use correct key"))?;
+
+        self.unwrap(recipient)
+    }
+
+    /// Decrypts vault contents, the data may not be valid UTF-8
     ///
     /// # Errors
     ///
     /// Returns an error if decryption fails or the fingerprint is invalid.
-    fn view(&self, password: &[u8], data: &[u8], fingerprint: &str) -> Result<String>;
+    fn view(&self, vault: &ParsedVault) -> Result<SecretSlice<u8>> {
+        let password = self.password(vault)?;
+        Ok(SecretSlice::new(vault.decrypt(password)?.into()))
+    }
+}
+
+#[cfg(test)]
+#[allow(
+    clippy::unwrap_used,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate
+)]
+pub mod test_vaults {
+    use super::{SshVault, find};
+    use ssh_key::{PrivateKey, PublicKey};
+    use std::path::Path;
+
+    /// The vault encrypting to the public key file.
+    pub fn public_vault(key: &str) -> SshVault {
+        let public_key = PublicKey::read_openssh_file(Path::new(key)).unwrap();
+        let key_type = find::key_type(&public_key.algorithm()).unwrap();
+        SshVault::new(&key_type, Some(public_key), None).unwrap()
+    }
+
+    /// The vault decrypting with the private key file.
+    pub fn private_vault(key: &str) -> SshVault {
+        let private_key = PrivateKey::read_openssh_file(Path::new(key)).unwrap();
+        let key_type = find::key_type(&private_key.algorithm()).unwrap();
+        SshVault::new(&key_type, None, Some(private_key)).unwrap()
+    }
 }
 
 #[cfg(test)]
@@ -156,7 +320,7 @@ mod tests {
         Vault, crypto, parse, ssh::decrypt_private_key, ssh::ed25519::Ed25519Vault,
         ssh::rsa::RsaVault,
     };
-    use secrecy::{SecretSlice, SecretString};
+    use secrecy::{ExposeSecret, SecretSlice, SecretString};
     use ssh_key::PublicKey;
     use std::path::Path;
 
@@ -189,13 +353,14 @@ mod tests {
         // filled with zeros
         assert!(secret.iter().all(|&byte| byte == 0));
 
-        let (_key_type, fingerprint, password, data) = parse(&vault)?;
+        let vault = parse(&vault)?;
+        assert_eq!(vault.algorithm, Algorithm::Aes256);
 
         let view = RsaVault::new(None, Some(private_key))?;
 
-        let vault = view.view(&password, &data, &fingerprint)?;
+        let vault = view.view(&vault)?;
 
-        assert_eq!(vault, SECRET);
+        assert_eq!(vault.expose_secret(), SECRET.as_bytes());
         Ok(())
     }
 
@@ -220,13 +385,14 @@ mod tests {
         // filled with zeros
         assert!(secret.iter().all(|&byte| byte == 0));
 
-        let (_key_type, fingerprint, password, data) = parse(&vault)?;
+        let vault = parse(&vault)?;
+        assert_eq!(vault.algorithm, Algorithm::ChaCha20Poly1305);
 
         let view = Ed25519Vault::new(None, Some(private_key))?;
 
-        let vault = view.view(&password, &data, &fingerprint)?;
+        let vault = view.view(&vault)?;
 
-        assert_eq!(vault, SECRET);
+        assert_eq!(vault.expose_secret(), SECRET.as_bytes());
         Ok(())
     }
 
@@ -277,8 +443,12 @@ mod tests {
 
             // view
             let private_key = test.private_key.to_string();
-            let (key_type, fingerprint, password, data) = parse(&vault)?;
-            let mut private_key = find::private_key_type(Some(private_key), key_type)?;
+            let parsed: ParsedVault = vault.parse()?;
+
+            // the vault can be formatted back to the same string
+            assert_eq!(parsed.to_string(), vault);
+
+            let mut private_key = find::private_key_type(Some(private_key), &parsed.algorithm)?;
 
             if private_key.is_encrypted() {
                 private_key =
@@ -289,9 +459,34 @@ mod tests {
 
             let v = SshVault::new(&key_type, None, Some(private_key))?;
 
-            let vault = v.view(&password, &data, &fingerprint)?;
+            let vault = v.view(&parsed)?;
+
+            assert_eq!(vault.expose_secret(), SECRET.as_bytes());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_vault_binary() -> Result<()> {
+        // not valid UTF-8
+        let binary: Vec<u8> = (0..=255).rev().collect();
+        assert!(std::str::from_utf8(&binary).is_err());
+
+        for (public_key, private_key) in [
+            ("test_data/id_rsa.pub", "test_data/id_rsa"),
+            ("test_data/ed25519.pub", "test_data/ed25519"),
+        ] {
+            let public_key = PublicKey::read_openssh_file(Path::new(public_key))?;
+            let key_type = find::key_type(&public_key.algorithm())?;
+            let vault = SshVault::new(&key_type, Some(public_key), None)?;
+
+            let mut secret = binary.clone();
+            let vault = parse(&vault.create(crypto::gen_password()?, &mut secret)?)?;
+
+            let private_key = PrivateKey::read_openssh_file(Path::new(private_key))?;
+            let view = SshVault::new(&key_type, None, Some(private_key))?;
 
-            assert_eq!(vault, SECRET);
+            assert_eq!(view.view(&vault)?.expose_secret(), binary.as_slice());
         }
         Ok(())
     }
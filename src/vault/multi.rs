@@ -0,0 +1,191 @@
+use crate::vault::{Metadata, ParsedVault, Recipient, SshVault};
+use anyhow::{Result, anyhow};
+use secrecy::SecretSlice;
+
+/// Wrap the password for every vault key, skipping duplicated keys.
+///
+/// # Errors
+///
+/// Returns an error if no vault is provided or wrapping fails.
+pub fn recipients(vaults: &[SshVault], password: &SecretSlice<u8>) -> Result<Vec<Recipient>> {
+    let mut recipients: Vec<Recipient> = Vec::new();
+
+    for vault in vaults {
+        // skip keys that are already recipients
+        let fingerprint = vault.fingerprint()?;
+        if recipients.iter().any(|r| r.fingerprint == fingerprint) {
+            continue;
+        }
+        recipients.push(vault.wrap(password)?);
+    }
+
+    if recipients.is_empty() {
+        return Err(anyhow!("At least one recipient is required"));
+    }
+
+    Ok(recipients)
+}
+
+/// Encrypt the data once in a `SSH-VAULT-V2` vault and wrap the password for
+/// every vault key.
+///
+/// # Errors
+///
+/// Returns an error if no vault is provided or encryption fails.
+pub fn create(
+    vaults: &[SshVault],
+    metadata: Metadata,
+    password: &SecretSlice<u8>,
+    data: &mut [u8],
+) -> Result<String> {
+    let recipients = recipients(vaults, password)?;
+
+    Ok(ParsedVault::seal_v2(metadata, recipients, password, data)?.to_string())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::indexing_slicing)]
+mod tests {
+    use super::*;
+    use crate::vault::{
+        Algorithm, SshKeyType, Version, crypto, metadata, parse,
+        test_vaults::{private_vault, public_vault},
+    };
+    use secrecy::ExposeSecret;
+
+    const SECRET: &str =
+        "Not everything that can be counted counts, and not everything that counts can be counted";
+
+    #[test]
+    fn test_multi_vault() -> Result<()> {
+        let vaults = [
+            public_vault("test_data/id_rsa.pub"),
+            public_vault("test_data/ed25519.pub"),
+        ];
+
+        let mut secret = String::from(SECRET).into_bytes();
+        let vault = create(
+            &vaults,
+            Metadata::default(),
+            &crypto::gen_password()?,
+            &mut secret,
+        )?;
+
+        // filled with zeros
+        assert!(secret.iter().all(|&byte| byte == 0));
+        assert!(vault.starts_with("SSH-VAULT-V2;CHACHA20-POLY1305;;"));
+
+        let vault = parse(&vault)?;
+        assert_eq!(vault.version, Version::V2);
+        assert_eq!(vault.algorithm, Algorithm::ChaCha20Poly1305);
+        assert_eq!(vault.recipients.len(), 2);
+        assert_eq!(vault.recipients[0].key_type, SshKeyType::Rsa);
+        assert_eq!(vault.recipients[1].key_type, SshKeyType::Ed25519);
+
+        for key in ["test_data/id_rsa", "test_data/ed25519"] {
+            let view = private_vault(key).view(&vault)?;
+            assert_eq!(view.expose_secret(), SECRET.as_bytes());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_vault_duplicate_recipients() -> Result<()> {
+        let vaults = [
+            public_vault("test_data/ed25519.pub"),
+            public_vault("test_data/ed25519.pub"),
+        ];
+
+        let mut secret = String::from(SECRET).into_bytes();
+        let vault = create(
+            &vaults,
+            Metadata::default(),
+            &crypto::gen_password()?,
+            &mut secret,
+        )?;
+
+        assert_eq!(parse(&vault)?.recipients.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_vault_not_a_recipient() -> Result<()> {
+        let vaults = [public_vault("test_data/ed25519.pub")];
+
+        let mut secret = String::from(SECRET).into_bytes();
+        let vault = create(
+            &vaults,
+            Metadata::default(),
+            &crypto::gen_password()?,
+            &mut secret,
+        )?;
+
+        let view = private_vault("test_data/id_rsa").view(&parse(&vault)?);
+        assert!(view.is_err());
+        assert!(
+            view.unwrap_err()
+                .to_string()
+                .contains("Fingerprint mismatch")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_vault_tampered_recipients() -> Result<()> {
+        let vaults = [
+            public_vault("test_data/id_rsa.pub"),
+            public_vault("test_data/ed25519.pub"),
+        ];
+
+        let mut secret = String::from(SECRET).into_bytes();
+        let vault = create(
+            &vaults,
+            Metadata::default(),
+            &crypto::gen_password()?,
+            &mut secret,
+        )?;
+
+        // drop a recipient, the associated data no longer matches
+        let mut vault = parse(&vault)?;
+        vault.recipients.remove(0);
+        assert!(private_vault("test_data/ed25519").view(&vault).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_vault_metadata() -> Result<()> {
+        let vaults = [public_vault("test_data/ed25519.pub")];
+
+        let mut metadata = Metadata::now()?;
+        metadata.insert(metadata::LABEL, "db password");
+
+        let mut secret = String::from(SECRET).into_bytes();
+        let vault = create(
+            &vaults,
+            metadata.clone(),
+            &crypto::gen_password()?,
+            &mut secret,
+        )?;
+
+        let parsed = parse(&vault)?;
+        assert_eq!(parsed.metadata, metadata);
+        assert_eq!(
+            private_vault("test_data/ed25519")
+                .view(&parsed)?
+                .expose_secret(),
+            SECRET.as_bytes()
+        );
+
+        // the metadata is authenticated
+        let mut tampered = parsed.clone();
+        tampered.metadata.insert(metadata::LABEL, "other");
+        assert!(private_vault("test_data/ed25519").view(&tampered).is_err());
+
+        // the cipher can't be downgraded to the legacy format
+        let mut tampered = parsed;
+        tampered.version = Version::V1;
+        tampered.metadata = Metadata::default();
+        assert!(private_vault("test_data/ed25519").view(&tampered).is_err());
+        Ok(())
+    }
+}
@@ -1,9 +1,13 @@
-use anyhow::Result;
+use crate::{config, vault::remote};
+use anyhow::{Context, Result, anyhow};
 use base58::ToBase58;
-use ssh_key::{HashAlg, PublicKey};
-use std::fmt::Write;
+use rand::{TryRng, rngs::SysRng};
+use ssh_key::{HashAlg, LineEnding, PrivateKey, PublicKey, private::Ed25519Keypair};
+use url::Url;
+use zeroize::Zeroize;
 
-/// Build the URL for retrieving a generated private key helper.
+/// Build the URL for retrieving a generated private key helper, `key/<id>`
+/// next to the `sshkeys_online` endpoint (`https://ssh-keys.online/new`).
 ///
 /// # Errors
 ///
@@ -11,15 +15,70 @@ use std::fmt::Write;
 pub fn get_private_key_id(key: &PublicKey, user: &str) -> Result<String> {
     match user {
         "new" => {
-            let fingerprint = key.fingerprint(HashAlg::Sha256);
-            let mut url = String::from("https://ssh-keys.online/key/");
-            write!(url, "{}", fingerprint.as_bytes().to_base58())?;
-            Ok(url)
+            let endpoint = config::get()?
+                .get_string("sshkeys_online")
+                .unwrap_or_else(|_| String::from(remote::SSHKEYS_ONLINE));
+
+            let url = Url::parse(&endpoint)
+                .and_then(|url| url.join(&format!("key/{}", key_id(key))))
+                .with_context(|| format!("Invalid sshkeys_online {endpoint}"))?;
+
+            Ok(url.to_string())
         }
         _ => Ok(String::new()),
     }
 }
 
+/// The base58 SHA256 fingerprint identifying a generated key.
+#[must_use]
+pub fn key_id(key: &PublicKey) -> String {
+    key.fingerprint(HashAlg::Sha256).as_bytes().to_base58()
+}
+
+/// Generate an Ed25519 key for `-u new` without a key server.
+///
+/// # Errors
+///
+/// Returns an error if the random seed can't be generated.
+pub fn generate_key() -> Result<PrivateKey> {
+    let mut seed = [0_u8; 32];
+
+    SysRng
+        .try_fill_bytes(&mut seed)
+        .map_err(|err| anyhow!("Error generating random key: {err}"))?;
+
+    let mut key = PrivateKey::from(Ed25519Keypair::from_seed(&seed));
+    seed.zeroize();
+
+    key.set_comment("ssh-vault");
+
+    Ok(key)
+}
+
+/// Upload a generated private key to `<new_key_upload>/<key id>` with a PUT
+/// request, returns the URL from where the key can be fetched.
+///
+/// # Errors
+///
+/// Returns an error if `new_key_upload` is not configured or the upload
+/// fails.
+pub fn upload_key(key: &PrivateKey) -> Result<String> {
+    let endpoint = config::get()?
+        .get_string("new_key_upload")
+        .map_err(|_| anyhow!("Set new_key_upload in config.yml to upload the private key"))?;
+
+    let url = Url::parse(&format!(
+        "{}/{}",
+        endpoint.trim_end_matches('/'),
+        key_id(key.public_key())
+    ))
+    .with_context(|| format!("Invalid new_key_upload {endpoint}"))?;
+
+    remote::upload(&url, &key.to_openssh(LineEnding::LF)?)?;
+
+    Ok(url.to_string())
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
@@ -32,10 +91,38 @@ mod tests {
             "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIIlI7XymEyB/xiPQGEuiIzt7z5VDNDzuYpr3v6+hbyDN",
         )
         .unwrap();
-        let id = get_private_key_id(&key, "new").unwrap();
-        assert_eq!(
-            id,
-            "https://ssh-keys.online/key/59fKS1A4ZEQysHCbWSKUkR4n3a9pfN8g8BLwrp1eVJis"
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_SSHKEYS_ONLINE", None),
+            ],
+            || {
+                let id = get_private_key_id(&key, "new").unwrap();
+                assert_eq!(
+                    id,
+                    "https://ssh-keys.online/key/59fKS1A4ZEQysHCbWSKUkR4n3a9pfN8g8BLwrp1eVJis"
+                );
+            },
+        );
+
+        // the key is fetched from the configured server
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                (
+                    "SSH_VAULT_SSHKEYS_ONLINE",
+                    Some("http://127.0.0.1:8080/vault/new".as_ref()),
+                ),
+            ],
+            || {
+                let id = get_private_key_id(&key, "new").unwrap();
+                assert_eq!(
+                    id,
+                    "http://127.0.0.1:8080/vault/key/59fKS1A4ZEQysHCbWSKUkR4n3a9pfN8g8BLwrp1eVJis"
+                );
+            },
         );
     }
 
@@ -48,4 +135,32 @@ mod tests {
         let id = get_private_key_id(&key, "random").unwrap();
         assert_eq!(id, "");
     }
+
+    #[test]
+    fn test_generate_key() {
+        let key = generate_key().unwrap();
+        assert_eq!(key.algorithm(), ssh_key::Algorithm::Ed25519);
+        assert_ne!(key.public_key(), generate_key().unwrap().public_key());
+        assert!(
+            key_id(key.public_key())
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric())
+        );
+    }
+
+    #[test]
+    fn test_upload_key_not_configured() {
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_NEW_KEY_UPLOAD", None),
+            ],
+            || {
+                let err = upload_key(&generate_key().unwrap()).unwrap_err();
+                assert!(err.to_string().contains("new_key_upload"));
+            },
+        );
+    }
 }
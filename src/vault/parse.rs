@@ -1,96 +1,489 @@
+use crate::vault::{
+    Recipient, SshKeyType,
+    crypto::{
+        Crypto, aes256::Aes256Crypto, chacha20poly1305::ChaCha20Poly1305Crypto,
+        stream::ChaCha20Poly1305StreamCrypto,
+    },
+    metadata::Metadata,
+    signature,
+};
 use anyhow::{Result, anyhow};
 use base64ct::{Base64, Encoding};
+use secrecy::SecretSlice;
+use std::{fmt, str::FromStr};
+use zeroize::Zeroize;
+
+/// Algorithm declared in the vault header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// AES-256-GCM payload, password wrapped with RSA-OAEP
+    Aes256,
+    /// ChaCha20-Poly1305 payload, password wrapped with X25519 and HKDF
+    ChaCha20Poly1305,
+    /// ChaCha20-Poly1305 payload encrypted in chunks (V2 only)
+    ChaCha20Poly1305Stream,
+}
+
+impl Algorithm {
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Aes256 => "AES256",
+            Self::ChaCha20Poly1305 => "CHACHA20-POLY1305",
+            Self::ChaCha20Poly1305Stream => "CHACHA20-POLY1305-STREAM",
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "AES256" => Ok(Self::Aes256),
+            "CHACHA20-POLY1305" => Ok(Self::ChaCha20Poly1305),
+            "CHACHA20-POLY1305-STREAM" => Ok(Self::ChaCha20Poly1305Stream),
+            _ => Err(anyhow!("Not a valid SSH-VAULT file")),
+        }
+    }
+}
+
+/// Version of the vault format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    /// `SSH-VAULT;<algorithm>;...`, only the fingerprints are authenticated
+    V1,
+    /// `SSH-VAULT-V2;<cipher>;<metadata>;<recipient>;...;<data>`, the whole
+    /// header is authenticated
+    V2,
+}
+
+/// The components of an SSH-VAULT
+///
+/// `AES256` and `CHACHA20-POLY1305` vaults have exactly one recipient,
+/// `SSH-VAULT-V2` vaults have one or more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedVault {
+    /// Version of the vault format
+    pub version: Version,
+    /// Algorithm declared in the vault header, the payload cipher for V2
+    pub algorithm: Algorithm,
+    /// Metadata bound to the payload (V2 only)
+    pub metadata: Metadata,
+    /// The password wrapped for each recipient key
+    pub recipients: Vec<Recipient>,
+    /// The encrypted payload including the nonce
+    pub ciphertext: Vec<u8>,
+}
+
+impl ParsedVault {
+    /// Encrypt the data with the password, the password must be the one
+    /// wrapped in the recipients.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recipients don't match the algorithm or
+    /// encryption fails.
+    pub fn seal(
+        algorithm: Algorithm,
+        recipients: Vec<Recipient>,
+        password: &SecretSlice<u8>,
+        data: &mut [u8],
+    ) -> Result<Self> {
+        Self {
+            version: Version::V1,
+            algorithm,
+            metadata: Metadata::default(),
+            recipients,
+            ciphertext: Vec::new(),
+        }
+        .reseal(password, data)
+    }
+
+    /// Encrypt the data in a `SSH-VAULT-V2` vault, the metadata and the
+    /// recipients are authenticated along with the payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no recipients or encryption fails.
+    pub fn seal_v2(
+        metadata: Metadata,
+        recipients: Vec<Recipient>,
+        password: &SecretSlice<u8>,
+        data: &mut [u8],
+    ) -> Result<Self> {
+        Self {
+            version: Version::V2,
+            algorithm: Algorithm::ChaCha20Poly1305,
+            metadata,
+            recipients,
+            ciphertext: Vec::new(),
+        }
+        .reseal(password, data)
+    }
+
+    /// Replace the payload keeping the header, the password must be the one
+    /// wrapped in the recipients.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header is not valid or encryption fails.
+    pub fn reseal(mut self, password: &SecretSlice<u8>, data: &mut [u8]) -> Result<Self> {
+        let valid = match (self.version, self.algorithm) {
+            (Version::V1, Algorithm::Aes256) => {
+                matches!(self.recipients.as_slice(), [r] if r.key_type == SshKeyType::Rsa)
+            }
+            (Version::V1, Algorithm::ChaCha20Poly1305) => {
+                matches!(self.recipients.as_slice(), [r] if r.key_type == SshKeyType::Ed25519)
+            }
+            (Version::V2, Algorithm::ChaCha20Poly1305 | Algorithm::ChaCha20Poly1305Stream) => {
+                !self.recipients.is_empty()
+            }
+            (Version::V1, Algorithm::ChaCha20Poly1305Stream) | (Version::V2, Algorithm::Aes256) => {
+                false
+            }
+        };
+
+        if !valid || (self.version == Version::V1 && !self.metadata.is_empty()) {
+            return Err(anyhow!("Invalid recipients for {} vault", self.algorithm));
+        }
+
+        let aad = self.aad();
+
+        self.ciphertext = match self.algorithm {
+            Algorithm::Aes256 => Aes256Crypto::new(password.clone()).encrypt(data, aad.as_bytes()),
+            Algorithm::ChaCha20Poly1305 => {
+                ChaCha20Poly1305Crypto::new(password.clone()).encrypt(data, aad.as_bytes())
+            }
+            Algorithm::ChaCha20Poly1305Stream => {
+                ChaCha20Poly1305StreamCrypto::new(password.clone()).encrypt(data, aad.as_bytes())
+            }
+        }?;
+
+        // zeroize data
+        data.zeroize();
+
+        Ok(self)
+    }
+
+    /// Decrypt the payload with the password recovered from a recipient.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if authentication or decryption fails.
+    pub fn decrypt(&self, password: SecretSlice<u8>) -> Result<Vec<u8>> {
+        let aad = self.aad();
+
+        match self.algorithm {
+            Algorithm::Aes256 => {
+                Aes256Crypto::new(password).decrypt(&self.ciphertext, aad.as_bytes())
+            }
+            Algorithm::ChaCha20Poly1305 => {
+                ChaCha20Poly1305Crypto::new(password).decrypt(&self.ciphertext, aad.as_bytes())
+            }
+            Algorithm::ChaCha20Poly1305Stream => ChaCha20Poly1305StreamCrypto::new(password)
+                .decrypt(&self.ciphertext, aad.as_bytes()),
+        }
+    }
+
+    /// Find the recipient with the given fingerprint.
+    #[must_use]
+    pub fn recipient(&self, fingerprint: &str) -> Option<&Recipient> {
+        self.recipients
+            .iter()
+            .find(|recipient| recipient.fingerprint == fingerprint)
+    }
+
+    // V1: the associated data is the fingerprint of the recipients, for single
+    // recipient vaults this is just the key fingerprint.
+    // V2: the associated data is the whole header.
+    fn aad(&self) -> String {
+        match self.version {
+            Version::V1 => self
+                .recipients
+                .iter()
+                .map(|recipient| recipient.fingerprint.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+            Version::V2 => self.header(),
+        }
+    }
+
+    /// The `SSH-VAULT-V2` header used as associated data:
+    /// `SSH-VAULT-V2;<cipher>;<metadata>;<recipient>;...`
+    #[must_use]
+    pub fn header(&self) -> String {
+        let mut header = format!("SSH-VAULT-V2;{};{}", self.algorithm, self.metadata);
+
+        for recipient in &self.recipients {
+            header.push(';');
+            header.push_str(&stanza(recipient));
+        }
+
+        header
+    }
+}
+
+// <algorithm>,<fingerprint>,[<ephemeral key>|<challenge>,]<wrapped key>
+fn stanza(recipient: &Recipient) -> String {
+    match recipient.key_type {
+        SshKeyType::Rsa => format!(
+            "AES256,{},{}",
+            recipient.fingerprint,
+            Base64::encode_string(&recipient.wrapped_key)
+        ),
+        SshKeyType::Ed25519 => format!(
+            "CHACHA20-POLY1305,{},{},{}",
+            recipient.fingerprint,
+            Base64::encode_string(recipient.ephemeral_key.as_deref().unwrap_or_default()),
+            Base64::encode_string(&recipient.wrapped_key)
+        ),
+        SshKeyType::Agent => format!(
+            "AGENT,{},{},{}",
+            recipient.fingerprint,
+            Base64::encode_string(recipient.ephemeral_key.as_deref().unwrap_or_default()),
+            Base64::encode_string(&recipient.wrapped_key)
+        ),
+        SshKeyType::Ecdsa => format!(
+            "ECDH,{},{},{}",
+            recipient.fingerprint,
+            Base64::encode_string(recipient.ephemeral_key.as_deref().unwrap_or_default()),
+            Base64::encode_string(&recipient.wrapped_key)
+        ),
+    }
+}
+
+// split the vault in lines of 64 chars
+fn wrap_lines(data: &str) -> String {
+    data.chars()
+        .collect::<Vec<_>>()
+        .chunks(64)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl fmt::Display for ParsedVault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ciphertext = Base64::encode_string(&self.ciphertext);
+
+        if self.version == Version::V2 {
+            // streams keep the header in the first line so it can be read
+            // before the payload
+            return if self.algorithm == Algorithm::ChaCha20Poly1305Stream {
+                write!(f, "{};\n{}", self.header(), wrap_lines(&ciphertext))
+            } else {
+                f.write_str(&wrap_lines(&format!("{};{ciphertext}", self.header())))
+            };
+        }
+
+        match self.algorithm {
+            Algorithm::Aes256 => {
+                let (fingerprint, wrapped_key) = self
+                    .recipients
+                    .first()
+                    .map(|r| {
+                        (
+                            r.fingerprint.as_str(),
+                            Base64::encode_string(&r.wrapped_key),
+                        )
+                    })
+                    .unwrap_or_default();
+
+                write!(
+                    f,
+                    "SSH-VAULT;AES256;{fingerprint}\n{}",
+                    wrap_lines(&format!("{wrapped_key};{ciphertext}"))
+                )
+            }
+            Algorithm::ChaCha20Poly1305 => {
+                let recipient = self
+                    .recipients
+                    .first()
+                    .map(|r| {
+                        format!(
+                            "{};{};{}",
+                            r.fingerprint,
+                            Base64::encode_string(r.ephemeral_key.as_deref().unwrap_or_default()),
+                            Base64::encode_string(&r.wrapped_key)
+                        )
+                    })
+                    .unwrap_or_default();
+
+                f.write_str(&wrap_lines(&format!(
+                    "SSH-VAULT;CHACHA20-POLY1305;{recipient};{ciphertext}"
+                )))
+            }
+            // streams are V2 only
+            Algorithm::ChaCha20Poly1305Stream => Err(fmt::Error),
+        }
+    }
+}
+
+impl FromStr for ParsedVault {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse(s)
+    }
+}
 
 /// Check if it's a valid SSH-VAULT file and return the parsed components.
+/// The signature of a signed vault is ignored, see [`signature::verify`].
 ///
 /// # Errors
 ///
 /// Returns an error if the input is malformed or any Base64 decoding fails.
-pub fn parse(data: &str) -> Result<(&str, String, Vec<u8>, Vec<u8>)> {
-    let tokens: Vec<_> = data.split(';').collect();
+pub fn parse(data: &str) -> Result<ParsedVault> {
+    let (data, _) = signature::split(data);
+
+    let tokens: Vec<_> = data.trim().split(';').collect();
 
     let vault_marker = tokens
         .first()
         .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?;
-    let algorithm = tokens
+
+    if *vault_marker == "SSH-VAULT-V2" {
+        return parse_v2(&tokens);
+    }
+
+    let token = |index: usize| token(&tokens, index);
+
+    let algorithm: Algorithm = tokens
         .get(1)
-        .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?;
+        .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?
+        .parse()?;
 
-    if *vault_marker != "SSH-VAULT" || (*algorithm != "AES256" && *algorithm != "CHACHA20-POLY1305")
-    {
+    if *vault_marker != "SSH-VAULT" {
         return Err(anyhow!("Not a valid SSH-VAULT file"));
     }
 
-    if *algorithm == "AES256" {
-        if tokens.len() != 4 {
-            return Err(anyhow!("Not a valid SSH-VAULT file"));
-        }
+    match algorithm {
+        Algorithm::Aes256 => {
+            if tokens.len() != 4 {
+                return Err(anyhow!("Not a valid SSH-VAULT file"));
+            }
 
-        let mut lines = tokens
-            .get(2)
-            .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?
-            .lines();
+            let mut lines = tokens
+                .get(2)
+                .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?
+                .lines();
 
-        let fingerprint = lines
-            .next()
-            .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?;
+            let fingerprint = lines
+                .next()
+                .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?;
 
-        let password = lines.collect::<Vec<&str>>().join("");
-        let password = Base64::decode_vec(&password)?;
+            let password = lines.collect::<Vec<&str>>().join("");
 
-        lines = tokens
-            .get(3)
-            .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?
-            .lines();
-
-        let data = lines.collect::<Vec<&str>>().join("");
-        let data = Base64::decode_vec(&data)?;
+            Ok(ParsedVault {
+                version: Version::V1,
+                algorithm,
+                metadata: Metadata::default(),
+                recipients: vec![Recipient {
+                    key_type: SshKeyType::Rsa,
+                    fingerprint: fingerprint.to_string(),
+                    ephemeral_key: None,
+                    wrapped_key: Base64::decode_vec(&password)?,
+                }],
+                ciphertext: Base64::decode_vec(&token(3)?)?,
+            })
+        }
+        Algorithm::ChaCha20Poly1305 => {
+            if tokens.len() != 6 {
+                return Err(anyhow!("Not a valid SSH-VAULT file"));
+            }
 
-        return Ok((algorithm, fingerprint.to_string(), password, data));
-    } else if *algorithm == "CHACHA20-POLY1305" {
-        if tokens.len() != 6 {
-            return Err(anyhow!("Not a valid SSH-VAULT file"));
+            Ok(ParsedVault {
+                version: Version::V1,
+                algorithm,
+                metadata: Metadata::default(),
+                recipients: vec![Recipient {
+                    key_type: SshKeyType::Ed25519,
+                    fingerprint: token(2)?,
+                    ephemeral_key: Some(Base64::decode_vec(&token(3)?)?),
+                    wrapped_key: Base64::decode_vec(&token(4)?)?,
+                }],
+                ciphertext: Base64::decode_vec(&token(5)?)?,
+            })
         }
+        Algorithm::ChaCha20Poly1305Stream => Err(anyhow!("Not a valid SSH-VAULT file")),
+    }
+}
+
+// SSH-VAULT-V2;<cipher>;<metadata>;<recipient>;...;<data>
+fn parse_v2(tokens: &[&str]) -> Result<ParsedVault> {
+    if tokens.len() < 5 {
+        return Err(anyhow!("Not a valid SSH-VAULT file"));
+    }
 
-        let fingerprint = tokens
-            .get(2)
-            .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?
-            .lines()
-            .collect::<Vec<&str>>()
-            .join("");
+    let algorithm: Algorithm = token(tokens, 1)?.parse()?;
+    if !matches!(
+        algorithm,
+        Algorithm::ChaCha20Poly1305 | Algorithm::ChaCha20Poly1305Stream
+    ) {
+        return Err(anyhow!("Unsupported SSH-VAULT-V2 cipher: {algorithm}"));
+    }
 
-        let epk = tokens
-            .get(3)
-            .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?
-            .lines()
-            .collect::<Vec<&str>>()
-            .join("");
-        let epk = Base64::decode_vec(&epk)?;
+    let recipients = (3..tokens.len() - 1)
+        .map(|index| parse_recipient(&token(tokens, index)?))
+        .collect::<Result<Vec<_>>>()?;
 
-        let password = tokens
-            .get(4)
-            .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?
-            .lines()
-            .collect::<Vec<&str>>()
-            .join("");
-        let password = Base64::decode_vec(&password)?;
+    Ok(ParsedVault {
+        version: Version::V2,
+        algorithm,
+        metadata: token(tokens, 2)?.parse()?,
+        recipients,
+        ciphertext: Base64::decode_vec(&token(tokens, tokens.len() - 1)?)?,
+    })
+}
 
-        let mut epk_and_password = Vec::new();
-        epk_and_password.extend_from_slice(&epk);
-        epk_and_password.extend_from_slice(&password);
+// join the lines of a token
+fn token(tokens: &[&str], index: usize) -> Result<String> {
+    Ok(tokens
+        .get(index)
+        .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?
+        .lines()
+        .collect::<Vec<&str>>()
+        .join(""))
+}
 
-        let data = tokens
-            .get(5)
-            .ok_or_else(|| anyhow!("Not a valid SSH-VAULT file"))?
-            .lines()
-            .collect::<Vec<&str>>()
-            .join("");
-        let data = Base64::decode_vec(&data)?;
+// <algorithm>,<fingerprint>,[<ephemeral key>,]<wrapped key>
+fn parse_recipient(recipient: &str) -> Result<Recipient> {
+    let fields: Vec<&str> = recipient.split(',').collect();
 
-        return Ok((algorithm, fingerprint, epk_and_password, data));
+    match fields.as_slice() {
+        ["AES256", fingerprint, wrapped_key] => Ok(Recipient {
+            key_type: SshKeyType::Rsa,
+            fingerprint: (*fingerprint).to_string(),
+            ephemeral_key: None,
+            wrapped_key: Base64::decode_vec(wrapped_key)?,
+        }),
+        ["CHACHA20-POLY1305", fingerprint, ephemeral_key, wrapped_key] => Ok(Recipient {
+            key_type: SshKeyType::Ed25519,
+            fingerprint: (*fingerprint).to_string(),
+            ephemeral_key: Some(Base64::decode_vec(ephemeral_key)?),
+            wrapped_key: Base64::decode_vec(wrapped_key)?,
+        }),
+        ["AGENT", fingerprint, challenge, wrapped_key] => Ok(Recipient {
+            key_type: SshKeyType::Agent,
+            fingerprint: (*fingerprint).to_string(),
+            ephemeral_key: Some(Base64::decode_vec(challenge)?),
+            wrapped_key: Base64::decode_vec(wrapped_key)?,
+        }),
+        ["ECDH", fingerprint, ephemeral_key, wrapped_key] => Ok(Recipient {
+            key_type: SshKeyType::Ecdsa,
+            fingerprint: (*fingerprint).to_string(),
+            ephemeral_key: Some(Base64::decode_vec(ephemeral_key)?),
+            wrapped_key: Base64::decode_vec(wrapped_key)?,
+        }),
+        _ => Err(anyhow!("Not a valid SSH-VAULT recipient")),
     }
-
-    Err(anyhow!("Not a valid SSH-VAULT file"))
 }
 
 #[cfg(test)]
@@ -192,4 +585,95 @@ pqwAZIHYhzss";
                 .contains("Not a valid SSH-VAULT file")
         );
     }
+
+    #[test]
+    fn test_parse_invalid_multi() {
+        assert!(parse("SSH-VAULT;MULTI").is_err());
+        assert!(parse("SSH-VAULT;MULTI;AAAA").is_err());
+        assert!(parse("SSH-VAULT;MULTI;RSA,x,AAAA;AAAA").is_err());
+        assert!(parse("SSH-VAULT;MULTI;AES256,x;AAAA").is_err());
+
+        // multiple recipients are SSH-VAULT-V2 only
+        assert!(parse("SSH-VAULT;MULTI;AES256,x,AAAA;CHACHA20-POLY1305,y,AAAA,AAAA;AAAA").is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid_v2() {
+        assert!(parse("SSH-VAULT-V2;CHACHA20-POLY1305;;AAAA").is_err());
+        assert!(parse("SSH-VAULT-V2;AES256;;AES256,x,AAAA;AAAA").is_err());
+        assert!(parse("SSH-VAULT-V2;MULTI;;AES256,x,AAAA;AAAA").is_err());
+        assert!(parse("SSH-VAULT-V2;CHACHA20-POLY1305;label;AES256,x,AAAA;AAAA").is_err());
+        assert!(parse("SSH-VAULT-V2;CHACHA20-POLY1305;;AES256,x;AAAA").is_err());
+    }
+
+    #[test]
+    fn test_parse_v2() {
+        // tokens can be split across lines
+        let data = "SSH-VAULT-V2;CHACHA20-POLY1305;label=dGVzdA==;AES256,x,AAAA;CHAC
+HA20-POLY1305,y,AAAA,AAAA;AAAA";
+        let vault = parse(data).unwrap();
+        assert_eq!(vault.version, Version::V2);
+        assert_eq!(vault.algorithm, Algorithm::ChaCha20Poly1305);
+        assert_eq!(vault.metadata.label(), Some("test"));
+        assert_eq!(vault.recipients.len(), 2);
+        assert_eq!(
+            vault.recipient("y").map(|r| r.key_type),
+            Some(SshKeyType::Ed25519)
+        );
+    }
+
+    #[test]
+    fn test_algorithm() {
+        for algorithm in [
+            Algorithm::Aes256,
+            Algorithm::ChaCha20Poly1305,
+            Algorithm::ChaCha20Poly1305Stream,
+        ] {
+            assert_eq!(
+                algorithm.to_string().parse::<Algorithm>().unwrap(),
+                algorithm
+            );
+        }
+        assert!("RSA".parse::<Algorithm>().is_err());
+        assert!("MULTI".parse::<Algorithm>().is_err());
+    }
+
+    #[test]
+    fn test_seal_invalid_recipients() {
+        let recipient = Recipient {
+            key_type: SshKeyType::Ed25519,
+            fingerprint: "SHA256:test".to_string(),
+            ephemeral_key: Some(vec![0u8; 32]),
+            wrapped_key: vec![0u8; 48],
+        };
+        let password = SecretSlice::new(vec![0u8; 32].into());
+
+        assert!(
+            ParsedVault::seal(
+                Algorithm::Aes256,
+                vec![recipient.clone()],
+                &password,
+                &mut []
+            )
+            .is_err()
+        );
+        assert!(
+            ParsedVault::seal(
+                Algorithm::ChaCha20Poly1305Stream,
+                vec![recipient.clone()],
+                &password,
+                &mut []
+            )
+            .is_err()
+        );
+        assert!(
+            ParsedVault::seal(
+                Algorithm::ChaCha20Poly1305,
+                vec![recipient.clone(), recipient],
+                &password,
+                &mut []
+            )
+            .is_err()
+        );
+    }
 }
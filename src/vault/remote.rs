@@ -1,43 +1,165 @@
-use crate::{cache, config, tools, vault::fingerprint};
+#[cfg(feature = "http")]
+use crate::vault::http;
+use crate::{
+    cache, config, tools,
+    vault::{fingerprint, ssh},
+};
 use anyhow::{Result, anyhow};
-use reqwest::header::HeaderMap;
 use rsa::RsaPublicKey;
 use ssh_key::{HashAlg, PublicKey};
-use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use url::Url;
 
 const GITHUB_BASE_URL: &str = "https://github.com";
-const SSHKEYS_ONLINE: &str = "https://ssh-keys.online/new";
+/// The default `sshkeys_online` endpoint generating the keys of `-u new`.
+pub const SSHKEYS_ONLINE: &str = "https://ssh-keys.online/new";
+
+// the key providers used as `<provider>:<user>` and their default base URL,
+// it can be changed in config.yml with `providers.<provider>`
+const PROVIDERS: [(&str, &str); 5] = [
+    ("github", GITHUB_BASE_URL),
+    ("gitlab", "https://gitlab.com"),
+    ("codeberg", "https://codeberg.org"),
+    ("srht", "https://meta.sr.ht"),
+    ("lp", "https://launchpad.net"),
+];
+
+// self-hosted Gitea `gitea@<host>:<user>`, {host} is replaced by the host
+const GITEA_BASE_URL: &str = "https://{host}";
+
+// set by the --offline flag
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Enable the offline mode for this process.
+pub fn set_offline() {
+    OFFLINE.store(true, Ordering::Relaxed);
+}
+
+/// Return true when no request must be sent: with `--offline`, the `offline`
+/// setting or when built without the `http` feature.
+///
+/// # Errors
+///
+/// Returns an error if the configuration can't be loaded.
+pub fn is_offline() -> Result<bool> {
+    Ok(!cfg!(feature = "http")
+        || OFFLINE.load(Ordering::Relaxed)
+        || config::get()?.get_bool("offline").unwrap_or(false))
+}
 
-/// Fetch the ssh keys from GitHub or configured endpoint.
+/// Fetch the ssh keys from GitHub, another key provider (`gitlab:alice`,
+/// `codeberg:bob`, `srht:~carol`, `lp:dave`, `gitea@git.example.com:erin`)
+/// or configured endpoint.
 ///
 /// # Errors
 ///
-/// Returns an error if the URL is invalid or the request fails.
+/// Returns an error if the URL is invalid, the provider is unknown or the
+/// request fails.
 pub fn get_keys(user: &str) -> Result<String> {
-    let mut cache = true;
+    let (url, cache) = keys_url(user)?;
 
-    let url = if user.starts_with("http://") || user.starts_with("https://") {
-        Url::parse(user)?
-    } else if user == "new" {
-        cache = false;
+    request(url.as_str(), cache)
+}
+
+/// Fetch the ssh keys of a user skipping the cache, the cache is updated with
+/// the response.
+///
+/// # Errors
+///
+/// Returns an error if the URL is invalid, the provider is unknown or the
+/// request fails.
+pub fn refresh_keys(user: &str) -> Result<String> {
+    let (url, cache) = keys_url(user)?;
+
+    http::get(&url, cache.then(|| url_cache_key(&url)).as_deref())
+}
 
+// the URL with the keys and whether the response can be cached
+fn keys_url(user: &str) -> Result<(Url, bool)> {
+    if user.starts_with("http://") || user.starts_with("https://") {
+        Ok((Url::parse(user)?, true))
+    } else if user == "new" {
         // get the config from ~/.config/ssh-vault/config.yml
         let config = config::get()?;
 
-        Url::parse(
+        let url = Url::parse(
             &config
                 .get_string("sshkeys_online")
                 .unwrap_or_else(|_| String::from(SSHKEYS_ONLINE)),
-        )?
+        )?;
+
+        Ok((url, false))
     } else {
-        Url::parse(&format!("{GITHUB_BASE_URL}/{user}.keys"))?
+        Ok((user_url(user)?, true))
+    }
+}
+
+// the URL with the keys of a user, without a provider GitHub is used
+fn user_url(user: &str) -> Result<Url> {
+    let config = config::get()?;
+
+    let base_url = |provider: &str, default: &str| {
+        config
+            .get_string(&format!("providers.{provider}"))
+            .unwrap_or_else(|_| default.to_string())
+            .trim_end_matches('/')
+            .to_string()
     };
 
-    request(url.as_str(), cache)
+    let (provider, name) = user.split_once(':').unwrap_or(("github", user));
+
+    if name.is_empty() {
+        return Err(anyhow!("Missing user name in {user}"));
+    }
+
+    if let Some(host) = provider.strip_prefix("gitea@") {
+        let base_url = base_url("gitea", GITEA_BASE_URL).replace("{host}", host);
+        return Ok(Url::parse(&format!("{base_url}/{name}.keys"))?);
+    }
+
+    let Some((_, default)) = PROVIDERS.iter().find(|(p, _)| *p == provider) else {
+        return Err(anyhow!(
+            "Unknown key provider {provider}, use github, gitlab, codeberg, srht, lp or gitea@<host>"
+        ));
+    };
+
+    let base_url = base_url(provider, default);
+
+    let url = match provider {
+        "srht" => format!("{base_url}/~{}.keys", name.trim_start_matches('~')),
+        "lp" => format!("{base_url}/~{}/+sshkeys", name.trim_start_matches('~')),
+        _ => format!("{base_url}/{name}.keys"),
+    };
+
+    Ok(Url::parse(&url)?)
 }
 
-/// Perform a GET request and optionally cache the response.
+/// The provider and name of a user, `alice` and `github:alice` are both
+/// `github:alice`, `srht:carol` is `srht:~carol`. URLs are only normalised.
+///
+/// # Errors
+///
+/// Returns an error if the URL is invalid or the provider is unknown.
+pub fn user_id(user: &str) -> Result<String> {
+    if user.starts_with("http://") || user.starts_with("https://") {
+        return Ok(Url::parse(user)?.to_string());
+    }
+
+    // fails for an unknown provider or a missing name
+    user_url(user)?;
+
+    let (provider, name) = user.split_once(':').unwrap_or(("github", user));
+
+    Ok(match provider {
+        "srht" | "lp" => format!("{provider}:~{}", name.trim_start_matches('~')),
+        _ => format!("{provider}:{name}"),
+    })
+}
+
+/// Perform a GET request and optionally cache the response. Cached responses
+/// are used until `cache_ttl` expires, then revalidated with the `ETag` or
+/// `Last-Modified` of the response. Without `cache` the cache is neither read
+/// nor written, for one-time and private responses.
 ///
 /// # Errors
 ///
@@ -46,66 +168,105 @@ pub fn get_keys(user: &str) -> Result<String> {
 pub fn request(url: &str, cache: bool) -> Result<String> {
     let url = Url::parse(url)?;
 
-    let cache_key = format!("{:x}", md5::compute(url.as_str().as_bytes()));
+    if !cache {
+        return http::get(&url, None);
+    }
+
+    let key = url_cache_key(&url);
 
     // load from cache
-    if let Ok(key) = cache::get(&cache_key) {
-        Ok(key)
-    } else {
-        // get the headers
-        let headers: HeaderMap = get_headers()?;
+    if let Ok(body) = cache::get(&key) {
+        return Ok(body);
+    }
 
-        // Create a client
-        let client = reqwest::blocking::Client::builder()
-            .user_agent("ssh-vault")
-            .default_headers(headers)
-            .build()?;
+    // offline the expired entries are still used
+    if is_offline()? {
+        return cache::read(&key).map_err(|_| anyhow!("Offline mode, {url} is not in the cache"));
+    }
 
-        // Make a GET request
-        let res = client.get(url).send()?;
+    http::revalidate(&url, &key)
+}
 
-        if res.status().is_success() {
-            // Read the response body
-            let body = res.text()?;
+/// Perform a GET request skipping the cache, the cache is updated with the
+/// response.
+///
+/// # Errors
+///
+/// Returns an error if the URL is invalid, the request fails, or the response
+/// cannot be read.
+pub fn refresh(url: &str) -> Result<String> {
+    let url = Url::parse(url)?;
 
-            if cache {
-                cache::put(&cache_key, &body)?;
-            }
-            Ok(body)
-        } else {
-            Err(anyhow!("Request failed with status: {}", res.status()))
-        }
-    }
+    http::get(&url, Some(&url_cache_key(&url)))
 }
 
-// Get the HTTP headers from the config
-fn get_headers() -> Result<HeaderMap> {
-    let mut config_headers: HashMap<String, String> = HashMap::new();
+/// Upload the body to the URL with a PUT request, nothing is cached.
+///
+/// # Errors
+///
+/// Returns an error in offline mode or if the upload fails.
+pub fn upload(url: &Url, body: &str) -> Result<()> {
+    http::put(url, body)
+}
 
-    // get the config from ~/.config/ssh-vault/config.yml
-    let config = config::get()?;
+/// The cache key of the keys of a user, a provider user or URL.
+///
+/// # Errors
+///
+/// Returns an error if the URL is invalid or the provider is unknown.
+pub fn cache_key(user: &str) -> Result<String> {
+    let (url, _) = keys_url(user)?;
 
-    if let Ok(http_headers) = config.get_table("http_headers") {
-        for (key, value) in &http_headers {
-            config_headers.insert(key.clone(), value.to_string());
-        }
+    Ok(url_cache_key(&url))
+}
+
+fn url_cache_key(url: &Url) -> String {
+    format!("{:x}", md5::compute(url.as_str().as_bytes()))
+}
+
+// without HTTP support every request fails, the cache is used in offline mode
+#[cfg(not(feature = "http"))]
+mod http {
+    use anyhow::{Result, anyhow};
+    use url::Url;
+
+    pub fn get(url: &Url, _cache_key: Option<&str>) -> Result<String> {
+        Err(anyhow!(
+            "Built without HTTP support, refusing to fetch {url}"
+        ))
     }
 
-    let headers: HeaderMap = (&config_headers).try_into().unwrap_or_default();
+    pub fn revalidate(url: &Url, _cache_key: &str) -> Result<String> {
+        get(url, None)
+    }
 
-    Ok(headers)
+    pub fn put(url: &Url, _body: &str) -> Result<()> {
+        Err(anyhow!(
+            "Built without HTTP support, refusing to upload to {url}"
+        ))
+    }
 }
 
-/// Get the user key from fetched keys by index or fingerprint.
+/// Get the user key from fetched keys by index or fingerprint, keys revoked
+/// in the configured `revoked_keys` are refused.
 ///
 /// # Errors
 ///
-/// Returns an error if the requested key is not found or cannot be parsed.
+/// Returns an error if the requested key is not found, cannot be parsed or is
+/// revoked.
 pub fn get_user_key(
     keys: &str,
     key: Option<u32>,
     fingerprint: &Option<String>,
 ) -> Result<PublicKey> {
+    let public_key = select_user_key(keys, key, fingerprint.as_deref())?;
+
+    ssh::krl::check(&public_key)?;
+
+    Ok(public_key)
+}
+
+fn select_user_key(keys: &str, key: Option<u32>, fingerprint: Option<&str>) -> Result<PublicKey> {
     // Get only SSH keys from the fetched keys
     let keys = tools::filter_fetched_keys(keys)?;
 
@@ -114,6 +275,9 @@ pub fn get_user_key(
         key
     });
 
+    // the reason a matching certificate was not trusted
+    let mut cert_error = None;
+
     for (id, line) in keys.lines().enumerate() {
         let u32_id = u32::try_from(id)?;
         if key >= u32::try_from(keys.lines().count())? {
@@ -123,11 +287,21 @@ pub fn get_user_key(
             ))?;
         }
 
-        // parse the line as a public key
-        if let Ok(public_key) = PublicKey::from_openssh(line) {
+        // parse the line as a public key, certificates must be trusted
+        let public_key = match ssh::cert::public_key(line) {
+            Ok(public_key) => Some(public_key),
+            Err(err) => {
+                if ssh::cert::is_certificate(line) && fingerprint.is_none() && u32_id == key {
+                    cert_error = Some(err);
+                }
+                None
+            }
+        };
+
+        if let Some(public_key) = public_key {
             // if fingerprint is provided, check if it matches
-            if let Some(f) = &fingerprint {
-                if public_key.fingerprint(HashAlg::Sha256).to_string() == *f {
+            if let Some(f) = fingerprint {
+                if public_key.fingerprint(HashAlg::Sha256).to_string() == f {
                     return Ok(public_key);
                 }
 
@@ -144,7 +318,7 @@ pub fn get_user_key(
         }
     }
 
-    Err(anyhow!("key not found"))
+    Err(cert_error.unwrap_or_else(|| anyhow!("key not found")))
 }
 #[cfg(test)]
 #[allow(
@@ -156,6 +330,8 @@ mod tests {
     use super::*;
     use crate::vault::fingerprint::Fingerprint;
     use crate::vault::fingerprint::get_remote_fingerprints;
+    #[cfg(feature = "http")]
+    use crate::vault::http::stand_in;
 
     const KEYS: &str = "
 # random comment
@@ -345,8 +521,235 @@ Fin
     }
 
     #[test]
-    fn test_get_headers() {
-        let headers = get_headers().unwrap();
-        assert!(headers.is_empty());
+    fn test_get_user_key_certificate() {
+        let cert = std::fs::read_to_string("test_data/ed25519_expired-cert.pub").unwrap();
+        let key = std::fs::read_to_string("test_data/ed25519.pub").unwrap();
+        let keys = format!("{cert}{key}");
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                (
+                    "SSH_VAULT_CERT_AUTHORITY",
+                    Some("test_data/ca_ed25519.pub".as_ref()),
+                ),
+            ],
+            || {
+                // the expired certificate is not trusted
+                let err = get_user_key(&keys, Some(1), &None).unwrap_err();
+                assert!(err.to_string().contains("not valid at this time"));
+                assert!(get_user_key(&keys, Some(2), &None).is_ok());
+            },
+        );
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_CERT_AUTHORITY", None),
+            ],
+            || {
+                assert_eq!(
+                    get_user_key(&keys, Some(1), &None).unwrap().key_data(),
+                    get_user_key(&keys, Some(2), &None).unwrap().key_data()
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_user_url() {
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            for (user, url) in [
+                ("alice", "https://github.com/alice.keys"),
+                ("github:alice", "https://github.com/alice.keys"),
+                ("gitlab:alice", "https://gitlab.com/alice.keys"),
+                ("codeberg:bob", "https://codeberg.org/bob.keys"),
+                ("srht:~carol", "https://meta.sr.ht/~carol.keys"),
+                ("srht:carol", "https://meta.sr.ht/~carol.keys"),
+                ("lp:dave", "https://launchpad.net/~dave/+sshkeys"),
+                (
+                    "gitea@git.example.com:erin",
+                    "https://git.example.com/erin.keys",
+                ),
+            ] {
+                assert_eq!(user_url(user).unwrap().as_str(), url);
+            }
+
+            assert!(user_url("unknown:alice").is_err());
+            assert!(user_url("gitlab:").is_err());
+        });
+    }
+
+    #[test]
+    fn test_user_id() {
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            for (user, id) in [
+                ("alice", "github:alice"),
+                ("github:alice", "github:alice"),
+                ("gitlab:alice", "gitlab:alice"),
+                ("srht:carol", "srht:~carol"),
+                ("srht:~carol", "srht:~carol"),
+                ("lp:~dave", "lp:~dave"),
+                ("gitea@git.example.com:erin", "gitea@git.example.com:erin"),
+                ("https://example.com/keys", "https://example.com/keys"),
+                ("https://Example.com", "https://example.com/"),
+            ] {
+                assert_eq!(user_id(user).unwrap(), id);
+            }
+
+            assert!(user_id("unknown:alice").is_err());
+            assert!(user_id("gitlab:").is_err());
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn test_get_keys_providers() {
+        let keys = std::fs::read_to_string("test_data/ed25519.pub").unwrap();
+        let server = stand_in::start(&[
+            ("/alice.keys", &keys),
+            ("/~carol.keys", &keys),
+            ("/~dave/+sshkeys", &keys),
+            ("/erin.keys", &keys),
+        ]);
+
+        let home = tempfile::TempDir::new().unwrap();
+        let config = home.path().join(".config").join("ssh-vault");
+        std::fs::create_dir_all(&config).unwrap();
+        std::fs::write(
+            config.join("config.yml"),
+            format!(
+                "providers:\n  gitlab: {url}/\n  srht: {url}\n  lp: {url}\n  gitea: {url}\n",
+                url = server.url
+            ),
+        )
+        .unwrap();
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            for user in [
+                "gitlab:alice",
+                "srht:~carol",
+                "lp:dave",
+                "gitea@git.example.com:erin",
+            ] {
+                let fetched = get_keys(user).unwrap();
+                let key = get_user_key(&fetched, None, &None).unwrap();
+                assert_eq!(key.to_openssh().unwrap(), keys.trim());
+            }
+
+            // the keys are cached
+            get_keys("gitlab:alice").unwrap();
+            assert_eq!(server.requests().len(), 4);
+
+            assert!(get_keys("gitlab:unknown").is_err());
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn test_request_revalidate() {
+        let server = stand_in::start(&[("/alice.keys", "ssh-ed25519 AAAA")]);
+        let home = tempfile::TempDir::new().unwrap();
+        let url = format!("{}/alice.keys", server.url);
+        let key = url_cache_key(&Url::parse(&url).unwrap());
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_CACHE_TTL", Some("0".as_ref())),
+            ],
+            || {
+                assert_eq!(request(&url, true).unwrap(), "ssh-ed25519 AAAA");
+
+                let meta = cache::meta(&key).unwrap().unwrap();
+                assert_eq!(meta.url.as_deref(), Some(url.as_str()));
+                assert!(meta.etag.is_some());
+
+                // expired, revalidated with the ETag
+                assert_eq!(request(&url, true).unwrap(), "ssh-ed25519 AAAA");
+                let requests = server.requests();
+                assert_eq!(requests.len(), 2);
+                assert!(
+                    requests
+                        .last()
+                        .unwrap()
+                        .to_lowercase()
+                        .contains("if-none-match")
+                );
+
+                // a missing body is fetched again
+                assert!(cache::remove(&key).unwrap());
+                assert_eq!(request(&url, true).unwrap(), "ssh-ed25519 AAAA");
+                assert!(
+                    !server
+                        .requests()
+                        .last()
+                        .unwrap()
+                        .to_lowercase()
+                        .contains("if-none-match")
+                );
+            },
+        );
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_CACHE_TTL", Some("never".as_ref())),
+            ],
+            || {
+                assert_eq!(request(&url, true).unwrap(), "ssh-ed25519 AAAA");
+                assert_eq!(server.requests().len(), 3);
+
+                // refresh skips the cache
+                assert_eq!(refresh(&url).unwrap(), "ssh-ed25519 AAAA");
+                assert_eq!(server.requests().len(), 4);
+                assert_eq!(cache_key(&url).unwrap(), key);
+            },
+        );
+    }
+
+    #[test]
+    fn test_request_offline() {
+        let home = tempfile::TempDir::new().unwrap();
+        let url = "https://keys.example.com/alice.keys";
+        let key = url_cache_key(&Url::parse(url).unwrap());
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_OFFLINE", Some("true".as_ref())),
+                ("SSH_VAULT_CACHE_TTL", Some("0".as_ref())),
+            ],
+            || {
+                assert!(is_offline().unwrap());
+
+                let err = request(url, true).unwrap_err();
+                assert!(err.to_string().contains("not in the cache"));
+                assert!(refresh(url).is_err());
+                assert!(refresh_keys(url).is_err());
+
+                // expired entries are used
+                cache::put(&key, "ssh-ed25519 AAAA").unwrap();
+                assert_eq!(request(url, true).unwrap(), "ssh-ed25519 AAAA");
+
+                // uncached requests never read the cache
+                assert!(request(url, false).is_err());
+            },
+        );
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_OFFLINE", None),
+            ],
+            || {
+                assert_eq!(is_offline().unwrap(), !cfg!(feature = "http"));
+            },
+        );
     }
 }
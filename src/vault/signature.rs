@@ -0,0 +1,291 @@
+use crate::{
+    config, tools,
+    vault::{
+        find, remote,
+        ssh::{allowed_signers, krl, rsa},
+        trust,
+    },
+};
+use ::rsa::{
+    pkcs1v15::SigningKey,
+    sha2::Sha512,
+    signature::{SignatureEncoding, Signer},
+};
+use anyhow::{Context, Result, anyhow};
+use ssh_key::{
+    Algorithm, HashAlg, LineEnding, PrivateKey, PublicKey, Signature, SshSig, private::KeypairData,
+};
+use std::path::PathBuf;
+
+/// The SSHSIG namespace of the vault signatures.
+pub const NAMESPACE: &str = "ssh-vault";
+
+const BEGIN: &str = "-----BEGIN SSH SIGNATURE-----";
+
+/// Sign the vault with SSHSIG, the armored signature is appended after the
+/// vault. It can be checked with `ssh-keygen -Y verify -n ssh-vault` using
+/// the vault without the signature as the message.
+///
+/// # Errors
+///
+/// Returns an error if the key can't sign.
+pub fn sign(vault: &str, key: &PrivateKey) -> Result<String> {
+    let signature = sign_message(key, NAMESPACE, vault.as_bytes())
+        .context("Could not sign the vault")?
+        .to_pem(LineEnding::LF)?;
+
+    Ok(format!("{vault}\n{signature}"))
+}
+
+/// Sign the message for the namespace with SSHSIG using SHA-512, like
+/// `ssh-keygen -Y sign`.
+///
+/// # Errors
+///
+/// Returns an error if the key can't sign.
+pub fn sign_message(key: &PrivateKey, namespace: &str, message: &[u8]) -> Result<SshSig> {
+    let KeypairData::Rsa(keypair) = key.key_data() else {
+        return Ok(key.sign(namespace, HashAlg::Sha512, message)?);
+    };
+
+    // the RSA signer of ssh-key can't load the key, see ssh::rsa::private_key
+    let data = SshSig::signed_data(namespace, HashAlg::Sha512, message)?;
+    let signature = SigningKey::<Sha512>::new(rsa::private_key(keypair)?).try_sign(&data)?;
+
+    Ok(SshSig::new(
+        key.public_key().key_data().clone(),
+        namespace,
+        HashAlg::Sha512,
+        Signature::new(
+            Algorithm::Rsa {
+                hash: Some(HashAlg::Sha512),
+            },
+            signature.to_vec(),
+        )?,
+    )?)
+}
+
+/// Split the vault and its signature, if it is signed.
+#[must_use]
+pub fn split(data: &str) -> (&str, Option<&str>) {
+    match data.find(&format!("\n{BEGIN}")) {
+        Some(index) => {
+            let (vault, signature) = data.split_at(index);
+            (vault, Some(signature.trim()))
+        }
+        None => (data, None),
+    }
+}
+
+/// Verify the signature of the vault, returns the signing key. Signatures by
+/// a key revoked in `revoked_keys` are refused.
+///
+/// # Errors
+///
+/// Returns an error if the signature is invalid, doesn't match the vault or
+/// the key is revoked.
+pub fn verify(vault: &str, signature: &str) -> Result<PublicKey> {
+    verify_message(NAMESPACE, vault.as_bytes(), signature)
+}
+
+/// Verify an armored SSHSIG signature of the message for the namespace, like
+/// `ssh-keygen -Y check-novalidate`, returns the signing key. Signatures by a
+/// key revoked in `revoked_keys` are refused.
+///
+/// # Errors
+///
+/// Returns an error if the signature is invalid, for another namespace,
+/// doesn't match the message or the key is revoked.
+pub fn verify_message(namespace: &str, message: &[u8], signature: &str) -> Result<PublicKey> {
+    let signature = SshSig::from_pem(signature.trim()).context("Invalid signature")?;
+
+    if signature.namespace() != namespace {
+        return Err(anyhow!(
+            "The signature is for the namespace {}, expected {namespace}",
+            signature.namespace()
+        ));
+    }
+
+    let key = PublicKey::from(signature.public_key().clone());
+
+    key.verify(namespace, message, &signature)
+        .map_err(|_| anyhow!("Bad signature by {}", key.fingerprint(HashAlg::Sha256)))?;
+
+    krl::check(&key)?;
+
+    Ok(key)
+}
+
+/// The `allowed_signers` files, the given file or the `allowed_signers`
+/// setting.
+///
+/// # Errors
+///
+/// Returns an error if the config can't be read.
+pub fn allowed_signers_paths(allowed_signers: Option<&str>) -> Result<Vec<PathBuf>> {
+    match allowed_signers {
+        Some(path) => Ok(vec![tools::expand_home(path)?]),
+        None => config::get_paths(&config::get()?, "allowed_signers"),
+    }
+}
+
+/// The keys trusted to sign and the name they are reported with.
+#[derive(Debug, Default)]
+pub struct Signers(Vec<(String, PublicKey)>);
+
+impl Signers {
+    /// Load the public key files, the keys of the users (pinned like the keys
+    /// of the recipients) and the entries of the `allowed_signers` file
+    /// valid for the namespace. Without a file the `allowed_signers` setting
+    /// is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a key or file can't be read, or the keys of a user
+    /// can't be fetched or changed since they were pinned.
+    pub fn load(
+        keys: &[String],
+        users: &[String],
+        allowed_signers: Option<&str>,
+        namespace: &str,
+    ) -> Result<Self> {
+        let mut signers = Vec::new();
+
+        for path in keys {
+            signers.push((path.clone(), find::public_key(Some(path.clone()))?));
+        }
+
+        for user in users {
+            let keys = remote::get_keys(user)?;
+
+            trust::check(user, &keys)?;
+
+            for line in tools::filter_fetched_keys(&keys)?.lines() {
+                if let Ok(key) = PublicKey::from_openssh(line) {
+                    signers.push((user.clone(), key));
+                }
+            }
+        }
+
+        for path in allowed_signers_paths(allowed_signers)? {
+            for signer in allowed_signers::load(&path)? {
+                if signer.allows(namespace) {
+                    signers.push((signer.principals, signer.key));
+                }
+            }
+        }
+
+        Ok(Self(signers))
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The name of the signer holding the key.
+    #[must_use]
+    pub fn find(&self, key: &PublicKey) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(_, signer)| signer.key_data() == key.key_data())
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn key(path: &str) -> PrivateKey {
+        find::private_key(
+            Some(path.to_string()),
+            &crate::vault::SshKeyType::Ed25519,
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            for path in [
+                "test_data/ed25519",
+                "test_data/id_rsa",
+                "test_data/ecdsa_p256",
+            ] {
+                let key = key(path);
+                let signed = sign("SSH-VAULT;CHACHA20-POLY1305;abc\ndef", &key).unwrap();
+
+                let (vault, signature) = split(&signed);
+                assert_eq!(vault, "SSH-VAULT;CHACHA20-POLY1305;abc\ndef");
+
+                let key_signer = verify(vault, signature.unwrap()).unwrap();
+                assert_eq!(key_signer.key_data(), key.public_key().key_data());
+
+                // the vault was changed
+                let err = verify("SSH-VAULT;CHACHA20-POLY1305;abc\nxyz", signature.unwrap());
+                assert!(err.unwrap_err().to_string().contains("Bad signature"));
+            }
+        });
+
+        assert_eq!(
+            split("SSH-VAULT;AES256;abc"),
+            ("SSH-VAULT;AES256;abc", None)
+        );
+    }
+
+    #[test]
+    fn test_verify_namespace() {
+        let key = key("test_data/ed25519");
+        let signature = key
+            .sign("file", HashAlg::Sha512, b"vault")
+            .unwrap()
+            .to_pem(LineEnding::LF)
+            .unwrap();
+
+        assert!(verify("vault", &signature).is_err());
+    }
+
+    #[test]
+    fn test_signers() {
+        let home = tempfile::TempDir::new().unwrap();
+        let ed25519 = fs::read_to_string("test_data/ed25519.pub").unwrap();
+        let rsa = fs::read_to_string("test_data/id_rsa.pub").unwrap();
+        let allowed_signers = home.path().join("allowed_signers");
+        fs::write(
+            &allowed_signers,
+            format!("alice@example.com {ed25519}bob@example.com namespaces=\"git\" {rsa}"),
+        )
+        .unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_ALLOWED_SIGNERS", None),
+            ],
+            || {
+                let ed25519 = PublicKey::from_openssh(&ed25519).unwrap();
+                let rsa = PublicKey::from_openssh(&rsa).unwrap();
+
+                let signers = Signers::load(&[], &[], None, NAMESPACE).unwrap();
+                assert!(signers.is_empty());
+
+                let signers =
+                    Signers::load(&[], &[], Some(allowed_signers.to_str().unwrap()), NAMESPACE)
+                        .unwrap();
+                assert_eq!(signers.find(&ed25519), Some("alice@example.com"));
+                assert_eq!(signers.find(&rsa), None);
+
+                let signers =
+                    Signers::load(&["test_data/id_rsa.pub".to_string()], &[], None, NAMESPACE)
+                        .unwrap();
+                assert_eq!(signers.find(&rsa), Some("test_data/id_rsa.pub"));
+            },
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use crate::vault::{
+    Algorithm, Metadata, ParsedVault, Recipient, SshKeyType, Vault, agent::Agent, crypto,
+    crypto::Crypto, crypto::chacha20poly1305::ChaCha20Poly1305Crypto,
+};
+use anyhow::{Context, Result, anyhow};
+use rand::{TryRng, rngs::SysRng};
+use secrecy::{ExposeSecret, SecretSlice};
+use ssh_key::{HashAlg, PrivateKey, PublicKey, public::KeyData};
+
+/// Prefix of the data signed by the agent, the signature can't be reused
+/// outside ssh-vault
+pub const NAMESPACE: &[u8] = b"ssh-vault-agent-v1:";
+
+/// Size of the random per-vault challenge
+pub const CHALLENGE_SIZE: usize = 32;
+
+/// Ed25519 keys held by ssh-agent
+///
+/// The password is wrapped with a key derived (HKDF) from the signature of a
+/// random challenge stored in the vault. Ed25519 signatures are deterministic,
+/// so the agent produces the same signature, and the same key, when viewing
+/// the vault. The private key never leaves the agent.
+pub struct AgentVault {
+    public_key: PublicKey,
+}
+
+impl Vault for AgentVault {
+    fn new(public: Option<PublicKey>, private: Option<PrivateKey>) -> Result<Self> {
+        match (public, private) {
+            (Some(public), None) => match public.key_data() {
+                KeyData::Ed25519(key_data) => {
+                    ed25519_dalek::VerifyingKey::try_from(key_data)
+                        .context("Could not load key")?;
+
+                    Ok(Self { public_key: public })
+                }
+                _ => Err(anyhow!("Only Ed25519 keys can be used with ssh-agent")),
+            },
+            (_, Some(_)) => Err(anyhow!("The private key is kept by ssh-agent")),
+            _ => Err(anyhow!("Missing public key")),
+        }
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::ChaCha20Poly1305
+    }
+
+    fn fingerprint(&self) -> Result<String> {
+        Ok(self.public_key.fingerprint(HashAlg::Sha256).to_string())
+    }
+
+    fn wrap(&self, password: &SecretSlice<u8>) -> Result<Recipient> {
+        let fingerprint = self.fingerprint()?;
+
+        let mut challenge = [0_u8; CHALLENGE_SIZE];
+        SysRng
+            .try_fill_bytes(&mut challenge)
+            .map_err(|err| anyhow!("Error generating random challenge: {err}"))?;
+
+        let signature = self.sign(&challenge)?;
+
+        // check the signature now, otherwise the vault could never be opened
+        let verifying_key = match self.public_key.key_data() {
+            KeyData::Ed25519(key_data) => ed25519_dalek::VerifyingKey::try_from(key_data)?,
+            _ => return Err(anyhow!("Only Ed25519 keys can be used with ssh-agent")),
+        };
+        verifying_key
+            .verify_strict(
+                &message(&challenge),
+                &ed25519_dalek::Signature::from_slice(signature.expose_secret())?,
+            )
+            .context("ssh-agent returned an invalid signature")?;
+
+        let enc_key = crypto::hkdf(
+            &challenge,
+            fingerprint.as_bytes(),
+            signature.expose_secret(),
+        )?;
+
+        // encrypt the password with the derived key
+        let crypto = ChaCha20Poly1305Crypto::new(SecretSlice::new(enc_key.into()));
+        let wrapped_key = crypto.encrypt(password.expose_secret(), fingerprint.as_bytes())?;
+
+        Ok(Recipient {
+            key_type: SshKeyType::Agent,
+            fingerprint,
+            ephemeral_key: Some(challenge.to_vec()),
+            wrapped_key,
+        })
+    }
+
+    fn unwrap(&self, recipient: &Recipient) -> Result<SecretSlice<u8>> {
+        if recipient.key_type != SshKeyType::Agent || self.fingerprint()? != recipient.fingerprint {
+            return Err(anyhow!("Fingerprint mismatch, use correct key"));
+        }
+
+        let challenge = recipient.ephemeral_key.as_deref().unwrap_or_default();
+
+        unwrap_password(recipient, &self.sign(challenge)?)
+    }
+
+    // a single recipient vault uses the SSH-VAULT-V2 format, the legacy
+    // format only supports RSA and Ed25519 recipients
+    fn create(&self, password: SecretSlice<u8>, data: &mut [u8]) -> Result<String> {
+        let recipient = self.wrap(&password)?;
+        let vault = ParsedVault::seal_v2(Metadata::now()?, vec![recipient], &password, data)?;
+        Ok(vault.to_string())
+    }
+}
+
+impl AgentVault {
+    // ask the agent to sign the challenge, the signature is the secret
+    fn sign(&self, challenge: &[u8]) -> Result<SecretSlice<u8>> {
+        let signature = Agent::connect()?.sign(&self.public_key, &message(challenge))?;
+
+        Ok(SecretSlice::new(signature.as_bytes().into()))
+    }
+}
+
+/// The data signed to derive the wrapping key.
+#[must_use]
+pub fn message(challenge: &[u8]) -> Vec<u8> {
+    [NAMESPACE, challenge].concat()
+}
+
+/// Recover the password of an agent recipient using the signature of the
+/// challenge, the signature can come from the agent or the private key.
+///
+/// # Errors
+///
+/// Returns an error if the challenge is invalid or decryption fails.
+pub fn unwrap_password(
+    recipient: &Recipient,
+    signature: &SecretSlice<u8>,
+) -> Result<SecretSlice<u8>> {
+    let challenge = recipient.ephemeral_key.as_deref().unwrap_or_default();
+
+    if challenge.len() != CHALLENGE_SIZE {
+        return Err(anyhow!(
+            "Invalid challenge: expected {CHALLENGE_SIZE} bytes, got {}",
+            challenge.len()
+        ));
+    }
+
+    let fingerprint = recipient.fingerprint.as_bytes();
+
+    let enc_key = crypto::hkdf(challenge, fingerprint, signature.expose_secret())?;
+
+    // use the enc_key to decrypt the password
+    let crypto = ChaCha20Poly1305Crypto::new(SecretSlice::new(enc_key.into()));
+
+    let password = crypto.decrypt(&recipient.wrapped_key, fingerprint)?;
+
+    if password.len() != 32 {
+        return Err(anyhow!(
+            "Invalid decrypted password: expected 32 bytes, got {}",
+            password.len()
+        ));
+    }
+
+    Ok(SecretSlice::new(password.into()))
+}
+
+#[cfg(all(test, unix))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::vault::{SshVault, agent::stand_in, parse, ssh::ed25519::Ed25519Vault};
+    use std::path::Path;
+
+    const SECRET: &str = "The private key never leaves the agent";
+
+    fn public_key(key: &str) -> PublicKey {
+        PublicKey::read_openssh_file(Path::new(key)).unwrap()
+    }
+
+    #[test]
+    fn test_agent_vault() {
+        let agent = stand_in::start(&["test_data/ed25519"]);
+
+        temp_env::with_var("SSH_AUTH_SOCK", Some(&agent.socket), || {
+            let vault = AgentVault::new(Some(public_key("test_data/ed25519.pub")), None).unwrap();
+
+            let mut secret = String::from(SECRET).into_bytes();
+            let data = vault
+                .create(crypto::gen_password().unwrap(), &mut secret)
+                .unwrap();
+            assert!(data.starts_with("SSH-VAULT-V2;CHACHA20-POLY1305;created="));
+
+            let parsed = parse(&data).unwrap();
+            assert_eq!(
+                parsed.recipients.first().unwrap().key_type,
+                SshKeyType::Agent
+            );
+            assert_eq!(parsed.to_string(), data);
+
+            // only the agent is needed
+            let view = SshVault::new(
+                &SshKeyType::Agent,
+                Some(public_key("test_data/ed25519.pub")),
+                None,
+            )
+            .unwrap();
+            assert_eq!(
+                view.view(&parsed).unwrap().expose_secret(),
+                SECRET.as_bytes()
+            );
+
+            // the private key produces the same signature
+            let private_key =
+                PrivateKey::read_openssh_file(Path::new("test_data/ed25519")).unwrap();
+            let view = Ed25519Vault::new(None, Some(private_key)).unwrap();
+            assert_eq!(
+                view.view(&parsed).unwrap().expose_secret(),
+                SECRET.as_bytes()
+            );
+        });
+    }
+
+    #[test]
+    fn test_agent_vault_missing_key() {
+        // the agent doesn't hold the key
+        let agent = stand_in::start(&["test_data/id_rsa"]);
+
+        temp_env::with_var("SSH_AUTH_SOCK", Some(&agent.socket), || {
+            let vault = AgentVault::new(Some(public_key("test_data/ed25519.pub")), None).unwrap();
+            assert!(vault.wrap(&crypto::gen_password().unwrap()).is_err());
+        });
+
+        temp_env::with_var("SSH_AUTH_SOCK", None::<&str>, || {
+            let vault = AgentVault::new(Some(public_key("test_data/ed25519.pub")), None).unwrap();
+            assert!(vault.wrap(&crypto::gen_password().unwrap()).is_err());
+        });
+    }
+
+    #[test]
+    fn test_agent_vault_invalid_key() {
+        assert!(AgentVault::new(Some(public_key("test_data/id_rsa.pub")), None).is_err());
+
+        let private_key = PrivateKey::read_openssh_file(Path::new("test_data/ed25519")).unwrap();
+        assert!(AgentVault::new(None, Some(private_key)).is_err());
+    }
+
+    #[test]
+    fn test_agent_vault_invalid_challenge() {
+        let recipient = Recipient {
+            key_type: SshKeyType::Agent,
+            fingerprint: "SHA256:test".to_string(),
+            ephemeral_key: Some(vec![0_u8; 16]),
+            wrapped_key: vec![0_u8; 48],
+        };
+
+        let unwrap = unwrap_password(&recipient, &SecretSlice::new(vec![0_u8; 64].into()));
+        assert!(
+            unwrap
+                .unwrap_err()
+                .to_string()
+                .contains("Invalid challenge")
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use crate::vault::ssh::config::wildcard;
+use anyhow::{Context, Result, anyhow};
+use ssh_key::PublicKey;
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// An entry of an `allowed_signers` file, see `ssh-keygen(1)`:
+///
+/// `<principals> [options] <key type> <base64 key> [comment]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowedSigner {
+    /// Comma separated principal patterns, `*` and `?` are wildcards
+    pub principals: String,
+    /// The namespaces the key may sign for, any if not restricted
+    pub namespaces: Option<Vec<String>>,
+    /// Unix time from which the key is valid
+    pub valid_after: Option<u64>,
+    /// Unix time until which the key is valid
+    pub valid_before: Option<u64>,
+    pub key: PublicKey,
+}
+
+impl AllowedSigner {
+    /// Return true if the key may sign for the namespace now.
+    #[must_use]
+    pub fn allows(&self, namespace: &str) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+
+        self.namespaces
+            .as_ref()
+            .is_none_or(|namespaces| namespaces.iter().any(|ns| wildcard(ns, namespace)))
+            && self.valid_after.is_none_or(|after| now >= after)
+            && self.valid_before.is_none_or(|before| now <= before)
+    }
+
+    /// Return true if one of the principal patterns matches the identity, a
+    /// matching negated pattern (`!pattern`) excludes it.
+    #[must_use]
+    pub fn matches_principal(&self, identity: &str) -> bool {
+        let mut matched = false;
+
+        for pattern in self.principals.split(',') {
+            if let Some(pattern) = pattern.strip_prefix('!') {
+                if wildcard(pattern, identity) {
+                    return false;
+                }
+            } else if wildcard(pattern, identity) {
+                matched = true;
+            }
+        }
+
+        matched
+    }
+}
+
+/// Read an `allowed_signers` file.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be read or an entry is invalid.
+pub fn load(path: &Path) -> Result<Vec<AllowedSigner>> {
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("Could not read allowed signers {}", path.display()))?;
+
+    parse(&data).with_context(|| format!("Invalid allowed signers {}", path.display()))
+}
+
+/// Parse the entries of an `allowed_signers` file. The `namespaces`,
+/// `valid-after` and `valid-before` options are supported, the timestamps are
+/// read as UTC. `cert-authority` entries are ignored, certificates are not
+/// accepted as signers.
+///
+/// # Errors
+///
+/// Returns an error if an entry is invalid.
+pub fn parse(data: &str) -> Result<Vec<AllowedSigner>> {
+    let mut signers = Vec::new();
+
+    for (number, line) in data.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = shell_words::split(line)
+            .with_context(|| format!("Invalid entry on line {}", number + 1))?;
+
+        let (principals, options, key) = match fields.as_slice() {
+            [principals, algorithm, data, ..] if is_key_type(algorithm) => {
+                (principals, None, format!("{algorithm} {data}"))
+            }
+            [principals, options, algorithm, data, ..] => {
+                (principals, Some(options), format!("{algorithm} {data}"))
+            }
+            _ => return Err(anyhow!("Invalid entry on line {}", number + 1)),
+        };
+
+        let key = PublicKey::from_openssh(&key)
+            .with_context(|| format!("Invalid key on line {}", number + 1))?;
+
+        let mut signer = AllowedSigner {
+            principals: principals.clone(),
+            namespaces: None,
+            valid_after: None,
+            valid_before: None,
+            key,
+        };
+
+        let mut cert_authority = false;
+
+        for option in options.iter().flat_map(|options| split_options(options)) {
+            let (name, value) = option.split_once('=').unwrap_or((option.as_str(), ""));
+
+            match name.to_lowercase().as_str() {
+                "cert-authority" => cert_authority = true,
+                "namespaces" => {
+                    signer.namespaces = Some(value.split(',').map(ToString::to_string).collect());
+                }
+                "valid-after" => signer.valid_after = Some(timestamp(value)?),
+                "valid-before" => signer.valid_before = Some(timestamp(value)?),
+                _ => {
+                    return Err(anyhow!("Unsupported option {name} on line {}", number + 1));
+                }
+            }
+        }
+
+        if !cert_authority {
+            signers.push(signer);
+        }
+    }
+
+    Ok(signers)
+}
+
+fn is_key_type(field: &str) -> bool {
+    ["ssh-", "ecdsa-", "sk-"]
+        .iter()
+        .any(|prefix| field.starts_with(prefix))
+}
+
+// the options are separated by commas, the quotes were removed but a value
+// like namespaces="git,file" keeps its commas
+fn split_options(options: &str) -> Vec<String> {
+    let mut split: Vec<String> = Vec::new();
+
+    for option in options.split(',') {
+        match split.last_mut() {
+            Some(last) if last.starts_with("namespaces=") && !option.contains('=') => {
+                last.push(',');
+                last.push_str(option);
+            }
+            _ => split.push(option.to_string()),
+        }
+    }
+
+    split
+}
+
+// YYYYMMDD[HHMM[SS]][Z] to unix time
+fn timestamp(value: &str) -> Result<u64> {
+    let invalid = || anyhow!("Invalid timestamp {value}, expected YYYYMMDD[HHMM[SS]]");
+
+    let digits = value.strip_suffix(['Z', 'z']).unwrap_or(value);
+
+    if !matches!(digits.len(), 8 | 12 | 14) || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let field = |range: std::ops::Range<usize>| -> Result<u64> {
+        digits
+            .get(range)
+            .map_or(Ok(0), str::parse)
+            .map_err(|_| invalid())
+    };
+
+    let (year, month, day) = (field(0..4)?, field(4..6)?, field(6..8)?);
+    let (hour, minute, second) = (field(8..10)?, field(10..12)?, field(12..14)?);
+
+    if year < 1970
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+    {
+        return Err(invalid());
+    }
+
+    // days since 1970-01-01 of the civil date
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Ok(days * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    const ED25519: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAINixf2m2nj8TDeazbWuemUY8ZHNg7znA7hVPN8TJLr2W";
+
+    #[test]
+    fn test_parse() {
+        let data = format!(
+            "# allowed signers\n\
+            alice@example.com {ED25519} alice\n\
+            *@example.com,!mallory@example.com namespaces=\"git,ssh-vault\" {ED25519}\n\
+            bob valid-after=\"20200101\",valid-before=\"20300101Z\" {ED25519}\n\
+            ca@example.com cert-authority {ED25519}\n"
+        );
+
+        let signers = parse(&data).unwrap();
+        assert_eq!(signers.len(), 3);
+
+        let [alice, any, bob] = signers.as_slice() else {
+            unreachable!()
+        };
+
+        assert!(alice.matches_principal("alice@example.com"));
+        assert!(!alice.matches_principal("bob@example.com"));
+        assert!(alice.allows("file"));
+
+        assert_eq!(
+            any.namespaces,
+            Some(vec!["git".to_string(), "ssh-vault".to_string()])
+        );
+        assert!(any.allows("ssh-vault"));
+        assert!(!any.allows("file"));
+        assert!(any.matches_principal("bob@example.com"));
+        assert!(!any.matches_principal("mallory@example.com"));
+
+        assert_eq!(bob.valid_after, Some(1_577_836_800));
+        assert_eq!(bob.valid_before, Some(1_893_456_000));
+
+        assert!(parse(&format!("alice unknown-option {ED25519}")).is_err());
+        assert!(parse("alice ssh-ed25519").is_err());
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp("19700101").unwrap(), 0);
+        assert_eq!(timestamp("20240229123045Z").unwrap(), 1_709_209_845);
+        assert_eq!(timestamp("202402291230").unwrap(), 1_709_209_800);
+        assert!(timestamp("2024").is_err());
+        assert!(timestamp("20241301").is_err());
+        assert!(timestamp("00000101").is_err());
+    }
+
+    #[test]
+    fn test_allows_expired() {
+        let signers = parse(&format!(
+            "alice valid-before=20200101 {ED25519}\nbob valid-after=29990101 {ED25519}"
+        ))
+        .unwrap();
+        assert!(signers.iter().all(|signer| !signer.allows("ssh-vault")));
+    }
+}
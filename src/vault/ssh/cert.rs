@@ -0,0 +1,279 @@
+use crate::{config, vault::ssh::krl};
+use anyhow::{Context, Result, anyhow};
+use ssh_key::{Certificate, HashAlg, PublicKey, certificate::CertType};
+use std::{
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Return true if the key is an OpenSSH certificate
+/// (`<algorithm>-cert-v01@openssh.com`).
+#[must_use]
+pub fn is_certificate(key: &str) -> bool {
+    key.split_whitespace()
+        .next()
+        .is_some_and(|algorithm| algorithm.ends_with("-cert-v01@openssh.com"))
+}
+
+/// Parse an openssh public key or certificate, for a certificate the embedded
+/// key is returned. Host certificates and certificates revoked in
+/// `revoked_keys` are refused.
+///
+/// When `cert_authority` is configured the certificate must be signed by one
+/// of the CA keys and be within its validity window, with `cert_principals`
+/// it must also be valid for one of the principals.
+///
+/// # Errors
+///
+/// Returns an error if the key can't be parsed or the certificate is not
+/// trusted.
+pub fn public_key(key: &str) -> Result<PublicKey> {
+    let key = key.trim();
+
+    if !is_certificate(key) {
+        return PublicKey::from_openssh(key)
+            .context("Ensure you are passing a valid openssh public key");
+    }
+
+    let certificate = Certificate::from_openssh(key)
+        .context("Ensure you are passing a valid openssh certificate")?;
+
+    // a host certificate doesn't identify a recipient
+    if certificate.cert_type() != CertType::User {
+        return Err(anyhow!(
+            "Certificate {} is not a user certificate",
+            certificate.key_id()
+        ));
+    }
+
+    if let Some(krl) = krl::load()?
+        && krl.is_certificate_revoked(&certificate)
+    {
+        return Err(anyhow!("Certificate {} is revoked", certificate.key_id()));
+    }
+
+    let config = config::get()?;
+
+    let mut authorities = Vec::new();
+
+    for path in config::get_paths(&config, "cert_authority")? {
+        let keys = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read the CA keys {}", path.display()))?;
+
+        authorities.extend(ca_keys(&keys));
+    }
+
+    if !authorities.is_empty() {
+        let principals: Vec<String> = if let Ok(principals) = config.get("cert_principals") {
+            principals
+        } else if let Ok(principals) = config.get_string("cert_principals") {
+            principals
+                .split(',')
+                .map(|p| p.trim().to_string())
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        validate(&certificate, &authorities, &principals, now)?;
+    }
+
+    Ok(PublicKey::from(certificate.public_key().clone()))
+}
+
+/// Validate a certificate: signed by one of the authorities, valid at `now`
+/// (unix time) and, if principals are given, valid for one of them.
+///
+/// # Errors
+///
+/// Returns an error describing why the certificate is not trusted.
+pub fn validate(
+    certificate: &Certificate,
+    authorities: &[PublicKey],
+    principals: &[String],
+    now: u64,
+) -> Result<()> {
+    let fingerprints = authorities
+        .iter()
+        .map(|ca| ca.fingerprint(HashAlg::Sha256))
+        .collect::<Vec<_>>();
+
+    let ca = certificate.signature_key().fingerprint(HashAlg::Sha256);
+
+    if !fingerprints.contains(&ca) {
+        return Err(anyhow!(
+            "Certificate {} is not signed by a trusted CA ({ca})",
+            certificate.key_id()
+        ));
+    }
+
+    if now < certificate.valid_after() || now >= certificate.valid_before() {
+        return Err(anyhow!(
+            "Certificate {} is not valid at this time, valid from {} to {}",
+            certificate.key_id(),
+            certificate.valid_after(),
+            certificate.valid_before()
+        ));
+    }
+
+    // verify the signature, the CA and the validity window are checked first
+    // for a meaningful error
+    certificate.validate_at(now, &fingerprints).map_err(|_| {
+        anyhow!(
+            "Certificate {} has an invalid signature",
+            certificate.key_id()
+        )
+    })?;
+
+    if !principals.is_empty()
+        && !certificate
+            .valid_principals()
+            .iter()
+            .any(|principal| principals.contains(principal))
+    {
+        return Err(anyhow!(
+            "Certificate {} is not valid for the principals {}",
+            certificate.key_id(),
+            principals.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+// CA keys, one per line as a public key, an authorized_keys `cert-authority`
+// entry or a known_hosts `@cert-authority` entry
+fn ca_keys(keys: &str) -> Vec<PublicKey> {
+    keys.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+
+            let line = if let Some(rest) = line.strip_prefix("@cert-authority") {
+                // skip the host patterns
+                rest.trim_start()
+                    .split_once(char::is_whitespace)
+                    .map_or("", |(_, key)| key)
+            } else {
+                line.strip_prefix("cert-authority").unwrap_or(line)
+            };
+
+            PublicKey::from_openssh(line.trim()).ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    // 2024-01-01T00:00:00Z
+    const NOW: u64 = 1_704_067_200;
+
+    fn certificate(path: &str) -> Certificate {
+        Certificate::from_openssh(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn ca() -> PublicKey {
+        PublicKey::read_openssh_file("test_data/ca_ed25519.pub".as_ref()).unwrap()
+    }
+
+    #[test]
+    fn test_is_certificate() {
+        assert!(is_certificate(
+            &fs::read_to_string("test_data/ed25519-cert.pub").unwrap()
+        ));
+        assert!(!is_certificate(
+            &fs::read_to_string("test_data/ed25519.pub").unwrap()
+        ));
+        assert!(!is_certificate(""));
+    }
+
+    #[test]
+    fn test_validate() {
+        let cert = certificate("test_data/ed25519-cert.pub");
+        let principals = |p: &[&str]| p.iter().map(ToString::to_string).collect::<Vec<_>>();
+
+        assert!(validate(&cert, &[ca()], &[], NOW).is_ok());
+        assert!(validate(&cert, &[ca()], &principals(&["bob", "deploy"]), NOW).is_ok());
+        assert!(validate(&cert, &[ca()], &principals(&["bob"]), NOW).is_err());
+
+        // not signed by the CA
+        let other = PublicKey::read_openssh_file("test_data/ed25519.pub".as_ref()).unwrap();
+        assert!(validate(&cert, &[other], &[], NOW).is_err());
+
+        let expired = certificate("test_data/ed25519_expired-cert.pub");
+        let err = validate(&expired, &[ca()], &[], NOW).unwrap_err();
+        assert!(err.to_string().contains("not valid at this time"));
+    }
+
+    #[test]
+    fn test_ca_keys() {
+        let ca = fs::read_to_string("test_data/ca_ed25519.pub").unwrap();
+        let keys = format!(
+            "# comment\n{ca}\ncert-authority {ca}\n@cert-authority *.example.com {ca}\ninvalid\n"
+        );
+        assert_eq!(ca_keys(&keys).len(), 3);
+    }
+
+    #[test]
+    fn test_public_key() {
+        let home = TempDir::new().unwrap();
+        let cert = fs::read_to_string("test_data/ed25519-cert.pub").unwrap();
+        let expired = fs::read_to_string("test_data/ed25519_expired-cert.pub").unwrap();
+        let host = fs::read_to_string("test_data/ed25519_host-cert.pub").unwrap();
+        let key = PublicKey::read_openssh_file("test_data/ed25519.pub".as_ref()).unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_CERT_AUTHORITY", None),
+                ("SSH_VAULT_CERT_PRINCIPALS", None),
+            ],
+            || {
+                // without a CA the embedded key is used
+                assert_eq!(public_key(&cert).unwrap().key_data(), key.key_data());
+                assert!(public_key(&expired).is_ok());
+
+                // a host certificate is never a recipient
+                let err = public_key(&host).unwrap_err();
+                assert!(err.to_string().contains("not a user certificate"));
+            },
+        );
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                (
+                    "SSH_VAULT_CERT_AUTHORITY",
+                    Some("test_data/ca_ed25519.pub".as_ref()),
+                ),
+                ("SSH_VAULT_CERT_PRINCIPALS", Some("alice".as_ref())),
+            ],
+            || {
+                assert_eq!(public_key(&cert).unwrap().key_data(), key.key_data());
+                assert!(public_key(&expired).is_err());
+                assert!(public_key(&host).is_err());
+                // plain keys are not affected
+                assert!(public_key(&key.to_openssh().unwrap()).is_ok());
+            },
+        );
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                (
+                    "SSH_VAULT_CERT_AUTHORITY",
+                    Some("test_data/ca_ed25519.pub".as_ref()),
+                ),
+                ("SSH_VAULT_CERT_PRINCIPALS", Some("bob, carol".as_ref())),
+            ],
+            || {
+                assert!(public_key(&cert).is_err());
+            },
+        );
+    }
+}
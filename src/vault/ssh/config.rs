@@ -0,0 +1,399 @@
+use crate::tools;
+use anyhow::{Context, Result, anyhow};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+// nested Include directives deeper than this are rejected, like ssh does
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Return the `IdentityFile` entries of `~/.ssh/config` in the order they are
+/// declared, a missing config has no entries.
+///
+/// With a host alias the entries of the matching `Host` blocks are returned,
+/// without one only the entries that apply to every host (outside any `Host`
+/// block or in `Host *`). `Include` is followed, `Match` blocks are skipped.
+///
+/// # Errors
+///
+/// Returns an error if the config or an included file can't be read.
+pub fn identity_files(host: Option<&str>) -> Result<Vec<PathBuf>> {
+    let config = tools::get_home()?.join(".ssh").join("config");
+
+    if !config.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let mut parser = Parser {
+        host,
+        identity_files: Vec::new(),
+    };
+
+    parser.parse(&config, 0)?;
+
+    Ok(parser.identity_files)
+}
+
+struct Parser<'a> {
+    host: Option<&'a str>,
+    identity_files: Vec<PathBuf>,
+}
+
+impl Parser<'_> {
+    fn parse(&mut self, path: &Path, depth: usize) -> Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(anyhow!(
+                "Too many nested Include directives in {}",
+                path.display()
+            ));
+        }
+
+        let config = fs::read_to_string(path)
+            .with_context(|| format!("Could not read ssh config {}", path.display()))?;
+
+        // entries before the first Host/Match block apply to every host
+        let mut active = true;
+
+        for line in config.lines() {
+            let Some((keyword, args)) = directive(line) else {
+                continue;
+            };
+
+            match keyword.to_lowercase().as_str() {
+                "host" => active = self.host_matches(&args),
+                "match" => active = args.iter().all(|arg| arg.eq_ignore_ascii_case("all")),
+                "include" if active => {
+                    for pattern in &args {
+                        for include in glob(&tools::expand_home(pattern)?)? {
+                            self.parse(&include, depth + 1)?;
+                        }
+                    }
+                }
+                "identityfile" if active => {
+                    for identity_file in &args {
+                        self.identity_files.push(self.expand(identity_file)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // a Host line matches if any pattern matches and no negated pattern
+    // matches, without a host alias only `Host *` matches
+    fn host_matches(&self, patterns: &[String]) -> bool {
+        let Some(host) = self.host else {
+            return patterns.iter().any(|pattern| pattern == "*");
+        };
+
+        let host = host.to_lowercase();
+        let mut matches = false;
+
+        for pattern in patterns {
+            if let Some(pattern) = pattern.strip_prefix('!') {
+                if wildcard(&pattern.to_lowercase(), &host) {
+                    return false;
+                }
+            } else if wildcard(&pattern.to_lowercase(), &host) {
+                matches = true;
+            }
+        }
+
+        matches
+    }
+
+    // expand `~` and the %d (home), %u (local user), %h (host) and %% tokens
+    fn expand(&self, path: &str) -> Result<PathBuf> {
+        let mut expanded = String::new();
+        let mut chars = path.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                expanded.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('%') | None => expanded.push('%'),
+                Some('d') => expanded.push_str(&tools::get_home()?.to_string_lossy()),
+                Some('u') => expanded.push_str(
+                    &env::var("USER")
+                        .or_else(|_| env::var("USERNAME"))
+                        .unwrap_or_default(),
+                ),
+                Some('h') if let Some(host) = self.host => expanded.push_str(host),
+                Some(token) => {
+                    expanded.push('%');
+                    expanded.push(token);
+                }
+            }
+        }
+
+        tools::expand_home(&expanded)
+    }
+}
+
+// split a config line in keyword and arguments, the keyword can be separated
+// by spaces or `=` and arguments can be quoted
+fn directive(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (keyword, rest) = line
+        .split_once(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or((line, ""));
+
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest);
+
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut quoted = false;
+
+    for c in rest.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !arg.is_empty() {
+                    args.push(std::mem::take(&mut arg));
+                }
+            }
+            c => arg.push(c),
+        }
+    }
+
+    if !arg.is_empty() {
+        args.push(arg);
+    }
+
+    Some((keyword.to_string(), args))
+}
+
+// expand `*` and `?` in the file name, relative paths are relative to ~/.ssh
+fn glob(pattern: &Path) -> Result<Vec<PathBuf>> {
+    let pattern = if pattern.is_relative() {
+        tools::get_home()?.join(".ssh").join(pattern)
+    } else {
+        pattern.to_path_buf()
+    };
+
+    let name = pattern
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    if !name.contains(['*', '?']) {
+        return Ok(if pattern.is_file() {
+            vec![pattern]
+        } else {
+            Vec::new()
+        });
+    }
+
+    let Some(dir) = pattern.parent() else {
+        return Ok(Vec::new());
+    };
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.is_file()
+                        && path
+                            .file_name()
+                            .is_some_and(|file| wildcard(&name, &file.to_string_lossy()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Match a pattern with `*` (any sequence) and `?` (any character).
+#[must_use]
+pub fn wildcard(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match (pattern.get(p), text.get(t)) {
+            (Some('*'), _) => {
+                star = Some((p, t));
+                p += 1;
+            }
+            (Some('?'), _) => {
+                p += 1;
+                t += 1;
+            }
+            (Some(pc), Some(tc)) if pc == tc => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((sp, st)) => {
+                    p = sp + 1;
+                    t = st + 1;
+                    star = Some((sp, st + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern
+        .get(p..)
+        .unwrap_or_default()
+        .iter()
+        .all(|c| *c == '*')
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const CONFIG: &str = r#"
+# global
+IdentityFile ~/.ssh/global_ed25519
+
+Include conf.d/*.conf
+
+Host work work-*.example.com !work-old.example.com
+    HostName work.example.com
+    IdentityFile ~/.ssh/work_ed25519
+    IdentityFile="%d/.ssh/work %h"
+
+Host *
+    IdentityFile ~/.ssh/default_rsa
+
+Match host other
+    IdentityFile ~/.ssh/match_rsa
+"#;
+
+    fn setup() -> TempDir {
+        let home = TempDir::new().unwrap();
+        let ssh_home = home.path().join(".ssh");
+        fs::create_dir_all(ssh_home.join("conf.d")).unwrap();
+        fs::write(ssh_home.join("config"), CONFIG).unwrap();
+        fs::write(
+            ssh_home.join("conf.d").join("git.conf"),
+            "Host git\n  IdentityFile /keys/git_ecdsa\n",
+        )
+        .unwrap();
+        fs::write(
+            ssh_home.join("conf.d").join("ignored.txt"),
+            "IdentityFile /x",
+        )
+        .unwrap();
+        home
+    }
+
+    #[test]
+    fn test_identity_files() {
+        let home = setup();
+        let ssh_home = home.path().join(".ssh");
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            assert_eq!(
+                identity_files(None).unwrap(),
+                vec![
+                    ssh_home.join("global_ed25519"),
+                    ssh_home.join("default_rsa")
+                ]
+            );
+
+            assert_eq!(
+                identity_files(Some("WORK")).unwrap(),
+                vec![
+                    ssh_home.join("global_ed25519"),
+                    ssh_home.join("work_ed25519"),
+                    ssh_home.join("work WORK"),
+                    ssh_home.join("default_rsa"),
+                ]
+            );
+
+            assert_eq!(
+                identity_files(Some("work-1.example.com")).unwrap().get(1),
+                Some(&ssh_home.join("work_ed25519"))
+            );
+
+            assert_eq!(
+                identity_files(Some("work-old.example.com")).unwrap(),
+                vec![
+                    ssh_home.join("global_ed25519"),
+                    ssh_home.join("default_rsa")
+                ]
+            );
+
+            assert_eq!(
+                identity_files(Some("git")).unwrap(),
+                vec![
+                    ssh_home.join("global_ed25519"),
+                    PathBuf::from("/keys/git_ecdsa"),
+                    ssh_home.join("default_rsa"),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_identity_files_no_config() {
+        let home = TempDir::new().unwrap();
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            assert!(identity_files(Some("work")).unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_include_loop() {
+        let home = TempDir::new().unwrap();
+        let ssh_home = home.path().join(".ssh");
+        fs::create_dir_all(&ssh_home).unwrap();
+        fs::write(ssh_home.join("config"), "Include config\n").unwrap();
+
+        temp_env::with_var("HOME", Some(home.path()), || {
+            assert!(identity_files(None).is_err());
+        });
+    }
+
+    #[test]
+    fn test_directive() {
+        assert_eq!(directive("  # comment"), None);
+        assert_eq!(
+            directive("IdentityFile=\"~/my key\""),
+            Some(("IdentityFile".to_string(), vec!["~/my key".to_string()]))
+        );
+        assert_eq!(
+            directive("Host a b"),
+            Some(("Host".to_string(), vec!["a".to_string(), "b".to_string()]))
+        );
+    }
+
+    #[test]
+    fn test_wildcard() {
+        assert!(wildcard("*", "anything"));
+        assert!(wildcard("*.example.com", "a.example.com"));
+        assert!(wildcard("h?st", "host"));
+        assert!(wildcard("a*b*c", "axxbyyc"));
+        assert!(!wildcard("*.example.com", "example.org"));
+        assert!(!wildcard("h?st", "hoost"));
+        assert!(wildcard("", ""));
+        assert!(!wildcard("", "a"));
+    }
+}
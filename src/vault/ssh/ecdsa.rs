@@ -0,0 +1,317 @@
+use crate::vault::{
+    Algorithm, Metadata, ParsedVault, Recipient, SshKeyType, Vault, crypto, crypto::Crypto,
+    crypto::chacha20poly1305::ChaCha20Poly1305Crypto,
+};
+use anyhow::{Context, Result, anyhow};
+use p256::elliptic_curve::{
+    AffinePoint, CurveArithmetic, FieldBytesSize, PublicKey as EcPublicKey, SecretKey,
+    ecdh::{EphemeralSecret, diffie_hellman},
+    rand_core::OsRng,
+    sec1::{FromEncodedPoint, ModulusSize, ToEncodedPoint},
+};
+use secrecy::{ExposeSecret, SecretSlice};
+use ssh_key::{EcdsaCurve, HashAlg, PrivateKey, PublicKey, private::KeypairData, public::KeyData};
+use zeroize::Zeroizing;
+
+/// ECDSA keys (nistp256, nistp384 and nistp521)
+///
+/// The password is wrapped like for Ed25519 keys but using ECDH over the curve
+/// of the key: an ephemeral key pair is generated, the shared secret goes
+/// through HKDF and the derived key encrypts the password with
+/// ChaCha20-Poly1305.
+pub struct EcdsaVault {
+    curve: EcdsaCurve,
+    public_key: PublicKey,
+    private_key: Option<Zeroizing<Vec<u8>>>,
+}
+
+impl Vault for EcdsaVault {
+    fn new(public: Option<PublicKey>, private: Option<PrivateKey>) -> Result<Self> {
+        match (public, private) {
+            (Some(public), None) => match public.key_data() {
+                KeyData::Ecdsa(key_data) => {
+                    let curve = key_data.curve();
+
+                    // reject points that are not on the curve
+                    validate(curve, key_data.as_sec1_bytes()).context("Could not load key")?;
+
+                    Ok(Self {
+                        curve,
+                        public_key: public,
+                        private_key: None,
+                    })
+                }
+                _ => Err(anyhow!("Invalid key type for EcdsaVault")),
+            },
+            (None, Some(private)) => match private.key_data() {
+                KeypairData::Ecdsa(keypair) => {
+                    if private.is_encrypted() {
+                        return Err(anyhow!("Private key is encrypted"));
+                    }
+
+                    Ok(Self {
+                        curve: keypair.curve(),
+                        public_key: private.public_key().clone(),
+                        private_key: Some(Zeroizing::new(keypair.private_key_bytes().to_vec())),
+                    })
+                }
+                _ => Err(anyhow!("Invalid key type for EcdsaVault")),
+            },
+            (Some(_), Some(_)) => Err(anyhow!("Only one of public and private key is required")),
+            _ => Err(anyhow!("Missing public and private key")),
+        }
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::ChaCha20Poly1305
+    }
+
+    fn fingerprint(&self) -> Result<String> {
+        Ok(self.public_key.fingerprint(HashAlg::Sha256).to_string())
+    }
+
+    fn wrap(&self, password: &SecretSlice<u8>) -> Result<Recipient> {
+        let fingerprint = self.fingerprint()?;
+        let point = self.point()?;
+
+        let (e_public, shared_secret) = match self.curve {
+            EcdsaCurve::NistP256 => ephemeral::<p256::NistP256>(point),
+            EcdsaCurve::NistP384 => ephemeral::<p384::NistP384>(point),
+            EcdsaCurve::NistP521 => ephemeral::<p521::NistP521>(point),
+        }?;
+
+        // the salt is the concatenation of the
+        // ephemeral public key and the receiver's public key
+        let salt = [e_public.as_slice(), point].concat();
+
+        let enc_key = crypto::hkdf(&salt, fingerprint.as_bytes(), &shared_secret)?;
+
+        // encrypt the password with the derived key
+        let crypto = ChaCha20Poly1305Crypto::new(SecretSlice::new(enc_key.into()));
+        let wrapped_key = crypto.encrypt(password.expose_secret(), fingerprint.as_bytes())?;
+
+        Ok(Recipient {
+            key_type: SshKeyType::Ecdsa,
+            fingerprint,
+            ephemeral_key: Some(e_public),
+            wrapped_key,
+        })
+    }
+
+    fn unwrap(&self, recipient: &Recipient) -> Result<SecretSlice<u8>> {
+        let fingerprint = self.fingerprint()?;
+
+        if recipient.key_type != SshKeyType::Ecdsa || fingerprint != recipient.fingerprint {
+            return Err(anyhow!("Fingerprint mismatch, use correct key"));
+        }
+
+        let private_key = self
+            .private_key
+            .as_ref()
+            .ok_or_else(|| anyhow!("Private key is required to view vault"))?;
+
+        let e_public = recipient.ephemeral_key.as_deref().unwrap_or_default();
+
+        let shared_secret = match self.curve {
+            EcdsaCurve::NistP256 => agree::<p256::NistP256>(private_key, e_public),
+            EcdsaCurve::NistP384 => agree::<p384::NistP384>(private_key, e_public),
+            EcdsaCurve::NistP521 => agree::<p521::NistP521>(private_key, e_public),
+        }?;
+
+        let salt = [e_public, self.point()?].concat();
+
+        let enc_key = crypto::hkdf(&salt, fingerprint.as_bytes(), &shared_secret)?;
+
+        // use the enc_key to decrypt the password
+        let crypto = ChaCha20Poly1305Crypto::new(SecretSlice::new(enc_key.into()));
+
+        let password = crypto.decrypt(&recipient.wrapped_key, fingerprint.as_bytes())?;
+
+        if password.len() != 32 {
+            return Err(anyhow!(
+                "Invalid decrypted password: expected 32 bytes, got {}",
+                password.len()
+            ));
+        }
+
+        Ok(SecretSlice::new(password.into()))
+    }
+
+    // a single recipient vault uses the SSH-VAULT-V2 format, the legacy
+    // format only supports RSA and Ed25519 recipients
+    fn create(&self, password: SecretSlice<u8>, data: &mut [u8]) -> Result<String> {
+        let recipient = self.wrap(&password)?;
+        let vault = ParsedVault::seal_v2(Metadata::now()?, vec![recipient], &password, data)?;
+        Ok(vault.to_string())
+    }
+}
+
+impl EcdsaVault {
+    // SEC1 encoded public key
+    fn point(&self) -> Result<&[u8]> {
+        match self.public_key.key_data() {
+            KeyData::Ecdsa(key_data) => Ok(key_data.as_sec1_bytes()),
+            _ => Err(anyhow!("Invalid key type for EcdsaVault")),
+        }
+    }
+}
+
+fn validate(curve: EcdsaCurve, point: &[u8]) -> Result<()> {
+    match curve {
+        EcdsaCurve::NistP256 => EcPublicKey::<p256::NistP256>::from_sec1_bytes(point).map(|_| ()),
+        EcdsaCurve::NistP384 => EcPublicKey::<p384::NistP384>::from_sec1_bytes(point).map(|_| ()),
+        EcdsaCurve::NistP521 => EcPublicKey::<p521::NistP521>::from_sec1_bytes(point).map(|_| ()),
+    }
+    .map_err(|_| anyhow!("Invalid {curve} public key"))
+}
+
+// generate an ephemeral key pair and return the SEC1 encoded public key and
+// the shared secret with the receiver's public key
+fn ephemeral<C>(point: &[u8]) -> Result<(Vec<u8>, Zeroizing<Vec<u8>>)>
+where
+    C: CurveArithmetic,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+    FieldBytesSize<C>: ModulusSize,
+{
+    let public_key = EcPublicKey::<C>::from_sec1_bytes(point)
+        .map_err(|_| anyhow!("Invalid ECDSA public key"))?;
+
+    let e_secret = EphemeralSecret::<C>::random(&mut OsRng);
+    let e_public = e_secret
+        .public_key()
+        .to_encoded_point(false)
+        .as_bytes()
+        .to_vec();
+
+    let shared_secret = e_secret.diffie_hellman(&public_key);
+
+    Ok((
+        e_public,
+        Zeroizing::new(shared_secret.raw_secret_bytes().to_vec()),
+    ))
+}
+
+// compute the shared secret with the ephemeral public key of the vault
+fn agree<C>(private_key: &[u8], e_public: &[u8]) -> Result<Zeroizing<Vec<u8>>>
+where
+    C: CurveArithmetic,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+    FieldBytesSize<C>: ModulusSize,
+{
+    let secret_key = SecretKey::<C>::from_slice(private_key)
+        .map_err(|_| anyhow!("Invalid ECDSA private key"))?;
+
+    let e_public = EcPublicKey::<C>::from_sec1_bytes(e_public)
+        .map_err(|_| anyhow!("Invalid ephemeral public key"))?;
+
+    let shared_secret = diffie_hellman(secret_key.to_nonzero_scalar(), e_public.as_affine());
+
+    Ok(Zeroizing::new(shared_secret.raw_secret_bytes().to_vec()))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::vault::{SshVault, find, parse, ssh::decrypt_private_key};
+    use secrecy::SecretString;
+    use std::path::Path;
+
+    const SECRET: &str = "Elliptic curves are everywhere";
+
+    #[test]
+    fn test_ecdsa_vault() -> Result<()> {
+        for key in [
+            "test_data/ecdsa_p256",
+            "test_data/ecdsa_p384",
+            "test_data/ecdsa_p521",
+        ] {
+            let public_key = PublicKey::read_openssh_file(Path::new(&format!("{key}.pub")))?;
+            assert_eq!(find::key_type(&public_key.algorithm())?, SshKeyType::Ecdsa);
+
+            let vault = EcdsaVault::new(Some(public_key), None)?;
+
+            let mut secret = String::from(SECRET).into_bytes();
+            let data = vault.create(crypto::gen_password()?, &mut secret)?;
+
+            // filled with zeros
+            assert!(secret.iter().all(|&byte| byte == 0));
+            assert!(data.starts_with("SSH-VAULT-V2;CHACHA20-POLY1305;"));
+
+            let parsed = parse(&data)?;
+            assert_eq!(parsed.to_string(), data);
+            assert_eq!(
+                parsed.recipients.first().map(|r| r.key_type),
+                Some(SshKeyType::Ecdsa)
+            );
+
+            let private_key = PrivateKey::read_openssh_file(Path::new(key))?;
+            let view = SshVault::new(&SshKeyType::Ecdsa, None, Some(private_key))?;
+            assert_eq!(view.view(&parsed)?.expose_secret(), SECRET.as_bytes());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_ecdsa_vault_password() -> Result<()> {
+        let public_key =
+            PublicKey::read_openssh_file(Path::new("test_data/ecdsa_p256_password.pub"))?;
+        let vault = EcdsaVault::new(Some(public_key), None)?;
+
+        let mut secret = String::from(SECRET).into_bytes();
+        let parsed = parse(&vault.create(crypto::gen_password()?, &mut secret)?)?;
+
+        let private_key =
+            PrivateKey::read_openssh_file(Path::new("test_data/ecdsa_p256_password"))?;
+        assert!(EcdsaVault::new(None, Some(private_key.clone())).is_err());
+
+        // echo -n "ssh-vault" | openssl dgst -sha1
+        let private_key = decrypt_private_key(
+            &private_key,
+            Some(SecretString::from(
+                "85990de849bb89120ea3016b6b76f6d004857cb7",
+            )),
+        )?;
+        let view = EcdsaVault::new(None, Some(private_key))?;
+        assert_eq!(view.view(&parsed)?.expose_secret(), SECRET.as_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ecdsa_vault_wrong_key() -> Result<()> {
+        let public_key = PublicKey::read_openssh_file(Path::new("test_data/ecdsa_p256.pub"))?;
+        let vault = EcdsaVault::new(Some(public_key), None)?;
+
+        let mut secret = String::from(SECRET).into_bytes();
+        let parsed = parse(&vault.create(crypto::gen_password()?, &mut secret)?)?;
+
+        // other curve
+        let private_key = PrivateKey::read_openssh_file(Path::new("test_data/ecdsa_p384"))?;
+        let view = EcdsaVault::new(None, Some(private_key))?.view(&parsed);
+        assert!(
+            view.unwrap_err()
+                .to_string()
+                .contains("Fingerprint mismatch")
+        );
+
+        // invalid ephemeral key
+        let mut tampered = parsed;
+        if let Some(recipient) = tampered.recipients.first_mut() {
+            recipient.ephemeral_key = Some(vec![4_u8; 65]);
+        }
+        let private_key = PrivateKey::read_openssh_file(Path::new("test_data/ecdsa_p256"))?;
+        assert!(
+            EcdsaVault::new(None, Some(private_key))?
+                .view(&tampered)
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ecdsa_vault_invalid_key() -> Result<()> {
+        let public_key = PublicKey::read_openssh_file(Path::new("test_data/ed25519.pub"))?;
+        assert!(EcdsaVault::new(Some(public_key), None).is_err());
+        Ok(())
+    }
+}
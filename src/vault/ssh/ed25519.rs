@@ -1,8 +1,9 @@
 use crate::vault::{
-    Vault, crypto, crypto::Crypto, crypto::chacha20poly1305::ChaCha20Poly1305Crypto,
+    Algorithm, Recipient, SshKeyType, Vault, crypto, crypto::Crypto,
+    crypto::chacha20poly1305::ChaCha20Poly1305Crypto, ssh::agent,
 };
 use anyhow::{Context, Result};
-use base64ct::{Base64, Encoding};
+use ed25519_dalek::Signer;
 use secrecy::{ExposeSecret, SecretSlice};
 use sha2::{Digest, Sha512};
 use ssh_key::{
@@ -11,7 +12,6 @@ use ssh_key::{
     public::KeyData,
 };
 use x25519_dalek::{EphemeralSecret, PublicKey as X25519PublicKey, StaticSecret};
-use zeroize::Zeroize;
 
 #[allow(clippy::struct_field_names)]
 pub struct Ed25519Vault {
@@ -60,17 +60,16 @@ impl Vault for Ed25519Vault {
         }
     }
 
-    fn create(&self, password: SecretSlice<u8>, data: &mut [u8]) -> Result<String> {
-        let crypto = ChaCha20Poly1305Crypto::new(password.clone());
-
-        // get the fingerprint of the public key
-        let fingerprint = self.public_key.fingerprint(HashAlg::Sha256);
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::ChaCha20Poly1305
+    }
 
-        // encrypt the data with the password
-        let encrypted_data = crypto.encrypt(data, fingerprint.as_bytes())?;
+    fn fingerprint(&self) -> Result<String> {
+        Ok(self.public_key.fingerprint(HashAlg::Sha256).to_string())
+    }
 
-        // zeroize data
-        data.zeroize();
+    fn wrap(&self, password: &SecretSlice<u8>) -> Result<Recipient> {
+        let fingerprint = self.fingerprint()?;
 
         // generate an ephemeral key pair
         let e_secret = EphemeralSecret::random();
@@ -89,48 +88,43 @@ impl Vault for Ed25519Vault {
 
         // encrypt the password with the derived key
         let crypto = ChaCha20Poly1305Crypto::new(SecretSlice::new(enc_key.into()));
-        let encrypted_password =
-            crypto.encrypt(password.expose_secret(), fingerprint.as_bytes())?;
+        let wrapped_key = crypto.encrypt(password.expose_secret(), fingerprint.as_bytes())?;
 
-        // create vault payload
-        Ok(format!(
-            "SSH-VAULT;CHACHA20-POLY1305;{};{};{};{}",
+        Ok(Recipient {
+            key_type: SshKeyType::Ed25519,
             fingerprint,
-            Base64::encode_string(e_public.as_bytes()),
-            Base64::encode_string(&encrypted_password),
-            Base64::encode_string(&encrypted_data)
-        )
-        .chars()
-        .collect::<Vec<_>>()
-        .chunks(64)
-        .map(|chunk| chunk.iter().collect::<String>())
-        .collect::<Vec<_>>()
-        .join("\n"))
+            ephemeral_key: Some(e_public.as_bytes().to_vec()),
+            wrapped_key,
+        })
     }
 
-    fn view(&self, password: &[u8], data: &[u8], fingerprint: &str) -> Result<String> {
-        let get_fingerprint = self.public_key.fingerprint(HashAlg::Sha256);
+    fn unwrap(&self, recipient: &Recipient) -> Result<SecretSlice<u8>> {
+        let fingerprint = self.fingerprint()?;
 
-        if get_fingerprint.to_string() != fingerprint {
+        if !matches!(recipient.key_type, SshKeyType::Ed25519 | SshKeyType::Agent)
+            || fingerprint != recipient.fingerprint
+        {
             return Err(anyhow::anyhow!("Fingerprint mismatch, use correct key"));
         }
 
         match &self.private_key {
-            Some(private_key) => {
-                // Validate password length before slicing
-                if password.len() < 32 {
-                    return Err(anyhow::anyhow!(
-                        "Invalid password data: too short (expected at least 32 bytes, got {})",
-                        password.len()
-                    ));
-                }
-
-                // extract the ephemeral public key
-                let (ephemeral_bytes, encrypted_password) = password.split_at(32);
-                let mut epk: [u8; 32] = [0; 32];
-                epk.copy_from_slice(ephemeral_bytes);
+            // sign the challenge like ssh-agent would do
+            Some(private_key) if recipient.key_type == SshKeyType::Agent => {
+                let challenge = recipient.ephemeral_key.as_deref().unwrap_or_default();
+                let signing_key = ed25519_dalek::SigningKey::from_bytes(&private_key.to_bytes());
+                let signature = signing_key.sign(&agent::message(challenge)).to_bytes();
 
+                agent::unwrap_password(recipient, &SecretSlice::new(signature.into()))
+            }
+            Some(private_key) => {
                 // decode the ephemeral public key
+                let ephemeral_key = recipient.ephemeral_key.as_deref().unwrap_or_default();
+                let epk: [u8; 32] = ephemeral_key.try_into().map_err(|_| {
+                    anyhow::anyhow!(
+                        "Invalid ephemeral public key: too short (expected 32 bytes, got {})",
+                        ephemeral_key.len()
+                    )
+                })?;
                 let epk = X25519PublicKey::from(epk);
 
                 // generate the static secret and public key
@@ -155,12 +149,12 @@ impl Vault for Ed25519Vault {
                 salt[32..].copy_from_slice(pk.as_bytes());
 
                 let enc_key =
-                    crypto::hkdf(&salt, get_fingerprint.as_bytes(), shared_secret.as_bytes())?;
+                    crypto::hkdf(&salt, fingerprint.as_bytes(), shared_secret.as_bytes())?;
 
                 // use the enc_key to decrypt the password
                 let crypto = ChaCha20Poly1305Crypto::new(SecretSlice::new(enc_key.into()));
 
-                let password = crypto.decrypt(encrypted_password, get_fingerprint.as_bytes())?;
+                let password = crypto.decrypt(&recipient.wrapped_key, fingerprint.as_bytes())?;
 
                 // Validate decrypted password length before slicing
                 if password.len() < 32 {
@@ -177,11 +171,7 @@ impl Vault for Ed25519Vault {
                         .ok_or_else(|| anyhow::anyhow!("password too short"))?,
                 );
 
-                // decrypt the data with the derived key
-                let crypto = ChaCha20Poly1305Crypto::new(SecretSlice::new(p.into()));
-
-                let out = crypto.decrypt(data, get_fingerprint.as_bytes())?;
-                Ok(String::from_utf8(out)?)
+                Ok(SecretSlice::new(p.into()))
             }
             None => Err(anyhow::anyhow!("Private key is required to view vault")),
         }
@@ -191,43 +181,56 @@ impl Vault for Ed25519Vault {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vault::{Metadata, ParsedVault, Version};
     use anyhow::Result;
+    use std::path::Path;
 
     const TEST_ED25519_PUBLIC_KEY: &str =
         "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAILr6U238r+PD4rSvZAu/RNJfaNgzglzSvdLKA28h4kB1";
 
+    fn parsed_vault(fingerprint: &str, ephemeral_key: Vec<u8>) -> ParsedVault {
+        ParsedVault {
+            version: Version::V1,
+            algorithm: Algorithm::ChaCha20Poly1305,
+            metadata: Metadata::default(),
+            recipients: vec![Recipient {
+                key_type: SshKeyType::Ed25519,
+                fingerprint: fingerprint.to_string(),
+                ephemeral_key: Some(ephemeral_key),
+                wrapped_key: vec![0u8; 48],
+            }],
+            ciphertext: vec![0u8; 50],
+        }
+    }
+
     #[test]
-    fn test_ed25519_view_short_password_data() -> Result<()> {
-        // Create an Ed25519 vault with a public key
-        let public_key = TEST_ED25519_PUBLIC_KEY.parse::<PublicKey>()?;
-        let vault = Ed25519Vault::new(Some(public_key), None)?;
+    fn test_ed25519_view_short_ephemeral_key() -> Result<()> {
+        let private_key = PrivateKey::read_openssh_file(Path::new("test_data/ed25519"))?;
+        let vault = Ed25519Vault::new(None, Some(private_key))?;
+        let fingerprint = vault.fingerprint()?;
 
-        // Test with password data shorter than 32 bytes
+        // Test with an ephemeral key shorter than 32 bytes
         for len in 0..32 {
-            let short_password = vec![0u8; len];
-            let data = vec![0u8; 50];
-            let fingerprint = "SHA256:test";
-
-            let result = vault.view(&short_password, &data, fingerprint);
+            let result = vault.view(&parsed_vault(&fingerprint, vec![0u8; len]));
             assert!(result.is_err(), "Should fail with {len} bytes");
             if let Err(err) = result {
                 let err_msg = err.to_string();
-                assert!(err_msg.contains("too short") || err_msg.contains("Fingerprint mismatch"));
+                assert!(err_msg.contains("too short"), "got: {err_msg}");
+                assert!(err_msg.contains(&len.to_string()));
             }
         }
         Ok(())
     }
 
     #[test]
-    fn test_ed25519_view_empty_password() -> Result<()> {
+    fn test_ed25519_view_fingerprint_mismatch() -> Result<()> {
         let public_key = TEST_ED25519_PUBLIC_KEY.parse::<PublicKey>()?;
         let vault = Ed25519Vault::new(Some(public_key), None)?;
 
-        let result = vault.view(&[], &[0u8; 50], "SHA256:test");
+        let result = vault.view(&parsed_vault("SHA256:test", vec![]));
         assert!(result.is_err());
         if let Err(err) = result {
-            let err_msg = err.to_string();
-            assert!(err_msg.contains("too short") || err_msg.contains("Fingerprint mismatch"));
+            assert!(err.to_string().contains("Fingerprint mismatch"));
         }
         Ok(())
     }
@@ -0,0 +1,235 @@
+use crate::vault::ssh::config::wildcard;
+use anyhow::{Context, Result};
+use base64ct::{Base64, Encoding};
+use hmac::{Hmac, KeyInit, Mac};
+use sha1::Sha1;
+use ssh_key::PublicKey;
+use std::{fs, path::Path};
+
+/// Return the keys of `host` found in a `known_hosts` file, a missing file
+/// has no keys.
+///
+/// The host can include a port (`host:port` or `[host]:port`), hashed
+/// (`|1|salt|hash`) and wildcard entries are matched, keys marked as
+/// `@revoked` are excluded and `@cert-authority` entries are ignored.
+///
+/// # Errors
+///
+/// Returns an error if the file exists but can't be read.
+pub fn host_keys(path: &Path, host: &str) -> Result<Vec<PublicKey>> {
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let known_hosts = fs::read_to_string(path)
+        .with_context(|| format!("Could not read known hosts {}", path.display()))?;
+
+    let name = lookup_name(host);
+
+    let mut keys = Vec::new();
+    let mut revoked = Vec::new();
+
+    for line in known_hosts.lines() {
+        let mut fields = line.split_whitespace();
+
+        let Some(mut patterns) = fields.next() else {
+            continue;
+        };
+
+        if patterns.starts_with('#') {
+            continue;
+        }
+
+        let marker = patterns.strip_prefix('@');
+
+        if marker.is_some() {
+            let Some(next) = fields.next() else {
+                continue;
+            };
+            patterns = next;
+        }
+
+        let (Some(algorithm), Some(data)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+
+        if !host_matches(patterns, &name) {
+            continue;
+        }
+
+        let Ok(key) = PublicKey::from_openssh(&format!("{algorithm} {data}")) else {
+            continue;
+        };
+
+        match marker {
+            None => keys.push(key),
+            Some("revoked") => revoked.push(key),
+            Some(_) => {}
+        }
+    }
+
+    keys.retain(|key| !revoked.iter().any(|r| r.key_data() == key.key_data()));
+
+    Ok(keys)
+}
+
+// the name used in known_hosts, non default ports are written as [host]:port
+fn lookup_name(host: &str) -> String {
+    let (name, port) = if let Some(rest) = host.strip_prefix('[') {
+        match rest.split_once(']') {
+            Some((name, port)) => (name, port.strip_prefix(':')),
+            None => (rest, None),
+        }
+    } else {
+        match host.rsplit_once(':') {
+            // IPv6 addresses without brackets have no port
+            Some((name, port)) if !name.contains(':') => (name, Some(port)),
+            _ => (host, None),
+        }
+    };
+
+    let name = name.to_lowercase();
+
+    match port {
+        Some(port) if !port.is_empty() && port != "22" => format!("[{name}]:{port}"),
+        _ => name,
+    }
+}
+
+// a list of patterns matches if any pattern matches and no negated pattern
+// matches, hashed entries hold a single host
+fn host_matches(patterns: &str, name: &str) -> bool {
+    if let Some(hashed) = patterns.strip_prefix("|1|") {
+        return hashed_matches(hashed, name);
+    }
+
+    let mut matches = false;
+
+    for pattern in patterns.split(',') {
+        if let Some(pattern) = pattern.strip_prefix('!') {
+            if wildcard(&pattern.to_lowercase(), name) {
+                return false;
+            }
+        } else if wildcard(&pattern.to_lowercase(), name) {
+            matches = true;
+        }
+    }
+
+    matches
+}
+
+// HMAC-SHA1 of the host name keyed with the salt, `salt|hash` in base64
+fn hashed_matches(hashed: &str, name: &str) -> bool {
+    let Some((salt, hash)) = hashed.split_once('|') else {
+        return false;
+    };
+
+    let (Ok(salt), Ok(hash)) = (Base64::decode_vec(salt), Base64::decode_vec(hash)) else {
+        return false;
+    };
+
+    let Ok(mut mac) = Hmac::<Sha1>::new_from_slice(&salt) else {
+        return false;
+    };
+
+    mac.update(name.as_bytes());
+
+    mac.verify_slice(&hash).is_ok()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn hashed(salt: &[u8], name: &str) -> String {
+        let mut mac = Hmac::<Sha1>::new_from_slice(salt).unwrap();
+        mac.update(name.as_bytes());
+        format!(
+            "|1|{}|{}",
+            Base64::encode_string(salt),
+            Base64::encode_string(&mac.finalize().into_bytes())
+        )
+    }
+
+    #[test]
+    fn test_host_keys() {
+        let ed25519 = fs::read_to_string("test_data/ed25519.pub").unwrap();
+        let rsa = fs::read_to_string("test_data/id_rsa.pub").unwrap();
+        let ecdsa = fs::read_to_string("test_data/ecdsa_p256.pub").unwrap();
+
+        let mut known_hosts = NamedTempFile::new().unwrap();
+        writeln!(known_hosts, "# comment").unwrap();
+        writeln!(known_hosts, "vault.example.com,10.0.0.1 {}", ed25519.trim()).unwrap();
+        writeln!(known_hosts, "*.example.org,!old.example.org {}", rsa.trim()).unwrap();
+        writeln!(known_hosts, "[vault.example.com]:2222 {}", rsa.trim()).unwrap();
+        writeln!(
+            known_hosts,
+            "{} {}",
+            hashed(b"salt", "hidden"),
+            ecdsa.trim()
+        )
+        .unwrap();
+        writeln!(known_hosts, "@cert-authority * {}", ecdsa.trim()).unwrap();
+        writeln!(known_hosts, "revoked.example.net {}", ed25519.trim()).unwrap();
+        writeln!(
+            known_hosts,
+            "@revoked revoked.example.net {}",
+            ed25519.trim()
+        )
+        .unwrap();
+        writeln!(known_hosts, "broken.example.com ssh-ed25519").unwrap();
+
+        let path = known_hosts.path();
+
+        let keys = host_keys(path, "VAULT.example.com").unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys.first().unwrap().to_openssh().unwrap(), ed25519.trim());
+
+        assert_eq!(host_keys(path, "vault.example.com:22").unwrap().len(), 1);
+        assert_eq!(host_keys(path, "10.0.0.1").unwrap().len(), 1);
+
+        let keys = host_keys(path, "[vault.example.com]:2222").unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(
+            keys.first().unwrap().algorithm(),
+            ssh_key::Algorithm::Rsa { hash: None }
+        );
+        assert_eq!(host_keys(path, "vault.example.com:2222").unwrap().len(), 1);
+
+        assert_eq!(host_keys(path, "a.example.org").unwrap().len(), 1);
+        assert!(host_keys(path, "old.example.org").unwrap().is_empty());
+        assert_eq!(host_keys(path, "hidden").unwrap().len(), 1);
+        assert!(host_keys(path, "revoked.example.net").unwrap().is_empty());
+        assert!(host_keys(path, "broken.example.com").unwrap().is_empty());
+        assert!(host_keys(path, "unknown").unwrap().is_empty());
+
+        assert!(
+            host_keys(Path::new("/nonexistent/known_hosts"), "unknown")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_lookup_name() {
+        assert_eq!(lookup_name("Host"), "host");
+        assert_eq!(lookup_name("host:22"), "host");
+        assert_eq!(lookup_name("host:2222"), "[host]:2222");
+        assert_eq!(lookup_name("[host]:2222"), "[host]:2222");
+        assert_eq!(lookup_name("[host]"), "host");
+        assert_eq!(lookup_name("::1"), "::1");
+        assert_eq!(lookup_name("[::1]:2222"), "[::1]:2222");
+    }
+
+    #[test]
+    fn test_hashed_matches() {
+        let entry = hashed(b"0123456789abcdef0123", "[host]:2222");
+        let hashed_entry = entry.strip_prefix("|1|").unwrap();
+        assert!(hashed_matches(hashed_entry, "[host]:2222"));
+        assert!(!hashed_matches(hashed_entry, "host"));
+        assert!(!hashed_matches("invalid", "host"));
+    }
+}
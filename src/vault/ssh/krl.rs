@@ -0,0 +1,416 @@
+use crate::{config, vault::fingerprint};
+use anyhow::{Context, Result, anyhow};
+use sha1::{Digest, Sha1};
+use ssh_key::{Certificate, Fingerprint, HashAlg, PublicKey};
+use std::{fs, path::Path};
+
+// "SSHKRL\n\0" followed by the format version, see PROTOCOL.krl in OpenSSH
+const KRL_MAGIC: &[u8] = b"SSHKRL\n\0";
+const KRL_FORMAT_VERSION: u32 = 1;
+
+const KRL_SECTION_CERTIFICATES: u8 = 1;
+const KRL_SECTION_EXPLICIT_KEY: u8 = 2;
+const KRL_SECTION_FINGERPRINT_SHA1: u8 = 3;
+const KRL_SECTION_SIGNATURE: u8 = 4;
+const KRL_SECTION_FINGERPRINT_SHA256: u8 = 5;
+
+const KRL_SECTION_CERT_SERIAL_LIST: u8 = 0x20;
+const KRL_SECTION_CERT_SERIAL_RANGE: u8 = 0x21;
+const KRL_SECTION_CERT_SERIAL_BITMAP: u8 = 0x22;
+const KRL_SECTION_CERT_KEY_ID: u8 = 0x23;
+
+/// Revoked keys, from an OpenSSH KRL (`ssh-keygen -k`) or a plain file with
+/// one public key per line.
+#[derive(Debug, Default)]
+pub struct Krl {
+    keys: Vec<Vec<u8>>,
+    sha1: Vec<Vec<u8>>,
+    sha256: Vec<Vec<u8>>,
+    certificates: Vec<CertificateSection>,
+}
+
+// certificates revoked by serial or key id, an empty CA matches any CA
+#[derive(Debug, Default)]
+struct CertificateSection {
+    ca_key: Vec<u8>,
+    serials: Vec<(u64, u64)>,
+    bitmaps: Vec<(u64, Vec<u8>)>,
+    key_ids: Vec<String>,
+}
+
+impl Krl {
+    /// Parse a binary KRL or a plain revoked-keys file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the KRL is malformed.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let Some(mut reader) = data.strip_prefix(KRL_MAGIC) else {
+            return Ok(Self::parse_keys(&String::from_utf8_lossy(data)));
+        };
+
+        if read_u32(&mut reader)? != KRL_FORMAT_VERSION {
+            return Err(anyhow!("Unsupported KRL format version"));
+        }
+
+        // krl_version, generated_date, flags, reserved and comment
+        read_u64(&mut reader)?;
+        read_u64(&mut reader)?;
+        read_u64(&mut reader)?;
+        read_string(&mut reader)?;
+        read_string(&mut reader)?;
+
+        let mut krl = Self::default();
+
+        while !reader.is_empty() {
+            let section_type = read_u8(&mut reader)?;
+            let mut section = read_string(&mut reader)?;
+
+            match section_type {
+                KRL_SECTION_CERTIFICATES => {
+                    krl.certificates.push(CertificateSection::parse(section)?);
+                }
+                KRL_SECTION_EXPLICIT_KEY => {
+                    while !section.is_empty() {
+                        krl.keys.push(read_string(&mut section)?.to_vec());
+                    }
+                }
+                KRL_SECTION_FINGERPRINT_SHA1 | KRL_SECTION_FINGERPRINT_SHA256 => {
+                    let hashes = if section_type == KRL_SECTION_FINGERPRINT_SHA1 {
+                        &mut krl.sha1
+                    } else {
+                        &mut krl.sha256
+                    };
+
+                    while !section.is_empty() {
+                        hashes.push(read_string(&mut section)?.to_vec());
+                    }
+                }
+                // the signatures are at the end, only the revocations are used
+                KRL_SECTION_SIGNATURE => break,
+                _ => return Err(anyhow!("Unsupported KRL section {section_type}")),
+            }
+        }
+
+        Ok(krl)
+    }
+
+    // one public key per line, comments and invalid lines are ignored
+    fn parse_keys(data: &str) -> Self {
+        Self {
+            keys: data
+                .lines()
+                .filter_map(|line| PublicKey::from_openssh(line.trim()).ok())
+                .filter_map(|key| key.to_bytes().ok())
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Return true if the key is revoked explicitly or by its SHA1/SHA256 hash.
+    #[must_use]
+    pub fn is_key_revoked(&self, key: &PublicKey) -> bool {
+        let Ok(blob) = key.to_bytes() else {
+            return false;
+        };
+
+        self.keys.contains(&blob)
+            || self
+                .sha1
+                .iter()
+                .any(|hash| *hash == Sha1::digest(&blob).as_slice())
+            || self
+                .sha256
+                .iter()
+                .any(|hash| hash == key.fingerprint(HashAlg::Sha256).as_bytes())
+    }
+
+    /// Return true if the certificate or its key is revoked.
+    #[must_use]
+    pub fn is_certificate_revoked(&self, certificate: &Certificate) -> bool {
+        let ca_key = PublicKey::from(certificate.signature_key().clone())
+            .to_bytes()
+            .unwrap_or_default();
+
+        self.is_key_revoked(&PublicKey::from(certificate.public_key().clone()))
+            || self
+                .certificates
+                .iter()
+                .filter(|section| section.ca_key.is_empty() || section.ca_key == ca_key)
+                .any(|section| section.is_revoked(certificate))
+    }
+
+    /// Return true if a vault fingerprint (MD5 for RSA, SHA256 for the rest)
+    /// belongs to a revoked key. The MD5 fingerprints can only be matched
+    /// against the explicitly revoked keys.
+    #[must_use]
+    pub fn is_fingerprint_revoked(&self, vault_fingerprint: &str) -> bool {
+        self.keys
+            .iter()
+            .filter_map(|blob| PublicKey::from_bytes(blob).ok())
+            .filter_map(|key| fingerprint::vault_fingerprint(&key).ok())
+            .chain(self.sha256.iter().filter_map(|hash| {
+                <[u8; 32]>::try_from(hash.as_slice())
+                    .ok()
+                    .map(|hash| Fingerprint::Sha256(hash).to_string())
+            }))
+            .any(|fingerprint| fingerprint == vault_fingerprint)
+    }
+}
+
+impl CertificateSection {
+    fn parse(mut section: &[u8]) -> Result<Self> {
+        let mut certificates = Self {
+            ca_key: read_string(&mut section)?.to_vec(),
+            ..Self::default()
+        };
+
+        // reserved
+        read_string(&mut section)?;
+
+        while !section.is_empty() {
+            let section_type = read_u8(&mut section)?;
+            let mut data = read_string(&mut section)?;
+
+            match section_type {
+                KRL_SECTION_CERT_SERIAL_LIST => {
+                    while !data.is_empty() {
+                        let serial = read_u64(&mut data)?;
+                        certificates.serials.push((serial, serial));
+                    }
+                }
+                KRL_SECTION_CERT_SERIAL_RANGE => {
+                    let min = read_u64(&mut data)?;
+                    let max = read_u64(&mut data)?;
+                    certificates.serials.push((min, max));
+                }
+                KRL_SECTION_CERT_SERIAL_BITMAP => {
+                    let offset = read_u64(&mut data)?;
+                    let bitmap = read_string(&mut data)?.to_vec();
+                    certificates.bitmaps.push((offset, bitmap));
+                }
+                KRL_SECTION_CERT_KEY_ID => {
+                    while !data.is_empty() {
+                        let key_id = read_string(&mut data)?;
+                        certificates
+                            .key_ids
+                            .push(String::from_utf8_lossy(key_id).to_string());
+                    }
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Unsupported KRL certificate section {section_type}"
+                    ));
+                }
+            }
+        }
+
+        Ok(certificates)
+    }
+
+    fn is_revoked(&self, certificate: &Certificate) -> bool {
+        self.key_ids.iter().any(|id| id == certificate.key_id())
+            || self.is_serial_revoked(certificate.serial())
+    }
+
+    fn is_serial_revoked(&self, serial: u64) -> bool {
+        self.serials
+            .iter()
+            .any(|(min, max)| (*min..=*max).contains(&serial))
+            || self.bitmaps.iter().any(|(offset, bitmap)| {
+                // the bitmap is a big endian mpint, bit 0 is the offset serial
+                serial.checked_sub(*offset).is_some_and(|bit| {
+                    usize::try_from(bit / 8)
+                        .ok()
+                        .and_then(|index| bitmap.len().checked_sub(index + 1))
+                        .and_then(|index| bitmap.get(index))
+                        .is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+                })
+            })
+    }
+}
+
+/// Load the revoked keys configured in `revoked_keys`, a KRL or plain file
+/// (or a list of them), `None` if there are none.
+///
+/// # Errors
+///
+/// Returns an error if a configured file can't be read or parsed.
+pub fn load() -> Result<Option<Krl>> {
+    let mut krl: Option<Krl> = None;
+
+    for path in config::get_paths(&config::get()?, "revoked_keys")? {
+        let revoked = read(&path)?;
+
+        let krl = krl.get_or_insert_with(Krl::default);
+        krl.keys.extend(revoked.keys);
+        krl.sha1.extend(revoked.sha1);
+        krl.sha256.extend(revoked.sha256);
+        krl.certificates.extend(revoked.certificates);
+    }
+
+    Ok(krl)
+}
+
+/// Fail if the key is revoked in the configured `revoked_keys`.
+///
+/// # Errors
+///
+/// Returns an error if the key is revoked or the revoked keys can't be read.
+pub fn check(key: &PublicKey) -> Result<()> {
+    if let Some(krl) = load()?
+        && krl.is_key_revoked(key)
+    {
+        return Err(anyhow!(
+            "The key {} is revoked",
+            key.fingerprint(HashAlg::Sha256)
+        ));
+    }
+
+    Ok(())
+}
+
+fn read(path: &Path) -> Result<Krl> {
+    let data = fs::read(path)
+        .with_context(|| format!("Could not read the revoked keys {}", path.display()))?;
+
+    Krl::parse(&data).with_context(|| format!("Invalid revoked keys {}", path.display()))
+}
+
+fn read_u8(reader: &mut &[u8]) -> Result<u8> {
+    let (byte, rest) = reader
+        .split_first()
+        .ok_or_else(|| anyhow!("Truncated KRL"))?;
+    *reader = rest;
+    Ok(*byte)
+}
+
+fn read_bytes<'a>(reader: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    let (bytes, rest) = reader
+        .split_at_checked(len)
+        .ok_or_else(|| anyhow!("Truncated KRL"))?;
+    *reader = rest;
+    Ok(bytes)
+}
+
+fn read_u32(reader: &mut &[u8]) -> Result<u32> {
+    Ok(u32::from_be_bytes(read_bytes(reader, 4)?.try_into()?))
+}
+
+fn read_u64(reader: &mut &[u8]) -> Result<u64> {
+    Ok(u64::from_be_bytes(read_bytes(reader, 8)?.try_into()?))
+}
+
+fn read_string<'a>(reader: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = usize::try_from(read_u32(reader)?)?;
+    read_bytes(reader, len)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn public_key(path: &str) -> PublicKey {
+        PublicKey::read_openssh_file(Path::new(path)).unwrap()
+    }
+
+    fn certificate(path: &str) -> Certificate {
+        Certificate::from_openssh(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    // revoked.krl revokes the ed25519_password key, the ecdsa_p384 key by its
+    // SHA256 hash and the certificates of ca_ed25519 with the key id "bob" or
+    // the serials 5-10
+    #[test]
+    fn test_krl() {
+        let krl = read(Path::new("test_data/revoked.krl")).unwrap();
+
+        assert!(krl.is_key_revoked(&public_key("test_data/ed25519_password.pub")));
+        assert!(krl.is_key_revoked(&public_key("test_data/ecdsa_p384.pub")));
+        assert!(!krl.is_key_revoked(&public_key("test_data/ed25519.pub")));
+        assert!(!krl.is_key_revoked(&public_key("test_data/id_rsa.pub")));
+
+        assert!(krl.is_certificate_revoked(&certificate("test_data/id_rsa-cert.pub")));
+        assert!(!krl.is_certificate_revoked(&certificate("test_data/ed25519-cert.pub")));
+
+        let ecdsa =
+            fingerprint::vault_fingerprint(&public_key("test_data/ecdsa_p384.pub")).unwrap();
+        let ed25519 =
+            fingerprint::vault_fingerprint(&public_key("test_data/ed25519_password.pub")).unwrap();
+        assert!(krl.is_fingerprint_revoked(&ecdsa));
+        assert!(krl.is_fingerprint_revoked(&ed25519));
+        assert!(!krl.is_fingerprint_revoked("SHA256:invalid"));
+    }
+
+    #[test]
+    fn test_krl_serials() {
+        let section = CertificateSection {
+            serials: vec![(1, 1), (20, 30)],
+            bitmaps: vec![(100, vec![0x01, 0x02])],
+            ..CertificateSection::default()
+        };
+
+        let revoked = |serial: u64| section.is_serial_revoked(serial);
+
+        // bit 1 and bit 8 of the bitmap
+        assert!(revoked(101));
+        assert!(revoked(108));
+        assert!(!revoked(100));
+        assert!(!revoked(200));
+        assert!(revoked(25));
+        assert!(!revoked(2));
+    }
+
+    #[test]
+    fn test_plain_revoked_keys() {
+        let revoked = format!(
+            "# revoked\n{}\ninvalid\n",
+            fs::read_to_string("test_data/id_rsa.pub").unwrap()
+        );
+        let krl = Krl::parse(revoked.as_bytes()).unwrap();
+
+        assert!(krl.is_key_revoked(&public_key("test_data/id_rsa.pub")));
+        assert!(!krl.is_key_revoked(&public_key("test_data/ed25519.pub")));
+
+        let rsa = fingerprint::vault_fingerprint(&public_key("test_data/id_rsa.pub")).unwrap();
+        assert!(krl.is_fingerprint_revoked(&rsa));
+    }
+
+    #[test]
+    fn test_krl_truncated() {
+        let krl = fs::read("test_data/revoked.krl").unwrap();
+        assert!(Krl::parse(krl.get(..krl.len() - 4).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let home = tempfile::TempDir::new().unwrap();
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                (
+                    "SSH_VAULT_REVOKED_KEYS",
+                    Some("test_data/revoked.krl".as_ref()),
+                ),
+            ],
+            || {
+                assert!(check(&public_key("test_data/ed25519.pub")).is_ok());
+                let err = check(&public_key("test_data/ed25519_password.pub")).unwrap_err();
+                assert!(err.to_string().contains("is revoked"));
+            },
+        );
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_REVOKED_KEYS", None),
+            ],
+            || {
+                assert!(load().unwrap().is_none());
+                assert!(check(&public_key("test_data/ed25519_password.pub")).is_ok());
+            },
+        );
+    }
+}
@@ -1,4 +1,12 @@
+pub mod agent;
+pub mod allowed_signers;
+pub mod cert;
+pub mod config;
+pub mod ecdsa;
 pub mod ed25519;
+pub mod known_hosts;
+pub mod krl;
+pub mod pem;
 pub mod rsa;
 
 use anyhow::{Context, Result};
@@ -1,12 +1,12 @@
-use crate::vault::{
-    Vault, crypto::Crypto, crypto::aes256::Aes256Crypto, fingerprint::md5_fingerprint,
-};
+use crate::vault::{Algorithm, Recipient, SshKeyType, Vault, fingerprint::md5_fingerprint};
 use anyhow::{Context, Result};
-use base64ct::{Base64, Encoding};
 use rsa::{BigUint, Oaep, RsaPrivateKey, RsaPublicKey, rand_core::OsRng, sha2::Sha256};
 use secrecy::{ExposeSecret, SecretSlice};
-use ssh_key::{PrivateKey, PublicKey, private::KeypairData, public::KeyData};
-use zeroize::Zeroize;
+use ssh_key::{
+    PrivateKey, PublicKey,
+    private::{KeypairData, RsaKeypair},
+    public::KeyData,
+};
 
 #[derive(Debug)]
 pub struct RsaVault {
@@ -36,45 +36,7 @@ impl Vault for RsaVault {
                         return Err(anyhow::anyhow!("Private key is encrypted"));
                     }
 
-                    // Extract components from ssh-key's RSA representation
-                    // Use as_bytes() or a similar method to get the &[u8] from Mpint
-                    //
-                    // <https://docs.rs/ssh-key/latest/ssh_key/private/struct.RsaPrivateKey.html>
-                    //
-                    // pub struct RsaPrivateKey {
-                    //     pub d: Mpint,
-                    //     pub iqmp: Mpint,
-                    //     pub p: Mpint,
-                    //     pub q: Mpint,
-                    // }
-                    let modulus = BigUint::from_bytes_be(rsa_keypair.public.n.as_ref());
-                    let public_exponent = BigUint::from_bytes_be(rsa_keypair.public.e.as_ref());
-                    let private_exponent = BigUint::from_bytes_be(rsa_keypair.private.d.as_ref());
-                    let prime_p = BigUint::from_bytes_be(rsa_keypair.private.p.as_ref());
-                    let prime_q = BigUint::from_bytes_be(rsa_keypair.private.q.as_ref());
-
-                    // Create the RSA private key
-                    //
-                    // Constructs an RSA key pair from individual components:
-                    //
-                    // n: RSA modulus
-                    // e: public exponent (i.e. encrypting exponent)
-                    // d: private exponent (i.e. decrypting exponent)
-                    // primes: prime factors of n: typically two primes p and q. More than two
-                    // primes can be provided for multiprime RSA, however this is generally not
-                    // recommended. If no primes are provided, a prime factor recovery algorithm
-                    // will be employed to attempt to recover the factors (as described in NIST SP
-                    // 800-56B Revision 2 Appendix C.2). This algorithm only works if there are
-                    // just two prime factors p and q (as opposed to multiprime), and e is between
-                    // 2^16 and 2^256.
-                    let private_key = RsaPrivateKey::from_components(
-                        modulus,
-                        public_exponent,
-                        private_exponent,
-                        vec![prime_p, prime_q],
-                    )?;
-
-                    // let private_key = RsaPrivateKey::try_from(key_data)?;
+                    let private_key = private_key(rsa_keypair)?;
 
                     let public_key = private_key.to_public_key();
 
@@ -94,61 +56,95 @@ impl Vault for RsaVault {
         }
     }
 
-    fn create(&self, password: SecretSlice<u8>, data: &mut [u8]) -> Result<String> {
-        let crypto = Aes256Crypto::new(password.clone());
-
-        let fingerprint = md5_fingerprint(&self.public_key)?;
-
-        let encrypted_data = crypto.encrypt(data, fingerprint.as_bytes())?;
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Aes256
+    }
 
-        // zeroize data
-        data.zeroize();
+    fn fingerprint(&self) -> Result<String> {
+        md5_fingerprint(&self.public_key)
+    }
 
+    fn wrap(&self, password: &SecretSlice<u8>) -> Result<Recipient> {
         // Keep the RSA boundary on rsa::rand_core::OsRng. The rest of the crate
         // uses rand 0.10, but current rsa/ssh-key releases still depend on the
         // older rand_core line. Revisit this when upstream removes that split.
-        let encrypted_password =
+        let wrapped_key =
             self.public_key
                 .encrypt(&mut OsRng, Oaep::new::<Sha256>(), password.expose_secret())?;
 
-        // create vault payload
-        let payload = format!(
-            "{};{}",
-            Base64::encode_string(&encrypted_password),
-            Base64::encode_string(&encrypted_data)
-        )
-        .chars()
-        .collect::<Vec<_>>()
-        .chunks(64)
-        .map(|chunk| chunk.iter().collect::<String>())
-        .collect::<Vec<_>>()
-        .join("\n");
-
-        Ok(format!("SSH-VAULT;AES256;{fingerprint}\n{payload}"))
+        Ok(Recipient {
+            key_type: SshKeyType::Rsa,
+            fingerprint: self.fingerprint()?,
+            ephemeral_key: None,
+            wrapped_key,
+        })
     }
 
-    fn view(&self, password: &[u8], data: &[u8], fingerprint: &str) -> Result<String> {
-        let get_fingerprint = md5_fingerprint(&self.public_key)?;
-
-        if get_fingerprint != fingerprint {
+    fn unwrap(&self, recipient: &Recipient) -> Result<SecretSlice<u8>> {
+        if recipient.key_type != SshKeyType::Rsa || self.fingerprint()? != recipient.fingerprint {
             return Err(anyhow::anyhow!("Fingerprint mismatch, use correct key"));
         }
 
         match &self.private_key {
-            Some(private_key) => {
-                let password: SecretSlice<u8> =
-                    SecretSlice::new(private_key.decrypt(Oaep::new::<Sha256>(), password)?.into());
-
-                let crypto = Aes256Crypto::new(password);
-
-                let out = crypto.decrypt(data, fingerprint.as_bytes())?;
-                Ok(String::from_utf8(out)?)
-            }
+            Some(private_key) => Ok(SecretSlice::new(
+                private_key
+                    .decrypt(Oaep::new::<Sha256>(), &recipient.wrapped_key)?
+                    .into(),
+            )),
             None => Err(anyhow::anyhow!("Private key is required to view vault")),
         }
     }
 }
 
+/// Convert the RSA keypair of ssh-key to an `RsaPrivateKey`.
+///
+/// # Errors
+///
+/// Returns an error if the components are not a valid RSA key.
+pub fn private_key(rsa_keypair: &RsaKeypair) -> Result<RsaPrivateKey> {
+    // Extract components from ssh-key's RSA representation
+    // Use as_bytes() or a similar method to get the &[u8] from Mpint
+    //
+    // <https://docs.rs/ssh-key/latest/ssh_key/private/struct.RsaPrivateKey.html>
+    //
+    // pub struct RsaPrivateKey {
+    //     pub d: Mpint,
+    //     pub iqmp: Mpint,
+    //     pub p: Mpint,
+    //     pub q: Mpint,
+    // }
+    let modulus = BigUint::from_bytes_be(rsa_keypair.public.n.as_ref());
+    let public_exponent = BigUint::from_bytes_be(rsa_keypair.public.e.as_ref());
+    let private_exponent = BigUint::from_bytes_be(rsa_keypair.private.d.as_ref());
+    let prime_p = BigUint::from_bytes_be(rsa_keypair.private.p.as_ref());
+    let prime_q = BigUint::from_bytes_be(rsa_keypair.private.q.as_ref());
+
+    // Create the RSA private key
+    //
+    // Constructs an RSA key pair from individual components:
+    //
+    // n: RSA modulus
+    // e: public exponent (i.e. encrypting exponent)
+    // d: private exponent (i.e. decrypting exponent)
+    // primes: prime factors of n: typically two primes p and q. More than two
+    // primes can be provided for multiprime RSA, however this is generally not
+    // recommended. If no primes are provided, a prime factor recovery algorithm
+    // will be employed to attempt to recover the factors (as described in NIST SP
+    // 800-56B Revision 2 Appendix C.2). This algorithm only works if there are
+    // just two prime factors p and q (as opposed to multiprime), and e is between
+    // 2^16 and 2^256.
+    //
+    // RsaPrivateKey::try_from(rsa_keypair) can't be used, ssh-key 0.6 passes p
+    // twice as the primes.
+    RsaPrivateKey::from_components(
+        modulus,
+        public_exponent,
+        private_exponent,
+        vec![prime_p, prime_q],
+    )
+    .context("Could not load key")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,486 @@
+use crate::vault::{
+    Algorithm, Metadata, ParsedVault, SshVault, Version,
+    crypto::{
+        self,
+        stream::{
+            CHUNK_SIZE, ENCRYPTED_CHUNK_SIZE, NONCE_PREFIX_SIZE, StreamDecryptor, StreamEncryptor,
+        },
+    },
+    multi, parse, signature,
+};
+use anyhow::{Result, anyhow};
+use base64ct::{Base64, Encoding};
+use std::io::{self, BufRead, Read, Write};
+use zeroize::Zeroizing;
+
+/// The start of the first line of a stream vault
+pub const STREAM_HEADER: &str = "SSH-VAULT-V2;CHACHA20-POLY1305-STREAM;";
+
+// bytes encoded per line, 48 bytes are 64 Base64 chars
+const LINE_BYTES: usize = 48;
+
+/// Writes a `SSH-VAULT-V2` vault encrypting the data in chunks as it is written
+///
+/// The header is written in the first line when the writer is created, the
+/// payload follows in lines of 64 Base64 chars. [`VaultWriter::finish`] must be
+/// called to write the last chunk, an unfinished vault can't be decrypted.
+pub struct VaultWriter<W: Write> {
+    encoder: Base64Lines<W>,
+    encryptor: StreamEncryptor,
+    buffer: Zeroizing<Vec<u8>>,
+}
+
+impl<W: Write> VaultWriter<W> {
+    /// Write the vault header for the given vault keys.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no vault is provided, wrapping the password fails or
+    /// the header can't be written.
+    pub fn new(mut inner: W, vaults: &[SshVault], metadata: Metadata) -> Result<Self> {
+        // generate password (32 rand chars)
+        let password = crypto::gen_password()?;
+
+        let vault = ParsedVault {
+            version: Version::V2,
+            algorithm: Algorithm::ChaCha20Poly1305Stream,
+            metadata,
+            recipients: multi::recipients(vaults, &password)?,
+            ciphertext: Vec::new(),
+        };
+
+        let header = vault.header();
+
+        let (encryptor, prefix) = StreamEncryptor::new(&password, header.as_bytes())?;
+
+        writeln!(inner, "{header};")?;
+
+        let mut encoder = Base64Lines::new(inner);
+        encoder.write_all(&prefix)?;
+
+        Ok(Self {
+            encoder,
+            encryptor,
+            buffer: Zeroizing::new(Vec::with_capacity(CHUNK_SIZE)),
+        })
+    }
+
+    /// Encrypt the last chunk and return the inner writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if encryption or writing fails.
+    pub fn finish(self) -> Result<W> {
+        let Self {
+            mut encoder,
+            encryptor,
+            buffer,
+        } = self;
+
+        encoder.write_all(&encryptor.encrypt_last(&buffer)?)?;
+
+        Ok(encoder.finish()?)
+    }
+}
+
+impl<W: Write> Write for VaultWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // a full chunk is only encrypted when more data arrives, the last
+        // chunk is encrypted by finish
+        if self.buffer.len() == CHUNK_SIZE && !buf.is_empty() {
+            let chunk = self
+                .encryptor
+                .encrypt_next(&self.buffer)
+                .map_err(io::Error::other)?;
+            self.encoder.write_all(&chunk)?;
+            self.buffer.clear();
+        }
+
+        // never grow the buffer so the secret is not copied around
+        let len = buf.len().min(CHUNK_SIZE - self.buffer.len());
+        let (data, _) = buf.split_at(len);
+        self.buffer.extend_from_slice(data);
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.inner.flush()
+    }
+}
+
+/// Reads and decrypts a vault
+///
+/// Stream vaults are decrypted one chunk at a time in constant memory, other
+/// vault formats are read and decrypted in memory. Chunks are authenticated
+/// before they are returned, but a truncated stream is only detected at the
+/// end, so the output must be discarded if reading fails.
+pub struct VaultReader<R: BufRead> {
+    inner: R,
+    vault: ParsedVault,
+    signed: bool,
+    text: Vec<u8>,
+    ciphertext: Vec<u8>,
+    decryptor: Option<StreamDecryptor>,
+    plaintext: Zeroizing<Vec<u8>>,
+    position: usize,
+    unlocked: bool,
+    eof: bool,
+}
+
+impl<R: BufRead> VaultReader<R> {
+    /// Read the vault header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails or the header is not valid.
+    pub fn new(mut inner: R) -> Result<Self> {
+        let mut data = String::new();
+
+        inner.read_line(&mut data)?;
+
+        // only the header is parsed for streams
+        if !data.starts_with(STREAM_HEADER) {
+            inner.read_to_string(&mut data)?;
+        }
+
+        let vault = parse(&data)?;
+        let signed = signature::split(&data).1.is_some();
+
+        Ok(Self {
+            inner,
+            vault,
+            signed,
+            text: Vec::new(),
+            ciphertext: Vec::new(),
+            decryptor: None,
+            plaintext: Zeroizing::new(Vec::new()),
+            position: 0,
+            unlocked: false,
+            eof: false,
+        })
+    }
+
+    /// The vault header, the ciphertext is empty for streams.
+    #[must_use]
+    pub const fn vault(&self) -> &ParsedVault {
+        &self.vault
+    }
+
+    /// Whether the vault is followed by a signature, streams are not signed.
+    #[must_use]
+    pub const fn is_signed(&self) -> bool {
+        self.signed
+    }
+
+    /// Recover the password using the vault private key, must be called
+    /// before reading.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is not a recipient of the vault or
+    /// decryption fails.
+    pub fn unlock(&mut self, vault: &SshVault) -> Result<()> {
+        let password = vault.password(&self.vault)?;
+
+        if self.vault.algorithm == Algorithm::ChaCha20Poly1305Stream {
+            self.fill()?;
+
+            let len = self.ciphertext.len().min(NONCE_PREFIX_SIZE);
+            let prefix: Vec<u8> = self.ciphertext.drain(..len).collect();
+
+            self.decryptor = Some(StreamDecryptor::new(
+                &password,
+                &prefix,
+                self.vault.header().as_bytes(),
+            )?);
+        } else {
+            self.plaintext = Zeroizing::new(self.vault.decrypt(password)?);
+        }
+
+        self.unlocked = true;
+
+        Ok(())
+    }
+
+    // decode the Base64 input until there is more than one encrypted chunk
+    fn fill(&mut self) -> Result<()> {
+        while !self.eof && self.ciphertext.len() <= ENCRYPTED_CHUNK_SIZE {
+            let buf = self.inner.fill_buf()?;
+            let len = buf.len();
+
+            self.eof = buf.is_empty();
+            self.text
+                .extend(buf.iter().filter(|byte| !byte.is_ascii_whitespace()));
+            self.inner.consume(len);
+
+            // decode complete groups of 4 chars, the rest at the end
+            let len = if self.eof {
+                self.text.len()
+            } else {
+                self.text.len() / 4 * 4
+            };
+
+            let (encoded, _) = self.text.split_at(len);
+            self.ciphertext
+                .extend(Base64::decode_vec(std::str::from_utf8(encoded)?)?);
+            self.text.drain(..len);
+        }
+
+        Ok(())
+    }
+
+    // decrypt the next chunk into the plaintext buffer
+    fn next_chunk(&mut self) -> Result<()> {
+        self.fill()?;
+
+        let plaintext = if self.ciphertext.len() > ENCRYPTED_CHUNK_SIZE {
+            let (chunk, _) = self.ciphertext.split_at(ENCRYPTED_CHUNK_SIZE);
+
+            let plaintext = self
+                .decryptor
+                .as_mut()
+                .ok_or_else(|| anyhow!("Vault already decrypted"))?
+                .decrypt_next(chunk)?;
+
+            self.ciphertext.drain(..ENCRYPTED_CHUNK_SIZE);
+
+            plaintext
+        } else {
+            // fill only stops before the end with more than one chunk
+            let plaintext = self
+                .decryptor
+                .take()
+                .ok_or_else(|| anyhow!("Vault already decrypted"))?
+                .decrypt_last(&self.ciphertext)?;
+
+            self.ciphertext.clear();
+
+            plaintext
+        };
+
+        self.plaintext = Zeroizing::new(plaintext);
+        self.position = 0;
+
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for VaultReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.unlocked {
+            return Err(io::Error::other(
+                "The vault must be unlocked before reading",
+            ));
+        }
+
+        while self.position >= self.plaintext.len() {
+            if self.decryptor.is_none() {
+                return Ok(0);
+            }
+
+            self.next_chunk().map_err(io::Error::other)?;
+        }
+
+        let available = self.plaintext.get(self.position..).unwrap_or_default();
+        let len = buf.len().min(available.len());
+
+        let (out, _) = buf.split_at_mut(len);
+        let (data, _) = available.split_at(len);
+        out.copy_from_slice(data);
+
+        self.position += len;
+
+        Ok(len)
+    }
+}
+
+// Base64 encoder writing lines of 64 chars separated by a new line
+struct Base64Lines<W: Write> {
+    inner: W,
+    pending: Vec<u8>,
+    first: bool,
+}
+
+impl<W: Write> Base64Lines<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            pending: Vec::with_capacity(LINE_BYTES),
+            first: true,
+        }
+    }
+
+    fn write_all(&mut self, mut data: &[u8]) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let len = data.len().min(LINE_BYTES - self.pending.len());
+            let (head, rest) = data.split_at(len);
+            self.pending.extend_from_slice(head);
+            data = rest;
+
+            if self.pending.len() < LINE_BYTES {
+                return Ok(());
+            }
+
+            let pending = std::mem::take(&mut self.pending);
+            self.line(&pending)?;
+        }
+
+        for chunk in data.chunks(LINE_BYTES) {
+            if chunk.len() == LINE_BYTES {
+                self.line(chunk)?;
+            } else {
+                self.pending.extend_from_slice(chunk);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn line(&mut self, data: &[u8]) -> io::Result<()> {
+        if !self.first {
+            self.inner.write_all(b"\n")?;
+        }
+        self.first = false;
+
+        self.inner.write_all(Base64::encode_string(data).as_bytes())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.line(&pending)?;
+        }
+
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::indexing_slicing)]
+mod tests {
+    use super::*;
+    use crate::vault::{
+        metadata,
+        test_vaults::{private_vault, public_vault},
+    };
+    use secrecy::ExposeSecret;
+    use std::io::Cursor;
+
+    fn create(data: &[u8]) -> String {
+        let vaults = [
+            public_vault("test_data/id_rsa.pub"),
+            public_vault("test_data/ed25519.pub"),
+        ];
+
+        let mut metadata = Metadata::default();
+        metadata.insert(metadata::LABEL, "stream");
+
+        let mut writer = VaultWriter::new(Vec::new(), &vaults, metadata).unwrap();
+
+        // write in small pieces
+        for chunk in data.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    fn view(vault: &str, key: &str) -> Result<Vec<u8>> {
+        let mut reader = VaultReader::new(Cursor::new(vault))?;
+        reader.unlock(&private_vault(key))?;
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    #[test]
+    fn test_stream_vault() {
+        for len in [0, 1, 47, 48, CHUNK_SIZE, CHUNK_SIZE + 1, CHUNK_SIZE * 2 + 5] {
+            let data: Vec<u8> = (0..len).map(|i| u8::try_from(i % 253).unwrap()).collect();
+
+            let vault = create(&data);
+            assert!(vault.starts_with(STREAM_HEADER));
+            assert!(vault.lines().skip(1).all(|line| line.len() <= 64));
+
+            for key in ["test_data/id_rsa", "test_data/ed25519"] {
+                assert_eq!(view(&vault, key).unwrap(), data, "len {len}");
+            }
+
+            // the vault can also be read in memory
+            let parsed = parse(&vault).unwrap();
+            assert_eq!(parsed.metadata.label(), Some("stream"));
+            assert_eq!(parsed.to_string(), vault);
+            assert_eq!(
+                private_vault("test_data/ed25519")
+                    .view(&parsed)
+                    .unwrap()
+                    .expose_secret(),
+                data.as_slice()
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_vault_truncated() {
+        let data = vec![7u8; CHUNK_SIZE * 2 + 5];
+        let vault = create(&data);
+
+        // drop the last lines
+        let lines: Vec<&str> = vault.lines().collect();
+        let truncated = lines[..lines.len() - 2].join("\n");
+        assert!(view(&truncated, "test_data/ed25519").is_err());
+
+        // tamper the header
+        let tampered = vault.replacen("label=c3RyZWFt", "label=c3RyZWFd", 1);
+        assert!(view(&tampered, "test_data/ed25519").is_err());
+    }
+
+    #[test]
+    fn test_stream_vault_locked() {
+        let vault = create(b"secret");
+
+        let mut reader = VaultReader::new(Cursor::new(vault.as_str())).unwrap();
+        assert_eq!(reader.vault().recipients.len(), 2);
+        assert!(reader.vault().ciphertext.is_empty());
+
+        let mut data = Vec::new();
+        assert!(reader.read_to_end(&mut data).is_err());
+
+        // not a recipient
+        let writer = VaultWriter::new(
+            Vec::new(),
+            &[public_vault("test_data/ed25519.pub")],
+            Metadata::default(),
+        )
+        .unwrap();
+        let vault = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        let mut reader = VaultReader::new(Cursor::new(vault.as_str())).unwrap();
+        assert!(reader.unlock(&private_vault("test_data/id_rsa")).is_err());
+    }
+
+    #[test]
+    fn test_reader_legacy_vault() {
+        let vault = public_vault("test_data/ed25519.pub");
+        let mut secret = b"secret".to_vec();
+        let vault = vault
+            .create(crypto::gen_password().unwrap(), &mut secret)
+            .unwrap();
+
+        assert_eq!(view(&vault, "test_data/ed25519").unwrap(), b"secret");
+
+        let reader = VaultReader::new(Cursor::new(vault.as_str())).unwrap();
+        assert!(!reader.is_signed());
+
+        // the signature is detected in the data already read
+        let key = ssh_key::PrivateKey::read_openssh_file("test_data/ed25519".as_ref()).unwrap();
+        let signed = signature::sign(&vault, &key).unwrap();
+        let reader = VaultReader::new(Cursor::new(signed.as_str())).unwrap();
+        assert!(reader.is_signed());
+        assert_eq!(view(&signed, "test_data/ed25519").unwrap(), b"secret");
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{config, tools, vault::remote};
+use anyhow::{Context, Result, anyhow};
+use ssh_key::{Certificate, HashAlg, PublicKey};
+use std::{fs, path::PathBuf};
+
+/// Check the fetched keys of a user against the pinned fingerprints, the keys
+/// are pinned on first use. Pinning is disabled with `pin_keys: false`.
+///
+/// Users are pinned by provider and name, `alice` and `github:alice` share
+/// the same pin.
+///
+/// # Errors
+///
+/// Returns an error if the keys changed since they were pinned or the pinned
+/// keys can't be read or written.
+pub fn check(user: &str, keys: &str) -> Result<()> {
+    let config = config::get()?;
+
+    if !config.get_bool("pin_keys").unwrap_or(true) {
+        return Ok(());
+    }
+
+    let fetched = fingerprints(keys)?;
+
+    let Some(pinned) = pinned(user)? else {
+        eprintln!(
+            "Pinned {} key(s) of {user} in {}",
+            fetched.len(),
+            get_pins_path()?.display()
+        );
+        return pin(user, &fetched);
+    };
+
+    if pinned == fetched {
+        return Ok(());
+    }
+
+    let changes: String = fetched
+        .iter()
+        .filter(|f| !pinned.contains(f))
+        .map(|f| format!("\n  added   {f}"))
+        .chain(
+            pinned
+                .iter()
+                .filter(|f| !fetched.contains(f))
+                .map(|f| format!("\n  removed {f}")),
+        )
+        .collect();
+
+    Err(anyhow!(
+        "The keys of {user} changed since they were pinned:{changes}\nIf the change is expected run: ssh-vault trust {user}"
+    ))
+}
+
+/// Pin the fingerprints of a user replacing the previous ones.
+///
+/// # Errors
+///
+/// Returns an error if the pinned keys can't be read or written.
+pub fn pin(user: &str, fingerprints: &[String]) -> Result<()> {
+    let user = remote::user_id(user)?;
+    let mut pins = pins()?;
+
+    pins.retain(|(pinned, _)| *pinned != user);
+    pins.extend(
+        fingerprints
+            .iter()
+            .map(|fingerprint| (user.clone(), fingerprint.clone())),
+    );
+
+    save(&pins)
+}
+
+/// Remove the pinned fingerprints of a user, returns false if the user was
+/// not pinned.
+///
+/// # Errors
+///
+/// Returns an error if the pinned keys can't be read or written.
+pub fn unpin(user: &str) -> Result<bool> {
+    let user = remote::user_id(user)?;
+    let mut pins = pins()?;
+    let len = pins.len();
+
+    pins.retain(|(pinned, _)| *pinned != user);
+
+    if pins.len() == len {
+        return Ok(false);
+    }
+
+    save(&pins)?;
+
+    Ok(true)
+}
+
+/// The pinned fingerprints of a user, `None` if the user is not pinned.
+///
+/// # Errors
+///
+/// Returns an error if the pinned keys can't be read.
+pub fn pinned(user: &str) -> Result<Option<Vec<String>>> {
+    let user = remote::user_id(user)?;
+    let mut fingerprints: Vec<String> = pins()?
+        .into_iter()
+        .filter(|(pinned, _)| *pinned == user)
+        .map(|(_, fingerprint)| fingerprint)
+        .collect();
+
+    if fingerprints.is_empty() {
+        return Ok(None);
+    }
+
+    fingerprints.sort();
+
+    Ok(Some(fingerprints))
+}
+
+/// All the pinned `(user, fingerprint)` pairs from `~/.ssh/vault/pinned_keys`,
+/// users pinned before by their plain name are given as `provider:name`.
+///
+/// # Errors
+///
+/// Returns an error if the file exists but can't be read.
+pub fn pins() -> Result<Vec<(String, String)>> {
+    let path = get_pins_path()?;
+
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let pins = fs::read_to_string(&path)
+        .with_context(|| format!("Could not read the pinned keys {}", path.display()))?;
+
+    Ok(pins
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .map(|(user, fingerprint)| {
+            (
+                remote::user_id(user).unwrap_or_else(|_| user.to_string()),
+                fingerprint.trim().to_string(),
+            )
+        })
+        .collect())
+}
+
+/// The sorted SHA256 fingerprints of the fetched keys, for certificates the
+/// fingerprint of the embedded key.
+///
+/// # Errors
+///
+/// Returns an error if there are no supported keys.
+pub fn fingerprints(keys: &str) -> Result<Vec<String>> {
+    let keys = tools::filter_fetched_keys(keys)?;
+
+    let mut fingerprints: Vec<String> = keys
+        .lines()
+        .filter_map(|line| {
+            PublicKey::from_openssh(line).ok().or_else(|| {
+                Certificate::from_openssh(line)
+                    .ok()
+                    .map(|cert| PublicKey::from(cert.public_key().clone()))
+            })
+        })
+        .map(|key| key.fingerprint(HashAlg::Sha256).to_string())
+        .collect();
+
+    fingerprints.sort();
+    fingerprints.dedup();
+
+    Ok(fingerprints)
+}
+
+fn save(pins: &[(String, String)]) -> Result<()> {
+    let path = get_pins_path()?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let content: String = std::iter::once(String::from(
+        "# ssh-vault pinned keys: <user> <fingerprint>\n",
+    ))
+    .chain(
+        pins.iter()
+            .map(|(user, fingerprint)| format!("{user} {fingerprint}\n")),
+    )
+    .collect();
+
+    fs::write(&path, content)
+        .with_context(|| format!("Could not write the pinned keys {}", path.display()))
+}
+
+// ~/.ssh/vault/pinned_keys
+fn get_pins_path() -> Result<PathBuf> {
+    Ok(tools::get_home()?
+        .join(".ssh")
+        .join("vault")
+        .join("pinned_keys"))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn keys(paths: &[&str]) -> String {
+        paths
+            .iter()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_fingerprints() {
+        let fingerprints = fingerprints(&keys(&[
+            "test_data/ed25519.pub",
+            "test_data/id_rsa.pub",
+            "test_data/ed25519-cert.pub",
+        ]))
+        .unwrap();
+
+        // the certificate holds the ed25519 key
+        assert_eq!(fingerprints.len(), 2);
+        assert!(fingerprints.iter().all(|f| f.starts_with("SHA256:")));
+
+        assert!(super::fingerprints("<html>").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let home = TempDir::new().unwrap();
+        let alice = keys(&["test_data/ed25519.pub", "test_data/id_rsa.pub"]);
+        let changed = keys(&["test_data/ed25519.pub", "test_data/ecdsa_p256.pub"]);
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_PIN_KEYS", None),
+            ],
+            || {
+                // first use
+                check("alice", &alice).unwrap();
+                assert_eq!(pinned("alice").unwrap().unwrap().len(), 2);
+
+                check("alice", &alice).unwrap();
+
+                let err = check("alice", &changed).unwrap_err().to_string();
+                assert!(err.contains("added"));
+                assert!(err.contains("removed"));
+                assert!(err.contains("ssh-vault trust alice"));
+
+                // other users are not affected
+                check("bob", &changed).unwrap();
+
+                pin("alice", &fingerprints(&changed).unwrap()).unwrap();
+                check("alice", &changed).unwrap();
+                assert!(check("alice", &alice).is_err());
+                assert_eq!(pins().unwrap().len(), 4);
+
+                assert!(unpin("alice").unwrap());
+                assert!(!unpin("alice").unwrap());
+                assert!(pinned("alice").unwrap().is_none());
+                assert!(pinned("bob").unwrap().is_some());
+            },
+        );
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_PIN_KEYS", Some("false".as_ref())),
+            ],
+            || {
+                check("bob", &alice).unwrap();
+            },
+        );
+    }
+
+    #[test]
+    fn test_check_provider() {
+        let home = TempDir::new().unwrap();
+        let alice = keys(&["test_data/ed25519.pub"]);
+        let changed = keys(&["test_data/id_rsa.pub"]);
+
+        temp_env::with_vars(
+            [
+                ("HOME", Some(home.path().as_os_str())),
+                ("SSH_VAULT_PIN_KEYS", None),
+            ],
+            || {
+                // a pin from before the users were normalised
+                let path = get_pins_path().unwrap();
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                let fingerprint = fingerprints(&alice).unwrap().join("");
+                fs::write(&path, format!("alice {fingerprint}\n")).unwrap();
+
+                // alice and github:alice are the same user
+                assert!(check("github:alice", &changed).is_err());
+                check("github:alice", &alice).unwrap();
+                assert_eq!(pinned("alice").unwrap(), pinned("github:alice").unwrap());
+
+                check("srht:carol", &alice).unwrap();
+                assert!(check("srht:~carol", &changed).is_err());
+
+                // a user of another provider has its own pin
+                check("gitlab:alice", &changed).unwrap();
+
+                pin("alice", &fingerprints(&changed).unwrap()).unwrap();
+                assert_eq!(pinned("github:alice").unwrap().unwrap().len(), 1);
+                check("github:alice", &changed).unwrap();
+
+                assert!(unpin("github:alice").unwrap());
+                assert!(!unpin("alice").unwrap());
+
+                assert!(check("unknown:alice", &alice).is_err());
+            },
+        );
+    }
+}